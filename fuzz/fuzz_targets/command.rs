@@ -0,0 +1,10 @@
+#![no_main]
+
+use anker_767_protocol::command::AnkerCommand;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(command) = AnkerCommand::from_bytes(data) {
+        assert_eq!(command.to_bytes(), data);
+    }
+});
@@ -0,0 +1,21 @@
+#![no_main]
+
+use anker_767_protocol::framing::FrameDecoder;
+use anker_767_protocol::telemetry::NotificationPacket;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // First byte picks the notification size, the rest is the byte stream
+    let Some((&chunk_size, stream)) = data.split_first() else {
+        return;
+    };
+    let mut decoder = FrameDecoder::new();
+    for chunk in stream.chunks(chunk_size.max(1) as usize) {
+        decoder.push(chunk);
+        while let Some(result) = decoder.next_frame() {
+            if let Ok(frame) = result {
+                let _ = NotificationPacket::from_bytes(&frame);
+            }
+        }
+    }
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use anker_767_protocol::telemetry::NotificationPacket;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Anything that parses must encode to a frame that parses and encodes
+    // identically again (unmapped bytes and non-canonical encodings are lost
+    // on the first pass)
+    if let Ok(packet) = NotificationPacket::from_bytes(data) {
+        let encoded = packet.to_bytes();
+        let reparsed = NotificationPacket::from_bytes(&encoded).expect("encoded packet must parse");
+        assert_eq!(reparsed.to_bytes(), encoded);
+    }
+});
@@ -1,11 +1,17 @@
 //! Command types and serialization for Anker PowerHouse 767 (F2000).
 
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 use thiserror::Error;
 
 /// Header bytes for all commands
-const HEADER: [u8; 6] = [0x08, 0xee, 0x00, 0x00, 0x00, 0x02];
+pub const HEADER: [u8; 6] = [0x08, 0xee, 0x00, 0x00, 0x00, 0x02];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[repr(u8)]
 pub enum CommandType {
     AcTimer = 0x02,
@@ -60,6 +66,24 @@ pub enum CommandError {
     UnknownCommandType(u8),
     #[error("Invalid value: {0}")]
     InvalidValue(String),
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(String),
+}
+
+/// Additive checksum appended to every frame
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, &b| acc.wrapping_add(b))
+}
+
+/// Frame a command: header, type byte, length byte, parameters, checksum
+fn frame(command_type: u8, length: u8, parameters: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(16);
+    output.extend_from_slice(&HEADER);
+    output.push(command_type);
+    output.push(length);
+    output.extend_from_slice(parameters);
+    output.push(checksum(&output));
+    output
 }
 
 /// Trait for commands that can be serialized to bytes
@@ -69,21 +93,93 @@ pub trait Command {
     fn parameters(&self) -> Vec<u8>;
 
     fn to_bytes(&self) -> Vec<u8> {
-        let mut output = Vec::with_capacity(16);
-        output.extend_from_slice(&HEADER);
-        output.push(self.command_type() as u8);
-        output.push(self.length());
-        output.extend_from_slice(&self.parameters());
+        frame(self.command_type() as u8, self.length(), &self.parameters())
+    }
+}
+
+/// A command frame with an arbitrary type byte, used to decode captured
+/// traffic and to probe undocumented commands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawCommand {
+    pub command_type: u8,
+    pub parameters: Vec<u8>,
+}
+
+impl RawCommand {
+    /// Fails if the frame would be too long for the one-byte length field
+    pub fn new(command_type: u8, parameters: Vec<u8>) -> Result<Self, CommandError> {
+        let max = u8::MAX as usize - (HEADER.len() + 3);
+        if parameters.len() > max {
+            return Err(CommandError::InvalidValue(format!(
+                "at most {} parameter bytes, got {}",
+                max,
+                parameters.len()
+            )));
+        }
+        Ok(Self {
+            command_type,
+            parameters,
+        })
+    }
+
+    /// The length byte covers the whole frame, checksum included
+    pub fn length(&self) -> u8 {
+        (HEADER.len() + 3 + self.parameters.len()) as u8
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        frame(self.command_type, self.length(), &self.parameters)
+    }
+
+    /// Parse a framed command, verifying header, length and checksum
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CommandError> {
+        let min_length = HEADER.len() + 3;
+        if data.len() < min_length {
+            return Err(CommandError::InvalidFrame(format!(
+                "expected at least {} bytes, got {}",
+                min_length,
+                data.len()
+            )));
+        }
+        if data[..HEADER.len()] != HEADER {
+            return Err(CommandError::InvalidFrame(format!(
+                "unexpected header {:02x?}",
+                &data[..HEADER.len()]
+            )));
+        }
 
-        let checksum = output.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
-        output.push(checksum);
+        let length = data[HEADER.len() + 1] as usize;
+        if length != data.len() {
+            return Err(CommandError::InvalidFrame(format!(
+                "length byte says {}, frame is {} bytes",
+                length,
+                data.len()
+            )));
+        }
+
+        let (body, trailer) = data.split_at(data.len() - 1);
+        let expected = checksum(body);
+        if trailer[0] != expected {
+            return Err(CommandError::InvalidFrame(format!(
+                "checksum 0x{:02x}, expected 0x{:02x}",
+                trailer[0], expected
+            )));
+        }
 
-        output
+        Ok(Self {
+            command_type: data[HEADER.len()],
+            parameters: body[HEADER.len() + 2..].to_vec(),
+        })
+    }
+
+    /// The typed command this frame corresponds to, if the type byte is known
+    pub fn known_type(&self) -> Option<CommandType> {
+        CommandType::try_from(self.command_type).ok()
     }
 }
 
 /// Turns Power Save mode on and off
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PowerSaveCommand {
     pub is_on: bool,
 }
@@ -109,7 +205,7 @@ impl Command for PowerSaveCommand {
 }
 
 /// Turns the AC output on and off
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AcOutputCommand {
     pub is_on: bool,
 }
@@ -135,7 +231,7 @@ impl Command for AcOutputCommand {
 }
 
 /// Turns the 12V output on and off
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TwelveVoltOutputCommand {
     pub is_on: bool,
 }
@@ -161,7 +257,7 @@ impl Command for TwelveVoltOutputCommand {
 }
 
 /// Sets the display screen brightness (0-3)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ScreenBrightnessCommand {
     pub brightness: u8,
 }
@@ -193,7 +289,7 @@ impl Command for ScreenBrightnessCommand {
 }
 
 /// Sets the LED strip level (0-4, where 4 is SOS mode)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LedCommand {
     pub level: u8,
 }
@@ -225,7 +321,7 @@ impl Command for LedCommand {
 }
 
 /// Sets the recharge power in watts (200-1440)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RechargePowerCommand {
     pub watts: u16,
 }
@@ -258,7 +354,7 @@ impl Command for RechargePowerCommand {
 }
 
 /// Sets the screen timeout in seconds (0-65535)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ScreenTimeoutCommand {
     pub seconds: u16,
 }
@@ -285,7 +381,7 @@ impl Command for ScreenTimeoutCommand {
 }
 
 /// Sets a timer for AC output auto-off (0-65535 seconds, 0 disables)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AcTimerCommand {
     pub seconds: u16,
 }
@@ -312,7 +408,7 @@ impl Command for AcTimerCommand {
 }
 
 /// Sets a timer for 12V output auto-off (0-65535 seconds, 0 disables)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TwelveVoltTimerCommand {
     pub seconds: u16,
 }
@@ -338,8 +434,50 @@ impl Command for TwelveVoltTimerCommand {
     }
 }
 
+/// Check parameters are `expected` bytes long with the leading zero byte every command has
+fn check_parameters(params: &[u8], expected: usize) -> Result<(), CommandError> {
+    if params.len() != expected || params[0] != 0x00 {
+        return Err(CommandError::InvalidFrame(format!(
+            "expected {} parameter bytes starting with 00, got {:02x?}",
+            expected, params
+        )));
+    }
+    Ok(())
+}
+
+/// `00 <0|1>`
+fn flag(params: &[u8]) -> Result<bool, CommandError> {
+    check_parameters(params, 2)?;
+    match params[1] {
+        0 => Ok(false),
+        1 => Ok(true),
+        other => Err(CommandError::InvalidValue(format!(
+            "expected 0 or 1, got {}",
+            other
+        ))),
+    }
+}
+
+/// `00 <value>`
+fn byte(params: &[u8]) -> Result<u8, CommandError> {
+    check_parameters(params, 2)?;
+    Ok(params[1])
+}
+
+/// `00 <lo> <hi>`, padded with zeros to `length` bytes
+fn word(params: &[u8], length: usize) -> Result<u16, CommandError> {
+    check_parameters(params, length)?;
+    if params[3..].iter().any(|&b| b != 0) {
+        return Err(CommandError::InvalidFrame(format!(
+            "unexpected trailing parameter bytes {:02x?}",
+            &params[3..]
+        )));
+    }
+    Ok(u16::from_le_bytes([params[1], params[2]]))
+}
+
 /// Enum wrapping all command types for dynamic dispatch
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AnkerCommand {
     PowerSave(PowerSaveCommand),
     AcOutput(AcOutputCommand),
@@ -367,6 +505,36 @@ impl AnkerCommand {
         }
     }
 
+    /// Decode a framed command, the inverse of `to_bytes`
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CommandError> {
+        let raw = RawCommand::from_bytes(data)?;
+        let command_type = CommandType::try_from(raw.command_type)?;
+        let params = &raw.parameters;
+
+        let command = match command_type {
+            CommandType::PowerSave => AnkerCommand::PowerSave(PowerSaveCommand::new(flag(params)?)),
+            CommandType::AcOutput => AnkerCommand::AcOutput(AcOutputCommand::new(flag(params)?)),
+            CommandType::TwelveVoltOutput => {
+                AnkerCommand::TwelveVoltOutput(TwelveVoltOutputCommand::new(flag(params)?))
+            }
+            CommandType::ScreenBrightness => {
+                AnkerCommand::ScreenBrightness(ScreenBrightnessCommand::new(byte(params)?)?)
+            }
+            CommandType::Led => AnkerCommand::Led(LedCommand::new(byte(params)?)?),
+            CommandType::RechargePower => {
+                AnkerCommand::RechargePower(RechargePowerCommand::new(word(params, 3)?)?)
+            }
+            CommandType::ScreenTimeout => {
+                AnkerCommand::ScreenTimeout(ScreenTimeoutCommand::new(word(params, 3)?))
+            }
+            CommandType::AcTimer => AnkerCommand::AcTimer(AcTimerCommand::new(word(params, 5)?)),
+            CommandType::TwelveVoltTimer => {
+                AnkerCommand::TwelveVoltTimer(TwelveVoltTimerCommand::new(word(params, 5)?))
+            }
+        };
+        Ok(command)
+    }
+
     pub fn command_type(&self) -> CommandType {
         match self {
             AnkerCommand::PowerSave(_) => CommandType::PowerSave,
@@ -381,3 +549,64 @@ impl AnkerCommand {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_commands() -> Vec<AnkerCommand> {
+        vec![
+            AnkerCommand::PowerSave(PowerSaveCommand::new(true)),
+            AnkerCommand::AcOutput(AcOutputCommand::new(false)),
+            AnkerCommand::TwelveVoltOutput(TwelveVoltOutputCommand::new(true)),
+            AnkerCommand::ScreenBrightness(ScreenBrightnessCommand::new(2).unwrap()),
+            AnkerCommand::Led(LedCommand::new(4).unwrap()),
+            AnkerCommand::RechargePower(RechargePowerCommand::new(1440).unwrap()),
+            AnkerCommand::ScreenTimeout(ScreenTimeoutCommand::new(300)),
+            AnkerCommand::AcTimer(AcTimerCommand::new(65535)),
+            AnkerCommand::TwelveVoltTimer(TwelveVoltTimerCommand::new(0)),
+        ]
+    }
+
+    #[test]
+    fn command_round_trip() {
+        for command in all_commands() {
+            let bytes = command.to_bytes();
+            assert_eq!(AnkerCommand::from_bytes(&bytes).unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn length_byte_matches_frame() {
+        for command in all_commands() {
+            let bytes = command.to_bytes();
+            assert_eq!(bytes[HEADER.len() + 1] as usize, bytes.len());
+        }
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut bytes = AnkerCommand::Led(LedCommand::new(1).unwrap()).to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(matches!(
+            AnkerCommand::from_bytes(&bytes),
+            Err(CommandError::InvalidFrame(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_type_and_out_of_range_values() {
+        let unknown = RawCommand::new(0x99, vec![0x00, 0x01]).unwrap();
+        assert!(matches!(
+            AnkerCommand::from_bytes(&unknown.to_bytes()),
+            Err(CommandError::UnknownCommandType(0x99))
+        ));
+
+        let led = RawCommand::new(CommandType::Led as u8, vec![0x00, 0x05]).unwrap();
+        assert!(matches!(
+            AnkerCommand::from_bytes(&led.to_bytes()),
+            Err(CommandError::InvalidValue(_))
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+//! Reassembly of notification frames from BLE notifications.
+//!
+//! With a small negotiated MTU a 102-byte telemetry frame arrives split over
+//! several notifications; with a large one, several frames may share one.
+//! `FrameDecoder` buffers bytes, cuts frames using the header's
+//! `packet_length` and resyncs on the header magic after garbage.
+//!
+//! The trailing checksum is only used to resync: a frame that fails it is
+//! still passed on when the next bytes start with the header magic, and left
+//! for `Header::verify` to report. Until those bytes arrive it is held back,
+//! since a lost fragment can splice two frames into one of the declared
+//! length.
+
+use crate::command::checksum;
+use crate::telemetry::{TelemetryError, NOTIFICATION_MAGIC};
+use alloc::vec::Vec;
+
+/// Bytes up to and including the `packet_length` field
+pub(crate) const HEADER_LENGTH: usize = 9;
+/// Shortest frame: header and checksum (command acks carry no payload)
+const MIN_FRAME_LENGTH: usize = HEADER_LENGTH + 1;
+/// Longer declared lengths are taken as a false magic match
+const MAX_FRAME_LENGTH: usize = 512;
+
+/// Streaming decoder: `push` notifications, then drain `next_frame`
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Drop any partial frame, e.g. after a reconnect
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Bytes waiting for the rest of a frame
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Next complete frame, or an error describing bytes that were dropped to
+    /// resync. `None` means more data is needed.
+    pub fn next_frame(&mut self) -> Option<Result<Vec<u8>, TelemetryError>> {
+        let start = find_magic(&self.buffer);
+        if start > 0 {
+            self.buffer.drain(..start);
+            return Some(Err(TelemetryError::Resync { skipped: start }));
+        }
+        if self.buffer.len() < HEADER_LENGTH {
+            return None;
+        }
+
+        let length = u16::from_le_bytes([self.buffer[7], self.buffer[8]]) as usize;
+        if !(MIN_FRAME_LENGTH..=MAX_FRAME_LENGTH).contains(&length) {
+            self.buffer.drain(..1);
+            return Some(Err(TelemetryError::InvalidLength(length)));
+        }
+        if self.buffer.len() < length {
+            return None;
+        }
+
+        let expected = checksum(&self.buffer[..length - 1]);
+        let actual = self.buffer[length - 1];
+        if actual != expected {
+            let tail = &self.buffer[length..];
+            if tail.len() < NOTIFICATION_MAGIC.len() && NOTIFICATION_MAGIC.starts_with(tail) {
+                // Can't tell yet whether another frame follows
+                return None;
+            }
+            if !tail.starts_with(&NOTIFICATION_MAGIC) {
+                // Most likely a false magic match or a lost fragment; skip past
+                // this magic and look for the next one
+                self.buffer.drain(..1);
+                return Some(Err(TelemetryError::BadChecksum { expected, actual }));
+            }
+        }
+
+        Some(Ok(self.buffer.drain(..length).collect()))
+    }
+}
+
+/// Offset of the first possible frame start. A trailing lone first magic byte
+/// counts, since the rest of the magic may be in the next notification.
+fn find_magic(buffer: &[u8]) -> usize {
+    buffer
+        .windows(NOTIFICATION_MAGIC.len())
+        .position(|w| w == NOTIFICATION_MAGIC)
+        .unwrap_or_else(|| match buffer.last() {
+            Some(&b) if b == NOTIFICATION_MAGIC[0] => buffer.len() - 1,
+            _ => buffer.len(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Well-formed frame with `payload_length` payload bytes
+    fn frame(packet_type: u8, id: u8, payload_length: usize, seed: u8) -> Vec<u8> {
+        let length = HEADER_LENGTH + payload_length + 1;
+        let mut data = vec![0x09, 0xff, 0x00, 0x00, 0x01, packet_type, id];
+        data.extend_from_slice(&(length as u16).to_le_bytes());
+        data.extend((0..payload_length).map(|i| seed.wrapping_add(i as u8)));
+        data.push(checksum(&data));
+        data
+    }
+
+    fn telemetry(seed: u8) -> Vec<u8> {
+        frame(0x01, 0x49, 92, seed)
+    }
+
+    fn decode_all(decoder: &mut FrameDecoder) -> (Vec<Vec<u8>>, usize) {
+        let mut frames = Vec::new();
+        let mut errors = 0;
+        while let Some(result) = decoder.next_frame() {
+            match result {
+                Ok(frame) => frames.push(frame),
+                Err(_) => errors += 1,
+            }
+        }
+        (frames, errors)
+    }
+
+    #[test]
+    fn whole_frame() {
+        let data = telemetry(0);
+        assert_eq!(data.len(), 102);
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&data);
+        assert_eq!(decode_all(&mut decoder), (vec![data], 0));
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn every_single_split_point() {
+        let data = telemetry(7);
+        for split in 0..=data.len() {
+            let mut decoder = FrameDecoder::new();
+            decoder.push(&data[..split]);
+            let (early, errors) = decode_all(&mut decoder);
+            assert_eq!(errors, 0, "split at {}", split);
+            decoder.push(&data[split..]);
+            let (late, errors) = decode_all(&mut decoder);
+            assert_eq!(errors, 0, "split at {}", split);
+
+            let frames: Vec<_> = early.into_iter().chain(late).collect();
+            assert_eq!(frames, vec![data.clone()], "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn every_pair_of_split_points() {
+        let data = telemetry(200);
+        for first in 0..=data.len() {
+            for second in first..=data.len() {
+                let mut decoder = FrameDecoder::new();
+                let mut frames = Vec::new();
+                for part in [&data[..first], &data[first..second], &data[second..]] {
+                    decoder.push(part);
+                    let (decoded, errors) = decode_all(&mut decoder);
+                    assert_eq!(errors, 0, "splits at {} and {}", first, second);
+                    frames.extend(decoded);
+                }
+                assert_eq!(
+                    frames,
+                    vec![data.clone()],
+                    "splits at {} and {}",
+                    first,
+                    second
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn byte_at_a_time() {
+        let data = telemetry(3);
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        for byte in &data {
+            decoder.push(std::slice::from_ref(byte));
+            frames.extend(decode_all(&mut decoder).0);
+        }
+        assert_eq!(frames, vec![data]);
+    }
+
+    #[test]
+    fn several_frames_in_one_notification() {
+        let ack = frame(0x02, 0x86, 1, 0);
+        let state_ack = frame(0x01, 0x48, 4, 1);
+        let telemetry = telemetry(9);
+        let joined = [ack.clone(), telemetry.clone(), state_ack.clone()].concat();
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&joined);
+        assert_eq!(
+            decode_all(&mut decoder),
+            (vec![ack, telemetry, state_ack], 0)
+        );
+    }
+
+    #[test]
+    fn frames_split_across_notification_boundaries() {
+        let frames = vec![telemetry(1), frame(0x02, 0x86, 1, 0), telemetry(2)];
+        let stream = frames.concat();
+
+        for chunk_size in 1..=stream.len() {
+            let mut decoder = FrameDecoder::new();
+            let mut decoded = Vec::new();
+            for chunk in stream.chunks(chunk_size) {
+                decoder.push(chunk);
+                let (out, errors) = decode_all(&mut decoder);
+                assert_eq!(errors, 0, "chunk size {}", chunk_size);
+                decoded.extend(out);
+            }
+            assert_eq!(decoded, frames, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn resyncs_after_leading_garbage() {
+        let data = telemetry(5);
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&[0x00, 0x13, 0x37, 0x09]);
+        decoder.push(&data);
+
+        let (frames, errors) = decode_all(&mut decoder);
+        assert_eq!(frames, vec![data]);
+        assert_eq!(errors, 1);
+    }
+
+    #[test]
+    fn resyncs_after_lost_fragment() {
+        let first = telemetry(1);
+        let second = telemetry(2);
+        let mut decoder = FrameDecoder::new();
+        // The tail of the first frame never arrives
+        decoder.push(&first[..40]);
+        decoder.push(&second);
+
+        let (frames, errors) = decode_all(&mut decoder);
+        assert_eq!(frames, vec![second]);
+        assert!(errors > 0);
+    }
+
+    #[test]
+    fn bad_checksum_is_kept_when_delimited() {
+        let mut corrupt = telemetry(1);
+        corrupt[50] ^= 0xff;
+        let good = telemetry(2);
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&corrupt);
+        decoder.push(&good);
+
+        assert_eq!(decode_all(&mut decoder), (vec![corrupt, good], 0));
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn bad_checksum_is_held_until_the_next_frame_starts() {
+        let mut corrupt = telemetry(1);
+        corrupt[50] ^= 0xff;
+        let good = telemetry(2);
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&corrupt);
+        assert_eq!(decode_all(&mut decoder), (vec![], 0));
+        decoder.push(&good[..1]);
+        assert_eq!(decode_all(&mut decoder), (vec![], 0));
+        decoder.push(&good[1..]);
+        assert_eq!(decode_all(&mut decoder), (vec![corrupt, good], 0));
+    }
+
+    #[test]
+    fn spliced_frame_from_lost_fragment_is_dropped() {
+        let first = telemetry(1);
+        let second = telemetry(2);
+        for chunk_size in 1..=second.len() {
+            let mut decoder = FrameDecoder::new();
+            let mut frames = Vec::new();
+            // The tail of the first frame never arrives, so its head plus the
+            // start of the second adds up to the declared length
+            decoder.push(&first[..40]);
+            frames.extend(decode_all(&mut decoder).0);
+            for chunk in second.chunks(chunk_size) {
+                decoder.push(chunk);
+                frames.extend(decode_all(&mut decoder).0);
+            }
+            assert_eq!(frames, vec![second.clone()], "chunk size {}", chunk_size);
+            assert_eq!(decoder.buffered(), 0, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn bad_checksum_is_dropped_when_not_delimited() {
+        let mut corrupt = telemetry(1);
+        corrupt[50] ^= 0xff;
+        let good = telemetry(2);
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&corrupt);
+        decoder.push(&[0x00, 0x13]);
+        decoder.push(&good);
+
+        let (frames, errors) = decode_all(&mut decoder);
+        assert_eq!(frames, vec![good]);
+        assert!(errors > 0);
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn absurd_length_is_rejected() {
+        let mut data = telemetry(0);
+        data[7] = 0xff;
+        data[8] = 0xff;
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&data);
+        let first = decoder.next_frame();
+        assert!(matches!(
+            first,
+            Some(Err(TelemetryError::InvalidLength(0xffff)))
+        ));
+    }
+}
@@ -0,0 +1,16 @@
+//! Wire protocol of Anker PowerHouse power stations: command encoding,
+//! notification parsing, frame reassembly and per-model layouts.
+//!
+//! `no_std` with `alloc`, so the same parser can run on a microcontroller BLE
+//! gateway. Enable `serde` (default) for serialization and `openapi` for
+//! utoipa schemas; the latter needs `std`.
+
+#![cfg_attr(not(any(test, feature = "openapi")), no_std)]
+
+extern crate alloc;
+
+pub mod command;
+pub mod framing;
+pub mod model;
+pub mod telemetry;
+pub mod units;
@@ -0,0 +1,229 @@
+//! Per-model packet layouts.
+//!
+//! Anker PowerHouse units share the framing but differ in port counts, where
+//! fields sit in the telemetry frame and which commands they accept. Each
+//! supported model is described by a `ModelLayout`; the parser, server and
+//! metrics work from the layout instead of hardcoded offsets.
+//!
+//! Only the 767 (F2000) is supported: it is the only model with a layout, and
+//! detection only matches its advertised name. To add a model, capture its
+//! telemetry (`ANKER_CAPTURE_FILE`), map the offsets with
+//! `anker767-protocol explore`, add a layout here and list it in `MODELS`.
+
+use crate::command::CommandType;
+use crate::framing::HEADER_LENGTH;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// On/off flag and 16-bit little-endian watts of one port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortLayout {
+    pub on: usize,
+    pub watts: usize,
+}
+
+/// A group of identical ports, optionally sharing a 16-bit timer (seconds left)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortGroupLayout {
+    pub ports: &'static [PortLayout],
+    pub timer: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryLayout {
+    pub temperature: usize,
+    pub percentage: usize,
+}
+
+/// Where each field sits in a telemetry frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryLayout {
+    /// Total frame length, checksum included
+    pub length: usize,
+    /// Remaining runtime is whole days in one byte plus tenths of an hour in another
+    pub remaining_days: usize,
+    pub remaining_tenths: usize,
+    pub ac_outlet: PortLayout,
+    pub twelve_volt: PortGroupLayout,
+    pub usb_c: PortGroupLayout,
+    pub usb_a: PortGroupLayout,
+    pub total_output_watts: usize,
+    pub ac_input_watts: usize,
+    pub solar_input_watts: usize,
+    pub total_input_watts: usize,
+    /// Raw watt values are multiplied by this
+    pub watts_scale: u16,
+    pub internal_battery: BatteryLayout,
+    /// Expansion battery slot, for models that take one
+    pub external_battery: Option<BatteryLayout>,
+    pub battery_state: usize,
+    pub total_battery_percentage: usize,
+    pub serial: Range<usize>,
+}
+
+impl TelemetryLayout {
+    /// Byte ranges the parser decodes, sorted and merged: the header, every
+    /// field in this layout and the trailing checksum. Everything else is
+    /// still unmapped.
+    pub fn mapped_offsets(&self) -> Vec<Range<usize>> {
+        let byte = |offset: usize| offset..offset + 1;
+        let word = |offset: usize| offset..offset + 2;
+
+        let mut ranges = vec![
+            0..HEADER_LENGTH,
+            byte(self.remaining_days),
+            byte(self.remaining_tenths),
+            word(self.total_output_watts),
+            word(self.ac_input_watts),
+            word(self.solar_input_watts),
+            word(self.total_input_watts),
+            byte(self.battery_state),
+            byte(self.total_battery_percentage),
+            self.serial.clone(),
+            self.length - 1..self.length,
+        ];
+        let groups = [&self.twelve_volt, &self.usb_c, &self.usb_a];
+        let ports = groups.iter().flat_map(|group| group.ports);
+        for port in core::iter::once(&self.ac_outlet).chain(ports) {
+            ranges.push(byte(port.on));
+            ranges.push(word(port.watts));
+        }
+        ranges.extend(groups.iter().filter_map(|group| group.timer).map(word));
+        for battery in core::iter::once(&self.internal_battery).chain(&self.external_battery) {
+            ranges.push(byte(battery.temperature));
+            ranges.push(byte(battery.percentage));
+        }
+
+        ranges.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ModelLayout {
+    /// Short model number, as accepted by `ANKER_MODEL`
+    pub model: &'static str,
+    pub name: &'static str,
+    /// Substring of the BLE advertised name
+    pub advertised_name: &'static str,
+    pub telemetry: TelemetryLayout,
+    pub commands: &'static [CommandType],
+}
+
+impl ModelLayout {
+    pub fn supports(&self, command_type: CommandType) -> bool {
+        self.commands.contains(&command_type)
+    }
+}
+
+pub static POWERHOUSE_767: ModelLayout = ModelLayout {
+    model: "767",
+    name: "PowerHouse 767 (F2000)",
+    advertised_name: "767_PowerHouse",
+    telemetry: TelemetryLayout {
+        length: 102,
+        remaining_days: 18,
+        remaining_tenths: 17,
+        ac_outlet: PortLayout { on: 63, watts: 21 },
+        twelve_volt: PortGroupLayout {
+            ports: &[
+                PortLayout { on: 80, watts: 33 },
+                PortLayout { on: 81, watts: 35 },
+            ],
+            timer: Some(13),
+        },
+        usb_c: PortGroupLayout {
+            ports: &[
+                PortLayout { on: 75, watts: 23 },
+                PortLayout { on: 76, watts: 25 },
+                PortLayout { on: 77, watts: 27 },
+            ],
+            timer: None,
+        },
+        usb_a: PortGroupLayout {
+            ports: &[
+                PortLayout { on: 78, watts: 29 },
+                PortLayout { on: 79, watts: 31 },
+            ],
+            timer: None,
+        },
+        total_output_watts: 41,
+        ac_input_watts: 19,
+        solar_input_watts: 37,
+        total_input_watts: 39,
+        watts_scale: 1,
+        internal_battery: BatteryLayout {
+            temperature: 66,
+            percentage: 70,
+        },
+        external_battery: Some(BatteryLayout {
+            temperature: 67,
+            percentage: 71,
+        }),
+        battery_state: 68,
+        total_battery_percentage: 72,
+        serial: 85..101,
+    },
+    commands: &[
+        CommandType::AcTimer,
+        CommandType::TwelveVoltTimer,
+        CommandType::RechargePower,
+        CommandType::ScreenTimeout,
+        CommandType::AcOutput,
+        CommandType::TwelveVoltOutput,
+        CommandType::ScreenBrightness,
+        CommandType::PowerSave,
+        CommandType::Led,
+    ],
+};
+
+/// Every model with a known layout
+pub static MODELS: &[&ModelLayout] = &[&POWERHOUSE_767];
+
+/// Model used when nothing else identifies the device
+pub fn default_model() -> &'static ModelLayout {
+    &POWERHOUSE_767
+}
+
+/// Look up a model by its number, e.g. "767"
+pub fn by_model(model: &str) -> Option<&'static ModelLayout> {
+    MODELS.iter().copied().find(|m| m.model == model)
+}
+
+/// Identify a model from its BLE advertised name
+pub fn by_advertised_name(name: &str) -> Option<&'static ModelLayout> {
+    MODELS
+        .iter()
+        .copied()
+        .find(|m| name.contains(m.advertised_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_offsets_cover_the_767_fields() {
+        assert_eq!(
+            POWERHOUSE_767.telemetry.mapped_offsets(),
+            vec![
+                0..9,
+                13..15,
+                17..43,
+                63..64,
+                66..69,
+                70..73,
+                75..82,
+                85..102,
+            ]
+        );
+    }
+}
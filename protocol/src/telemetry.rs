@@ -0,0 +1,1055 @@
+//! Notification parsing for Anker PowerHouse units. Field offsets come from
+//! the per-model layouts in `model`; the 767 (F2000) is the default.
+
+use crate::command::{checksum, CommandType};
+use crate::model::{
+    self, BatteryLayout, ModelLayout, PortGroupLayout, PortLayout, TelemetryLayout,
+};
+use crate::units::{Celsius, Percentage, Seconds, Watts};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use thiserror::Error;
+
+const EXPECTED_PACKET_LENGTH: usize = 10;
+const STATE_ACK_LED_OFFSET: usize = 12;
+/// Shortest state acknowledgement we can parse: header plus the four state
+/// bytes (9..13), as before frames were encoded
+const STATE_ACK_MIN_LENGTH: usize = STATE_ACK_LED_OFFSET + 1;
+/// Length of the state acknowledgements we encode: the parsed bytes plus a
+/// trailing checksum, like every frame `seal` produces. No captured ack
+/// confirms the device sends that extra byte, so parsing never requires it.
+const STATE_ACK_LENGTH: usize = STATE_ACK_MIN_LENGTH + 1;
+/// First bytes of every notification (commands we send start with `08 ee`)
+pub const NOTIFICATION_MAGIC: [u8; 2] = [0x09, 0xff];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BatteryState {
+    Idle,
+    Discharging,
+    Charging,
+    /// A value this version doesn't know; only produced by lenient parsing
+    Unknown(u8),
+}
+
+impl BatteryState {
+    /// Decode without rejecting unknown values
+    pub fn from_byte(value: u8) -> Self {
+        match value {
+            0 => BatteryState::Idle,
+            1 => BatteryState::Discharging,
+            2 => BatteryState::Charging,
+            _ => BatteryState::Unknown(value),
+        }
+    }
+}
+
+impl TryFrom<u8> for BatteryState {
+    type Error = TelemetryError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match BatteryState::from_byte(value) {
+            BatteryState::Unknown(value) => Err(TelemetryError::UnknownBatteryState(value)),
+            state => Ok(state),
+        }
+    }
+}
+
+impl From<BatteryState> for u8 {
+    fn from(state: BatteryState) -> u8 {
+        match state {
+            BatteryState::Idle => 0,
+            BatteryState::Discharging => 1,
+            BatteryState::Charging => 2,
+            BatteryState::Unknown(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LedState {
+    Off,
+    Low,
+    Mid,
+    High,
+    Sos,
+    /// A value this version doesn't know; only produced by lenient parsing
+    Unknown(u8),
+}
+
+impl LedState {
+    /// Decode without rejecting unknown values
+    pub fn from_byte(value: u8) -> Self {
+        match value {
+            0 => LedState::Off,
+            1 => LedState::Low,
+            2 => LedState::Mid,
+            3 => LedState::High,
+            4 => LedState::Sos,
+            _ => LedState::Unknown(value),
+        }
+    }
+}
+
+impl TryFrom<u8> for LedState {
+    type Error = TelemetryError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match LedState::from_byte(value) {
+            LedState::Unknown(value) => Err(TelemetryError::UnknownLedState(value)),
+            state => Ok(state),
+        }
+    }
+}
+
+impl From<LedState> for u8 {
+    fn from(state: LedState) -> u8 {
+        match state {
+            LedState::Off => 0,
+            LedState::Low => 1,
+            LedState::Mid => 2,
+            LedState::High => 3,
+            LedState::Sos => 4,
+            LedState::Unknown(value) => value,
+        }
+    }
+}
+
+/// How to handle enum bytes with values this version doesn't know
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Reject the whole frame
+    #[default]
+    Strict,
+    /// Keep the raw value as `Unknown(u8)` and deliver the rest of the frame
+    Lenient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PacketType {
+    Telemetry = 1,
+    CommandAck = 2,
+}
+
+impl TryFrom<u8> for PacketType {
+    type Error = TelemetryError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(PacketType::Telemetry),
+            2 => Ok(PacketType::CommandAck),
+            _ => Err(TelemetryError::UnknownPacketType(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TelemetryType {
+    StateAck = 0x48,
+    Telemetry = 0x49,
+}
+
+impl TryFrom<u8> for TelemetryType {
+    type Error = TelemetryError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x48 => Ok(TelemetryType::StateAck),
+            0x49 => Ok(TelemetryType::Telemetry),
+            _ => Err(TelemetryError::UnknownTelemetryType(value)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TelemetryError {
+    #[error("Data too short: expected at least {expected}, got {actual}")]
+    DataTooShort { expected: usize, actual: usize },
+    #[error("Unknown packet type: {0}")]
+    UnknownPacketType(u8),
+    #[error("Unknown telemetry type: 0x{0:02x}")]
+    UnknownTelemetryType(u8),
+    #[error("Unknown battery state: {0}")]
+    UnknownBatteryState(u8),
+    #[error("Unknown LED state: {0}")]
+    UnknownLedState(u8),
+    #[error("Invalid UTF-8 in serial: {0}")]
+    InvalidSerial(#[from] alloc::string::FromUtf8Error),
+    #[error("Bad header magic: {0:02x?}")]
+    BadMagic([u8; 2]),
+    #[error("Length mismatch: header says {declared}, got {actual}")]
+    LengthMismatch { declared: usize, actual: usize },
+    #[error("Bad checksum: expected 0x{expected:02x}, got 0x{actual:02x}")]
+    BadChecksum { expected: u8, actual: u8 },
+    #[error("Implausible packet length {0}")]
+    InvalidLength(usize),
+    #[error("Skipped {skipped} bytes before the next frame header")]
+    Resync { skipped: usize },
+}
+
+impl TelemetryError {
+    /// Short reason if the frame itself is corrupt (as opposed to well-formed
+    /// but not understood)
+    pub fn corruption(&self) -> Option<&'static str> {
+        match self {
+            TelemetryError::BadMagic(_) => Some("magic"),
+            TelemetryError::LengthMismatch { .. } | TelemetryError::InvalidLength(_) => {
+                Some("length")
+            }
+            TelemetryError::BadChecksum { .. } => Some("checksum"),
+            TelemetryError::Resync { .. } => Some("resync"),
+            _ => None,
+        }
+    }
+
+    /// Variant name, used as a metrics label
+    pub fn variant(&self) -> &'static str {
+        match self {
+            TelemetryError::DataTooShort { .. } => "data_too_short",
+            TelemetryError::UnknownPacketType(_) => "unknown_packet_type",
+            TelemetryError::UnknownTelemetryType(_) => "unknown_telemetry_type",
+            TelemetryError::UnknownBatteryState(_) => "unknown_battery_state",
+            TelemetryError::UnknownLedState(_) => "unknown_led_state",
+            TelemetryError::InvalidSerial(_) => "invalid_serial",
+            TelemetryError::BadMagic(_) => "bad_magic",
+            TelemetryError::LengthMismatch { .. } => "length_mismatch",
+            TelemetryError::BadChecksum { .. } => "bad_checksum",
+            TelemetryError::InvalidLength(_) => "invalid_length",
+            TelemetryError::Resync { .. } => "resync",
+        }
+    }
+
+    /// Offset of the offending byte in `frame`, parsed as `model`
+    pub fn offset(&self, frame: &[u8], model: &ModelLayout) -> Option<usize> {
+        match self {
+            TelemetryError::DataTooShort { actual, .. } => Some(*actual),
+            TelemetryError::UnknownPacketType(_) => Some(5),
+            TelemetryError::UnknownTelemetryType(_) => Some(6),
+            TelemetryError::UnknownBatteryState(_) => Some(model.telemetry.battery_state),
+            TelemetryError::UnknownLedState(_) => Some(STATE_ACK_LED_OFFSET),
+            TelemetryError::InvalidSerial(e) => {
+                Some(model.telemetry.serial.start + e.utf8_error().valid_up_to())
+            }
+            TelemetryError::BadMagic(_) | TelemetryError::Resync { .. } => Some(0),
+            TelemetryError::LengthMismatch { .. } | TelemetryError::InvalidLength(_) => Some(7),
+            TelemetryError::BadChecksum { .. } => frame.len().checked_sub(1),
+        }
+    }
+}
+
+/// A notification that failed to parse, with the context needed for a bug report
+#[derive(Debug)]
+pub struct ParseError {
+    pub error: TelemetryError,
+    /// Packet type and id bytes, if the frame got that far
+    pub packet_type: Option<u8>,
+    pub telemetry_id: Option<u8>,
+    pub offset: Option<usize>,
+    /// The frame, or the whole notification for reassembly errors
+    pub frame: Vec<u8>,
+}
+
+impl ParseError {
+    pub fn new(error: TelemetryError, frame: &[u8], model: &ModelLayout) -> Self {
+        let has_header = frame.starts_with(&NOTIFICATION_MAGIC);
+        Self {
+            offset: error.offset(frame, model),
+            packet_type: frame.get(5).filter(|_| has_header).copied(),
+            telemetry_id: frame.get(6).filter(|_| has_header).copied(),
+            frame: frame.to_vec(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let (Some(packet_type), Some(id)) = (self.packet_type, self.telemetry_id) {
+            write!(f, " in packet {}/0x{:02x}", packet_type, id)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        write!(f, ": ")?;
+        for b in &self.frame {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Notification header. `from_bytes` only reads the packet type, id and
+/// declared length; the integrity checks live in `verify`.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub packet_type: PacketType,
+    pub telemetry_id: u8,
+    pub packet_length: u16,
+}
+
+impl Header {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TelemetryError> {
+        if data.len() < EXPECTED_PACKET_LENGTH {
+            return Err(TelemetryError::DataTooShort {
+                expected: EXPECTED_PACKET_LENGTH,
+                actual: data.len(),
+            });
+        }
+
+        let packet_id = data[5];
+        let telemetry_id = data[6];
+        let packet_length = u16::from_le_bytes([data[7], data[8]]);
+        let packet_type = PacketType::try_from(packet_id)?;
+
+        Ok(Header {
+            packet_type,
+            telemetry_id,
+            packet_length,
+        })
+    }
+
+    /// Check the magic, the declared length and the trailing checksum.
+    ///
+    /// These match the frames we encode, but no capture from a real device
+    /// confirms them yet, so callers should report a failure rather than drop
+    /// the frame.
+    pub fn verify(data: &[u8]) -> Result<(), TelemetryError> {
+        if data.len() < EXPECTED_PACKET_LENGTH {
+            return Err(TelemetryError::DataTooShort {
+                expected: EXPECTED_PACKET_LENGTH,
+                actual: data.len(),
+            });
+        }
+
+        if data[..2] != NOTIFICATION_MAGIC {
+            return Err(TelemetryError::BadMagic([data[0], data[1]]));
+        }
+
+        let packet_length = u16::from_le_bytes([data[7], data[8]]) as usize;
+        if packet_length != data.len() {
+            return Err(TelemetryError::LengthMismatch {
+                declared: packet_length,
+                actual: data.len(),
+            });
+        }
+
+        // Same additive checksum as outgoing commands, over everything but the last byte
+        let (body, trailer) = data.split_at(data.len() - 1);
+        let expected = checksum(body);
+        if trailer[0] != expected {
+            return Err(TelemetryError::BadChecksum {
+                expected,
+                actual: trailer[0],
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Extract a 16-bit little-endian integer from data
+fn extract16(data: &[u8], index: usize) -> Result<u16, TelemetryError> {
+    match data.get(index..index + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(TelemetryError::DataTooShort {
+            expected: index + 2,
+            actual: data.len(),
+        }),
+    }
+}
+
+/// Write a 16-bit little-endian integer into data
+fn insert16(data: &mut [u8], index: usize, value: u16) {
+    data[index..index + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Zeroed frame of `length` bytes with the header filled in; the payload is
+/// written by the caller before `seal`
+fn new_frame(packet_type: PacketType, id: u8, length: usize) -> Vec<u8> {
+    let mut data = vec![0u8; length];
+    data[..NOTIFICATION_MAGIC.len()].copy_from_slice(&NOTIFICATION_MAGIC);
+    data[5] = packet_type as u8;
+    data[6] = id;
+    insert16(&mut data, 7, length as u16);
+    data
+}
+
+/// Set the trailing checksum byte
+fn seal(mut data: Vec<u8>) -> Vec<u8> {
+    let last = data.len() - 1;
+    data[last] = checksum(&data[..last]);
+    data
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Output {
+    pub is_on: bool,
+    pub watts: Watts,
+    /// Time left on the port group's timer
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub time_remaining: Option<Seconds>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Battery {
+    pub temperature: Celsius,
+    pub percentage: Percentage,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Telemetry {
+    /// Runtime left at the current draw, as estimated by the device
+    pub battery_remaining: Seconds,
+    pub ac_outlet: Output,
+    pub twelve_volt: Vec<Output>,
+    pub usb_c: Vec<Output>,
+    pub usb_a: Vec<Output>,
+    pub total_output_watts: Watts,
+    pub ac_input_watts: Watts,
+    pub solar_input_watts: Watts,
+    pub total_input_watts: Watts,
+    pub internal_battery: Battery,
+    /// Expansion battery, absent when no pack is attached
+    pub external_battery: Option<Battery>,
+    pub battery_state: BatteryState,
+    pub total_battery_percentage: Percentage,
+    pub device_serial: String,
+}
+
+impl Telemetry {
+    /// Parse a PowerHouse 767 telemetry frame
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TelemetryError> {
+        Self::from_bytes_with(data, &model::default_model().telemetry, ParseMode::Strict)
+    }
+
+    /// Parse a telemetry frame laid out as `layout`
+    pub fn from_bytes_with(
+        data: &[u8],
+        layout: &TelemetryLayout,
+        mode: ParseMode,
+    ) -> Result<Self, TelemetryError> {
+        if data.len() < layout.length {
+            return Err(TelemetryError::DataTooShort {
+                expected: layout.length,
+                actual: data.len(),
+            });
+        }
+
+        let battery_remaining = Seconds::from_days_and_tenths(
+            data[layout.remaining_days],
+            data[layout.remaining_tenths],
+        );
+        let watts = |index: usize| -> Result<Watts, TelemetryError> {
+            Ok(Watts(
+                extract16(data, index)?.saturating_mul(layout.watts_scale),
+            ))
+        };
+        let port = |port: &PortLayout, time: Option<Seconds>| -> Result<Output, TelemetryError> {
+            Ok(Output {
+                is_on: data[port.on] != 0,
+                watts: watts(port.watts)?,
+                time_remaining: time,
+            })
+        };
+        let group = |group: &PortGroupLayout| -> Result<Vec<Output>, TelemetryError> {
+            let time = group
+                .timer
+                .map(|i| extract16(data, i).map(Seconds::from))
+                .transpose()?;
+            group.ports.iter().map(|p| port(p, time)).collect()
+        };
+
+        let battery = |battery: &BatteryLayout| Battery {
+            temperature: Celsius(data[battery.temperature]),
+            percentage: Percentage(data[battery.percentage]),
+        };
+        // Without an expansion pack both its temperature and percentage read zero.
+        // No presence flag is known, so a real pack at 0% and 0 °C reads as absent.
+        let external_battery = layout
+            .external_battery
+            .as_ref()
+            .filter(|b| data[b.temperature] != 0 || data[b.percentage] != 0)
+            .map(battery);
+
+        let device_serial = String::from_utf8(data[layout.serial.clone()].to_vec())?;
+
+        Ok(Telemetry {
+            battery_remaining,
+            ac_outlet: port(&layout.ac_outlet, None)?,
+            twelve_volt: group(&layout.twelve_volt)?,
+            usb_c: group(&layout.usb_c)?,
+            usb_a: group(&layout.usb_a)?,
+            total_output_watts: watts(layout.total_output_watts)?,
+            ac_input_watts: watts(layout.ac_input_watts)?,
+            solar_input_watts: watts(layout.solar_input_watts)?,
+            total_input_watts: watts(layout.total_input_watts)?,
+            internal_battery: battery(&layout.internal_battery),
+            external_battery,
+            battery_state: match mode {
+                ParseMode::Strict => BatteryState::try_from(data[layout.battery_state])?,
+                ParseMode::Lenient => BatteryState::from_byte(data[layout.battery_state]),
+            },
+            total_battery_percentage: Percentage(data[layout.total_battery_percentage]),
+            device_serial,
+        })
+    }
+
+    /// Encode as a PowerHouse 767 telemetry frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with(&model::default_model().telemetry)
+    }
+
+    /// Encode as a telemetry frame laid out as `layout`. Unmapped bytes are
+    /// zero, so `from_bytes(to_bytes(t)) == t` but the frame isn't
+    /// byte-identical to what the device sent. Missing ports encode as off and
+    /// idle; ports the layout lacks are dropped.
+    pub fn to_bytes_with(&self, layout: &TelemetryLayout) -> Vec<u8> {
+        let mut data = new_frame(
+            PacketType::Telemetry,
+            TelemetryType::Telemetry as u8,
+            layout.length,
+        );
+
+        let (days, tenths) = self.battery_remaining.to_days_and_tenths();
+        data[layout.remaining_days] = days;
+        data[layout.remaining_tenths] = tenths;
+
+        let raw = |watts: Watts| watts.0 / layout.watts_scale;
+        let put_port = |data: &mut [u8], port: &PortLayout, output: Option<&Output>| {
+            let (is_on, watts) = output.map_or((false, Watts(0)), |o| (o.is_on, o.watts));
+            data[port.on] = is_on as u8;
+            insert16(data, port.watts, raw(watts));
+        };
+
+        put_port(&mut data, &layout.ac_outlet, Some(&self.ac_outlet));
+        for (group, outputs) in [
+            (&layout.twelve_volt, &self.twelve_volt),
+            (&layout.usb_c, &self.usb_c),
+            (&layout.usb_a, &self.usb_a),
+        ] {
+            if let Some(timer) = group.timer {
+                let time = outputs
+                    .first()
+                    .and_then(|o| o.time_remaining)
+                    .map_or(0, |t| t.0.min(u16::MAX as u32) as u16);
+                insert16(&mut data, timer, time);
+            }
+            for (i, port) in group.ports.iter().enumerate() {
+                put_port(&mut data, port, outputs.get(i));
+            }
+        }
+
+        insert16(
+            &mut data,
+            layout.total_output_watts,
+            raw(self.total_output_watts),
+        );
+        insert16(&mut data, layout.ac_input_watts, raw(self.ac_input_watts));
+        insert16(
+            &mut data,
+            layout.solar_input_watts,
+            raw(self.solar_input_watts),
+        );
+        insert16(
+            &mut data,
+            layout.total_input_watts,
+            raw(self.total_input_watts),
+        );
+
+        data[layout.internal_battery.temperature] = self.internal_battery.temperature.0;
+        data[layout.internal_battery.percentage] = self.internal_battery.percentage.0;
+        if let (Some(slot), Some(external)) = (&layout.external_battery, &self.external_battery) {
+            data[slot.temperature] = external.temperature.0;
+            data[slot.percentage] = external.percentage.0;
+        }
+        data[layout.battery_state] = self.battery_state.into();
+        data[layout.total_battery_percentage] = self.total_battery_percentage.0;
+
+        let serial = self.device_serial.as_bytes();
+        let serial_length = serial.len().min(layout.serial.len());
+        let start = layout.serial.start;
+        data[start..start + serial_length].copy_from_slice(&serial[..serial_length]);
+
+        seal(data)
+    }
+
+    /// Number of battery packs reporting (internal plus optional expansion)
+    pub fn battery_packs(&self) -> u8 {
+        1 + self.external_battery.is_some() as u8
+    }
+
+    /// Settings the device reports in telemetry: the AC and 12V outputs and the
+    /// 12V timer. Power save and the LED level come from `StateAck::settings`;
+    /// the rest aren't reported at all (see `DeviceSettings`).
+    pub fn settings(&self) -> DeviceSettings {
+        DeviceSettings {
+            ac_output: Some(self.ac_outlet.is_on),
+            twelve_volt_output: Some(self.twelve_volt.iter().any(|o| o.is_on)),
+            twelve_volt_timer: self
+                .twelve_volt
+                .first()
+                .and_then(|o| u16::try_from(o.time_remaining?.0).ok()),
+            ..DeviceSettings::default()
+        }
+    }
+}
+
+/// Version of the telemetry JSON served by `/api/telemetry`. Adding a field
+/// keeps the version; renaming, removing or retyping one bumps it.
+pub const TELEMETRY_SCHEMA_VERSION: u32 = 1;
+
+/// Telemetry tagged with `TELEMETRY_SCHEMA_VERSION`, as the API serves it.
+/// Deserializing fails on any other version; unknown fields are ignored.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VersionedTelemetry {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "schema_version"))]
+    pub schema_version: u32,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub telemetry: Telemetry,
+}
+
+impl From<Telemetry> for VersionedTelemetry {
+    fn from(telemetry: Telemetry) -> Self {
+        Self {
+            schema_version: TELEMETRY_SCHEMA_VERSION,
+            telemetry,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn schema_version<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = <u32 as serde::Deserialize>::deserialize(deserializer)?;
+    if version != TELEMETRY_SCHEMA_VERSION {
+        return Err(serde::de::Error::custom(format_args!(
+            "unsupported telemetry schema version {}, expected {}",
+            version, TELEMETRY_SCHEMA_VERSION
+        )));
+    }
+    Ok(version)
+}
+
+/// Device settings as reported by the device itself.
+///
+/// Screen brightness, screen timeout, recharge power and the AC timer haven't
+/// been located in any notification yet (see the protocol explorer), so they
+/// are always `None` for now.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceSettings {
+    pub ac_output: Option<bool>,
+    pub twelve_volt_output: Option<bool>,
+    pub power_save: Option<bool>,
+    pub led_level: Option<u8>,
+    pub screen_brightness: Option<u8>,
+    pub screen_timeout: Option<u16>,
+    pub recharge_power: Option<u16>,
+    /// Seconds left on the AC timer
+    pub ac_timer: Option<u16>,
+    /// Seconds left on the 12V timer
+    pub twelve_volt_timer: Option<u16>,
+}
+
+impl DeviceSettings {
+    /// Fill settings missing from `self` with those from `other`
+    pub fn or(self, other: DeviceSettings) -> DeviceSettings {
+        DeviceSettings {
+            ac_output: self.ac_output.or(other.ac_output),
+            twelve_volt_output: self.twelve_volt_output.or(other.twelve_volt_output),
+            power_save: self.power_save.or(other.power_save),
+            led_level: self.led_level.or(other.led_level),
+            screen_brightness: self.screen_brightness.or(other.screen_brightness),
+            screen_timeout: self.screen_timeout.or(other.screen_timeout),
+            recharge_power: self.recharge_power.or(other.recharge_power),
+            ac_timer: self.ac_timer.or(other.ac_timer),
+            twelve_volt_timer: self.twelve_volt_timer.or(other.twelve_volt_timer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StateAck {
+    pub ac_outlet_on: bool,
+    pub twelve_volt_on: bool,
+    pub power_save_on: bool,
+    pub led_state: LedState,
+}
+
+impl StateAck {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TelemetryError> {
+        Self::from_bytes_with(data, ParseMode::Strict)
+    }
+
+    pub fn from_bytes_with(data: &[u8], mode: ParseMode) -> Result<Self, TelemetryError> {
+        if data.len() < STATE_ACK_MIN_LENGTH {
+            return Err(TelemetryError::DataTooShort {
+                expected: STATE_ACK_MIN_LENGTH,
+                actual: data.len(),
+            });
+        }
+
+        Ok(StateAck {
+            ac_outlet_on: data[9] != 0,
+            twelve_volt_on: data[10] != 0,
+            power_save_on: data[11] != 0,
+            led_state: match mode {
+                ParseMode::Strict => LedState::try_from(data[STATE_ACK_LED_OFFSET])?,
+                ParseMode::Lenient => LedState::from_byte(data[STATE_ACK_LED_OFFSET]),
+            },
+        })
+    }
+
+    /// Encode as a state acknowledgement frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = new_frame(
+            PacketType::Telemetry,
+            TelemetryType::StateAck as u8,
+            STATE_ACK_LENGTH,
+        );
+        data[9] = self.ac_outlet_on as u8;
+        data[10] = self.twelve_volt_on as u8;
+        data[11] = self.power_save_on as u8;
+        data[STATE_ACK_LED_OFFSET] = self.led_state.into();
+        seal(data)
+    }
+
+    /// Settings the device reports in a state acknowledgement
+    pub fn settings(&self) -> DeviceSettings {
+        DeviceSettings {
+            ac_output: Some(self.ac_outlet_on),
+            twelve_volt_output: Some(self.twelve_volt_on),
+            power_save: Some(self.power_save_on),
+            led_level: Some(self.led_state.into()),
+            ..DeviceSettings::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandAck {
+    pub command_type: CommandType,
+}
+
+impl CommandAck {
+    /// Encode as a command acknowledgement frame (header and checksum only)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        seal(new_frame(
+            PacketType::CommandAck,
+            self.command_type as u8,
+            EXPECTED_PACKET_LENGTH,
+        ))
+    }
+}
+
+/// Parsed notification packet from the device
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum NotificationPacket {
+    Telemetry(Telemetry),
+    StateAck(StateAck),
+    CommandAck(CommandAck),
+}
+
+impl NotificationPacket {
+    /// Encode for a PowerHouse 767
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with(model::default_model())
+    }
+
+    /// Encode as a device of the given model would send it
+    pub fn to_bytes_with(&self, model: &ModelLayout) -> Vec<u8> {
+        match self {
+            NotificationPacket::Telemetry(telemetry) => telemetry.to_bytes_with(&model.telemetry),
+            NotificationPacket::StateAck(state_ack) => state_ack.to_bytes(),
+            NotificationPacket::CommandAck(command_ack) => command_ack.to_bytes(),
+        }
+    }
+
+    /// Parse a notification from a PowerHouse 767, rejecting unknown values
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TelemetryError> {
+        Self::from_bytes_with(data, model::default_model(), ParseMode::Strict)
+    }
+
+    /// Parse a notification from a device of the given model
+    pub fn from_bytes_with(
+        data: &[u8],
+        model: &ModelLayout,
+        mode: ParseMode,
+    ) -> Result<Self, TelemetryError> {
+        let header = Header::from_bytes(data)?;
+
+        match header.packet_type {
+            PacketType::Telemetry => {
+                let telemetry_type = TelemetryType::try_from(header.telemetry_id)?;
+                match telemetry_type {
+                    TelemetryType::Telemetry => Ok(NotificationPacket::Telemetry(
+                        Telemetry::from_bytes_with(data, &model.telemetry, mode)?,
+                    )),
+                    TelemetryType::StateAck => Ok(NotificationPacket::StateAck(
+                        StateAck::from_bytes_with(data, mode)?,
+                    )),
+                }
+            }
+            PacketType::CommandAck => {
+                let command_type = CommandType::try_from(header.telemetry_id)
+                    .map_err(|_| TelemetryError::UnknownTelemetryType(header.telemetry_id))?;
+                Ok(NotificationPacket::CommandAck(CommandAck { command_type }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(is_on: bool, watts: u16, time: Option<u16>) -> Output {
+        Output {
+            is_on,
+            watts: Watts(watts),
+            time_remaining: time.map(Seconds::from),
+        }
+    }
+
+    fn sample_telemetry(external: bool) -> Telemetry {
+        Telemetry {
+            battery_remaining: Seconds::from_days_and_tenths(2, 15),
+            ac_outlet: output(true, 312, None),
+            twelve_volt: vec![output(true, 24, Some(3600)), output(false, 0, Some(3600))],
+            usb_c: vec![
+                output(true, 60, None),
+                output(false, 0, None),
+                output(true, 15, None),
+            ],
+            usb_a: vec![output(false, 0, None), output(true, 5, None)],
+            total_output_watts: Watts(416),
+            ac_input_watts: Watts(0),
+            solar_input_watts: Watts(180),
+            total_input_watts: Watts(180),
+            internal_battery: Battery {
+                temperature: Celsius(27),
+                percentage: Percentage(81),
+            },
+            external_battery: external.then_some(Battery {
+                temperature: Celsius(25),
+                percentage: Percentage(77),
+            }),
+            battery_state: BatteryState::Discharging,
+            total_battery_percentage: Percentage(79),
+            device_serial: "AZV1F2000A123456".to_string(),
+        }
+    }
+
+    #[test]
+    fn telemetry_round_trip() {
+        for external in [false, true] {
+            let telemetry = sample_telemetry(external);
+            let bytes = telemetry.to_bytes();
+            assert_eq!(bytes.len(), model::POWERHOUSE_767.telemetry.length);
+
+            match NotificationPacket::from_bytes(&bytes).unwrap() {
+                NotificationPacket::Telemetry(parsed) => assert_eq!(parsed, telemetry),
+                other => panic!("expected telemetry, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn telemetry_bytes_round_trip() {
+        let bytes = sample_telemetry(true).to_bytes();
+        let parsed = Telemetry::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn other_layout_round_trip() {
+        // One USB-A port fewer, no expansion slot, watts reported in tens
+        let layout = TelemetryLayout {
+            usb_a: PortGroupLayout {
+                ports: &model::POWERHOUSE_767.telemetry.usb_a.ports[..1],
+                timer: None,
+            },
+            watts_scale: 10,
+            external_battery: None,
+            ..model::POWERHOUSE_767.telemetry.clone()
+        };
+        let mut telemetry = sample_telemetry(false);
+        telemetry.usb_a.truncate(1);
+        // Only multiples of the scale survive encoding
+        let round = |watts: &mut Watts| watts.0 -= watts.0 % 10;
+        round(&mut telemetry.ac_outlet.watts);
+        round(&mut telemetry.total_output_watts);
+        round(&mut telemetry.ac_input_watts);
+        round(&mut telemetry.solar_input_watts);
+        round(&mut telemetry.total_input_watts);
+        for output in telemetry
+            .twelve_volt
+            .iter_mut()
+            .chain(&mut telemetry.usb_c)
+            .chain(&mut telemetry.usb_a)
+        {
+            round(&mut output.watts);
+        }
+
+        let bytes = telemetry.to_bytes_with(&layout);
+        assert_eq!(
+            Telemetry::from_bytes_with(&bytes, &layout, ParseMode::Strict).unwrap(),
+            telemetry
+        );
+    }
+
+    #[test]
+    fn state_ack_round_trip() {
+        let state_ack = StateAck {
+            ac_outlet_on: true,
+            twelve_volt_on: false,
+            power_save_on: true,
+            led_state: LedState::Sos,
+        };
+        let packet = NotificationPacket::StateAck(state_ack);
+        assert_eq!(
+            NotificationPacket::from_bytes(&packet.to_bytes()).unwrap(),
+            packet
+        );
+    }
+
+    #[test]
+    fn command_ack_round_trip() {
+        let packet = NotificationPacket::CommandAck(CommandAck {
+            command_type: CommandType::Led,
+        });
+        let bytes = packet.to_bytes();
+        assert_eq!(bytes.len(), EXPECTED_PACKET_LENGTH);
+        assert_eq!(NotificationPacket::from_bytes(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn verify_flags_corrupt_frames_that_still_parse() {
+        let bytes = sample_telemetry(false).to_bytes();
+        assert!(Header::verify(&bytes).is_ok());
+
+        let mut bad_checksum = bytes.clone();
+        bad_checksum[40] ^= 0x01;
+        assert!(matches!(
+            Header::verify(&bad_checksum),
+            Err(TelemetryError::BadChecksum { .. })
+        ));
+        assert!(NotificationPacket::from_bytes(&bad_checksum).is_ok());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = 0x08;
+        assert!(matches!(
+            Header::verify(&bad_magic),
+            Err(TelemetryError::BadMagic(_))
+        ));
+        assert!(NotificationPacket::from_bytes(&bad_magic).is_ok());
+
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert!(matches!(
+            Header::verify(&extended),
+            Err(TelemetryError::LengthMismatch { .. })
+        ));
+        assert!(NotificationPacket::from_bytes(&extended).is_ok());
+    }
+
+    #[test]
+    fn state_ack_parses_without_trailing_checksum() {
+        let ack = StateAck {
+            ac_outlet_on: true,
+            twelve_volt_on: false,
+            power_save_on: true,
+            led_state: LedState::Low,
+        };
+        let bytes = ack.to_bytes();
+        assert_eq!(bytes.len(), STATE_ACK_LENGTH);
+        assert_eq!(
+            StateAck::from_bytes(&bytes[..STATE_ACK_MIN_LENGTH]).unwrap(),
+            ack
+        );
+        assert!(matches!(
+            StateAck::from_bytes(&bytes[..STATE_ACK_MIN_LENGTH - 1]),
+            Err(TelemetryError::DataTooShort { expected: 13, .. })
+        ));
+    }
+
+    #[test]
+    fn lenient_mode_keeps_unknown_values() {
+        let mut bytes = sample_telemetry(true).to_bytes();
+        bytes[model::POWERHOUSE_767.telemetry.battery_state] = 7;
+        let bytes = seal(bytes);
+
+        assert!(matches!(
+            Telemetry::from_bytes(&bytes),
+            Err(TelemetryError::UnknownBatteryState(7))
+        ));
+        let lenient = Telemetry::from_bytes_with(
+            &bytes,
+            &model::POWERHOUSE_767.telemetry,
+            ParseMode::Lenient,
+        )
+        .unwrap();
+        assert_eq!(lenient.battery_state, BatteryState::Unknown(7));
+        assert_eq!(lenient.total_battery_percentage, Percentage(79));
+        assert_eq!(lenient.to_bytes(), bytes);
+
+        let state_ack = StateAck {
+            ac_outlet_on: true,
+            twelve_volt_on: true,
+            power_save_on: false,
+            led_state: LedState::Unknown(9),
+        };
+        let bytes = state_ack.to_bytes();
+        assert!(StateAck::from_bytes(&bytes).is_err());
+        assert_eq!(
+            StateAck::from_bytes_with(&bytes, ParseMode::Lenient).unwrap(),
+            state_ack
+        );
+    }
+
+    #[test]
+    fn parse_error_points_at_offending_byte() {
+        let layout = &model::POWERHOUSE_767.telemetry;
+        let mut bytes = sample_telemetry(false).to_bytes();
+        bytes[layout.battery_state] = 9;
+        let bytes = seal(bytes);
+
+        let error = NotificationPacket::from_bytes(&bytes).unwrap_err();
+        assert_eq!(error.variant(), "unknown_battery_state");
+
+        let error = ParseError::new(error, &bytes, &model::POWERHOUSE_767);
+        assert_eq!(error.offset, Some(layout.battery_state));
+        assert_eq!(error.packet_type, Some(PacketType::Telemetry as u8));
+        assert_eq!(error.telemetry_id, Some(TelemetryType::Telemetry as u8));
+        assert!(error
+            .to_string()
+            .contains("in packet 1/0x49 at offset 68: 09ff"));
+    }
+}
@@ -0,0 +1,181 @@
+//! Unit-carrying value types for telemetry fields.
+//!
+//! Each serializes as `{"value": 120, "unit": "W"}`, so JSON consumers never
+//! have to guess whether a number is watts, percent or hours. Deserializing
+//! rejects a value tagged with a different unit.
+
+use core::fmt;
+use core::time::Duration;
+
+/// Power in watts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Watts(pub u16);
+
+/// State of charge, 0-100
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Percentage(pub u8);
+
+/// Temperature in whole degrees Celsius, as the device reports it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Celsius(pub u8);
+
+/// A span of time in whole seconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Seconds(pub u32);
+
+impl Celsius {
+    pub fn fahrenheit(self) -> f32 {
+        self.0 as f32 * 9.0 / 5.0 + 32.0
+    }
+}
+
+impl Seconds {
+    const PER_DAY: u32 = 24 * 3600;
+    const PER_TENTH_HOUR: u32 = 360;
+
+    /// Runtime as the device encodes it: whole days plus tenths of an hour
+    pub fn from_days_and_tenths(days: u8, tenths: u8) -> Self {
+        Seconds(days as u32 * Self::PER_DAY + tenths as u32 * Self::PER_TENTH_HOUR)
+    }
+
+    /// Inverse of `from_days_and_tenths`, rounded to the nearest tenth of an
+    /// hour. The tenths byte only exceeds a day when the days byte is saturated.
+    pub fn to_days_and_tenths(self) -> (u8, u8) {
+        let tenths = self.0.saturating_add(Self::PER_TENTH_HOUR / 2) / Self::PER_TENTH_HOUR;
+        let tenths_per_day = Self::PER_DAY / Self::PER_TENTH_HOUR;
+        let days = (tenths / tenths_per_day).min(u8::MAX as u32);
+        let rest = (tenths - days * tenths_per_day).min(u8::MAX as u32);
+        (days as u8, rest as u8)
+    }
+
+    pub fn hours(self) -> f32 {
+        self.0 as f32 / 3600.0
+    }
+}
+
+impl From<Seconds> for Duration {
+    fn from(seconds: Seconds) -> Self {
+        Duration::from_secs(seconds.0 as u64)
+    }
+}
+
+/// Saturates at `u32::MAX` seconds; sub-second precision is dropped
+impl From<Duration> for Seconds {
+    fn from(duration: Duration) -> Self {
+        Seconds(u32::try_from(duration.as_secs()).unwrap_or(u32::MAX))
+    }
+}
+
+impl From<u16> for Seconds {
+    fn from(seconds: u16) -> Self {
+        Seconds(seconds as u32)
+    }
+}
+
+macro_rules! quantity {
+    ($name:ident, $unit:literal, $display:literal, $schema_type:ident) => {
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, $display, self.0)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut quantity = serializer.serialize_struct(stringify!($name), 2)?;
+                quantity.serialize_field("value", &self.0)?;
+                quantity.serialize_field("unit", $unit)?;
+                quantity.end()
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let quantity = Quantity::deserialize(deserializer)?;
+                if quantity.unit != $unit {
+                    return Err(serde::de::Error::invalid_value(
+                        serde::de::Unexpected::Str(&quantity.unit),
+                        &concat!("\"", $unit, "\""),
+                    ));
+                }
+                Ok($name(quantity.value))
+            }
+        }
+
+        #[cfg(feature = "openapi")]
+        impl utoipa::PartialSchema for $name {
+            fn schema() -> utoipa::openapi::RefOr<utoipa::openapi::schema::Schema> {
+                quantity_schema(utoipa::openapi::schema::Type::$schema_type, $unit)
+            }
+        }
+
+        #[cfg(feature = "openapi")]
+        impl utoipa::ToSchema for $name {}
+    };
+}
+
+quantity!(Watts, "W", "{} W", Integer);
+quantity!(Percentage, "%", "{}%", Integer);
+quantity!(Celsius, "°C", "{} °C", Integer);
+quantity!(Seconds, "s", "{} s", Integer);
+
+/// Wire form of every quantity
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Quantity<T> {
+    value: T,
+    unit: alloc::string::String,
+}
+
+/// `{"value": <value_type>, "unit": "<unit>"}`
+#[cfg(feature = "openapi")]
+fn quantity_schema(
+    value_type: utoipa::openapi::schema::Type,
+    unit: &str,
+) -> utoipa::openapi::RefOr<utoipa::openapi::schema::Schema> {
+    use utoipa::openapi::schema::{ObjectBuilder, Type};
+
+    ObjectBuilder::new()
+        .property("value", ObjectBuilder::new().schema_type(value_type))
+        .required("value")
+        .property(
+            "unit",
+            ObjectBuilder::new()
+                .schema_type(Type::String)
+                .enum_values(Some([unit])),
+        )
+        .required("unit")
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_encoding_round_trips() {
+        let remaining = Seconds::from_days_and_tenths(2, 15);
+        assert_eq!(remaining, Seconds(2 * 86400 + 5400));
+        assert_eq!(remaining.hours(), 49.5);
+        assert_eq!(remaining.to_days_and_tenths(), (2, 15));
+        assert_eq!(Seconds(u32::MAX).to_days_and_tenths(), (255, 255));
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Celsius(25).fahrenheit(), 77.0);
+        assert_eq!(Duration::from(Seconds(90)), Duration::from_secs(90));
+        assert_eq!(Watts(120).to_string(), "120 W");
+    }
+
+    #[test]
+    fn serializes_with_unit() {
+        let json = serde_json::to_string(&Watts(120)).unwrap();
+        assert_eq!(json, r#"{"value":120,"unit":"W"}"#);
+        assert_eq!(serde_json::from_str::<Watts>(&json).unwrap(), Watts(120));
+        assert!(serde_json::from_str::<Watts>(r#"{"value":120,"unit":"kW"}"#).is_err());
+    }
+}
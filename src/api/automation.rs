@@ -0,0 +1,154 @@
+//! API endpoint handlers for server-side output automation.
+
+use crate::api::handlers::AppState;
+use crate::api::types::{ApiError, ApiSuccess};
+use crate::automation::{
+    DutyCycleConfig, DutyCycleStatus, OutputPort, OutputTimerRequest, OutputTimerStatus,
+};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+
+/// List running duty cycles
+#[utoipa::path(
+    get,
+    path = "/api/duty-cycle",
+    responses(
+        (status = 200, description = "Running duty cycles", body = Vec<DutyCycleStatus>)
+    ),
+    tag = "automation"
+)]
+pub async fn list_duty_cycles(State(state): State<AppState>) -> Json<Vec<DutyCycleStatus>> {
+    Json(state.duty_cycles.status().await)
+}
+
+/// Start (or replace) a duty cycle on an output, cancelling its output timer if any
+#[utoipa::path(
+    put,
+    path = "/api/duty-cycle/{output}",
+    params(("output" = OutputPort, Path, description = "Output to cycle")),
+    request_body = DutyCycleConfig,
+    responses(
+        (status = 200, description = "Duty cycle started", body = DutyCycleStatus),
+        (status = 400, description = "Invalid duty cycle", body = ApiError)
+    ),
+    tag = "automation"
+)]
+pub async fn start_duty_cycle(
+    State(state): State<AppState>,
+    Path(output): Path<OutputPort>,
+    Json(req): Json<DutyCycleConfig>,
+) -> Result<Json<DutyCycleStatus>, (StatusCode, Json<ApiError>)> {
+    state
+        .duty_cycles
+        .start(output, req)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ApiError {
+                    error: e.to_string(),
+                }),
+            )
+        })
+}
+
+/// Cancel the duty cycle on an output
+#[utoipa::path(
+    delete,
+    path = "/api/duty-cycle/{output}",
+    params(("output" = OutputPort, Path, description = "Output to stop cycling")),
+    responses(
+        (status = 200, description = "Duty cycle cancelled", body = ApiSuccess),
+        (status = 404, description = "No duty cycle running", body = ApiError)
+    ),
+    tag = "automation"
+)]
+pub async fn cancel_duty_cycle(
+    State(state): State<AppState>,
+    Path(output): Path<OutputPort>,
+) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
+    if state.duty_cycles.cancel(output).await {
+        Ok(Json(ApiSuccess { success: true }))
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: format!("No duty cycle running on {}", output.as_str()),
+            }),
+        ))
+    }
+}
+
+/// List pending server-side output timers
+#[utoipa::path(
+    get,
+    path = "/api/output-timer",
+    responses(
+        (status = 200, description = "Pending output timers", body = Vec<OutputTimerStatus>)
+    ),
+    tag = "automation"
+)]
+pub async fn list_output_timers(State(state): State<AppState>) -> Json<Vec<OutputTimerStatus>> {
+    Json(state.output_timers.status().await)
+}
+
+/// Start (or replace) a server-side off timer on an output
+#[utoipa::path(
+    put,
+    path = "/api/output-timer/{output}",
+    params(("output" = OutputPort, Path, description = "Output to turn off")),
+    request_body = OutputTimerRequest,
+    responses(
+        (status = 200, description = "Timer started", body = OutputTimerStatus),
+        (status = 400, description = "Invalid timer", body = ApiError)
+    ),
+    tag = "automation"
+)]
+pub async fn start_output_timer(
+    State(state): State<AppState>,
+    Path(output): Path<OutputPort>,
+    Json(req): Json<OutputTimerRequest>,
+) -> Result<Json<OutputTimerStatus>, (StatusCode, Json<ApiError>)> {
+    state
+        .output_timers
+        .start(output, req)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ApiError {
+                    error: e.to_string(),
+                }),
+            )
+        })
+}
+
+/// Cancel the server-side timer on an output
+#[utoipa::path(
+    delete,
+    path = "/api/output-timer/{output}",
+    params(("output" = OutputPort, Path, description = "Output whose timer to cancel")),
+    responses(
+        (status = 200, description = "Timer cancelled", body = ApiSuccess),
+        (status = 404, description = "No timer pending", body = ApiError)
+    ),
+    tag = "automation"
+)]
+pub async fn cancel_output_timer(
+    State(state): State<AppState>,
+    Path(output): Path<OutputPort>,
+) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
+    if state.output_timers.cancel(output).await {
+        Ok(Json(ApiSuccess { success: true }))
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: format!("No timer pending on {}", output.as_str()),
+            }),
+        ))
+    }
+}
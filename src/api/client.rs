@@ -0,0 +1,257 @@
+//! Typed client for a running server's REST API. Uses the same request and
+//! response types as the handlers, so the two can't drift apart.
+
+use crate::api::types::{
+    ApiError, ApiSuccess, BoolRequest, BrightnessRequest, LedRequest, SecondsRequest,
+    StatusResponse, WattsRequest,
+};
+use crate::automation::{OutputPort, OutputTimerRequest, OutputTimerStatus};
+use crate::ble::telemetry::VersionedTelemetry;
+use crate::ble::{DeviceEvent, DeviceSettingsReport};
+use futures::Stream;
+use reqwest::{Method, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ApiClientError {
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The server answered with an error status
+    #[error("{error} (HTTP {status})")]
+    Api { status: u16, error: String },
+    #[error("Invalid event: {0}")]
+    Event(#[from] serde_json::Error),
+}
+
+impl ApiClientError {
+    /// Whether the server couldn't be reached at all
+    pub fn is_connect(&self) -> bool {
+        matches!(self, ApiClientError::Http(e) if e.is_connect() || e.is_timeout())
+    }
+}
+
+/// Client for the server at `base_url`, e.g. `http://localhost:3000`
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl ApiClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn status(&self) -> Result<StatusResponse, ApiClientError> {
+        self.get("/api/status").await
+    }
+
+    pub async fn telemetry(&self) -> Result<VersionedTelemetry, ApiClientError> {
+        self.get("/api/telemetry").await
+    }
+
+    pub async fn device_state(&self) -> Result<DeviceSettingsReport, ApiClientError> {
+        self.get("/api/device-state").await
+    }
+
+    /// Device events from the server-sent event stream, until the server closes it
+    pub async fn events(
+        &self,
+    ) -> Result<
+        impl Stream<Item = Result<DeviceEvent, ApiClientError>> + Send + 'static,
+        ApiClientError,
+    > {
+        let url = format!("{}/api/events", self.base_url);
+        let response = check(self.http.get(url).send().await?).await?;
+
+        Ok(futures::stream::unfold(
+            (Some(response), Vec::new()),
+            |(mut response, mut buffer)| async move {
+                loop {
+                    if let Some(data) = next_event_data(&mut buffer) {
+                        let event = serde_json::from_slice(&data).map_err(ApiClientError::from);
+                        return Some((event, (response, buffer)));
+                    }
+                    match response.as_mut()?.chunk().await {
+                        Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+                        Ok(None) => return None,
+                        Err(e) => return Some((Err(e.into()), (None, buffer))),
+                    }
+                }
+            },
+        ))
+    }
+
+    pub async fn set_ac_output(&self, is_on: bool) -> Result<(), ApiClientError> {
+        self.command("/api/ac-output", &BoolRequest { is_on }).await
+    }
+
+    pub async fn set_twelve_volt_output(&self, is_on: bool) -> Result<(), ApiClientError> {
+        self.command("/api/twelve-volt-output", &BoolRequest { is_on })
+            .await
+    }
+
+    pub async fn set_power_save(&self, is_on: bool) -> Result<(), ApiClientError> {
+        self.command("/api/power-save", &BoolRequest { is_on })
+            .await
+    }
+
+    /// LED level 0-4, where 4 is SOS
+    pub async fn set_led(&self, level: u8) -> Result<(), ApiClientError> {
+        self.command("/api/led", &LedRequest { level }).await
+    }
+
+    /// Brightness level 0-3
+    pub async fn set_screen_brightness(&self, level: u8) -> Result<(), ApiClientError> {
+        self.command("/api/screen-brightness", &BrightnessRequest { level })
+            .await
+    }
+
+    /// Recharge power in watts (200-1440)
+    pub async fn set_recharge_power(&self, watts: u16) -> Result<(), ApiClientError> {
+        self.command("/api/recharge-power", &WattsRequest { watts })
+            .await
+    }
+
+    pub async fn set_screen_timeout(&self, seconds: u16) -> Result<(), ApiClientError> {
+        self.command("/api/screen-timeout", &SecondsRequest { seconds })
+            .await
+    }
+
+    /// Device auto-off timer for the AC outlets; 0 disables it
+    pub async fn set_ac_timer(&self, seconds: u16) -> Result<(), ApiClientError> {
+        self.command("/api/ac-timer", &SecondsRequest { seconds })
+            .await
+    }
+
+    /// Device auto-off timer for the 12V outputs; 0 disables it
+    pub async fn set_twelve_volt_timer(&self, seconds: u16) -> Result<(), ApiClientError> {
+        self.command("/api/twelve-volt-timer", &SecondsRequest { seconds })
+            .await
+    }
+
+    pub async fn output_timers(&self) -> Result<Vec<OutputTimerStatus>, ApiClientError> {
+        self.get("/api/output-timer").await
+    }
+
+    /// Start (or replace) a server-side timer that turns `port` off
+    pub async fn start_output_timer(
+        &self,
+        port: OutputPort,
+        req: &OutputTimerRequest,
+    ) -> Result<OutputTimerStatus, ApiClientError> {
+        let path = format!("/api/output-timer/{}", port.as_str());
+        self.request(Method::PUT, &path, Some(req)).await
+    }
+
+    pub async fn cancel_output_timer(&self, port: OutputPort) -> Result<(), ApiClientError> {
+        let path = format!("/api/output-timer/{}", port.as_str());
+        self.request::<ApiSuccess, ()>(Method::DELETE, &path, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ApiClientError> {
+        self.request::<T, ()>(Method::GET, path, None).await
+    }
+
+    async fn command<B: Serialize>(&self, path: &str, body: &B) -> Result<(), ApiClientError> {
+        self.request::<ApiSuccess, B>(Method::POST, path, Some(body))
+            .await?;
+        Ok(())
+    }
+
+    async fn request<T: DeserializeOwned, B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<T, ApiClientError> {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await?;
+        Ok(check(response).await?.json().await?)
+    }
+}
+
+/// Take the `data` of the next complete message out of an event stream
+/// buffer, skipping keep-alive comments
+fn next_event_data(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    loop {
+        let end = buffer.windows(2).position(|w| w == b"\n\n")?;
+        let message: Vec<u8> = buffer.drain(..end + 2).collect();
+        let data: Vec<&[u8]> = message
+            .split(|&b| b == b'\n')
+            .filter_map(|line| line.strip_prefix(b"data:"))
+            .map(|data| data.strip_prefix(b" ").unwrap_or(data))
+            .collect();
+        if !data.is_empty() {
+            return Some(data.join(&b'\n'));
+        }
+    }
+}
+
+/// Turn an error status into `ApiClientError::Api`, using the server's message if any
+async fn check(response: Response) -> Result<Response, ApiClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let error = match response.json::<ApiError>().await {
+        Ok(body) => body.error,
+        Err(_) => status
+            .canonical_reason()
+            .unwrap_or("Request failed")
+            .to_string(),
+    };
+    Err(ApiClientError::Api {
+        status: status.as_u16(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_data_skips_keep_alive_comments() {
+        let mut buffer = b": keep-alive\n\n: keep-alive\n\ndata: {\"a\":1}\n\n".to_vec();
+        assert_eq!(next_event_data(&mut buffer), Some(b"{\"a\":1}".to_vec()));
+        assert!(buffer.is_empty());
+        assert_eq!(next_event_data(&mut buffer), None);
+    }
+
+    #[test]
+    fn event_data_joins_multi_line_data() {
+        let mut buffer = b"event: telemetry\ndata: {\"a\":\ndata:1}\nid: 7\n\n".to_vec();
+        assert_eq!(next_event_data(&mut buffer), Some(b"{\"a\":\n1}".to_vec()));
+    }
+
+    #[test]
+    fn event_data_waits_for_the_rest_of_a_split_message() {
+        let stream = b"data: {\"a\":1}\n\ndata: {\"b\":2}\n\n";
+        for split in 0..stream.len() {
+            let mut buffer = stream[..split].to_vec();
+            let mut messages = Vec::new();
+            messages.extend(std::iter::from_fn(|| next_event_data(&mut buffer)));
+            buffer.extend_from_slice(&stream[split..]);
+            messages.extend(std::iter::from_fn(|| next_event_data(&mut buffer)));
+            assert_eq!(
+                messages,
+                vec![b"{\"a\":1}".to_vec(), b"{\"b\":2}".to_vec()],
+                "split at {}",
+                split
+            );
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! API endpoint handlers for protocol debugging and research.
+
+use crate::api::handlers::AppState;
+use crate::api::types::ApiError;
+use crate::ble::capture::from_hex;
+use crate::ble::command::RawCommand;
+use crate::ble::explorer::ExplorerReport;
+use crate::ble::probe::{self, ProbeResult};
+use crate::ble::rejected::RejectedFrame;
+use crate::metrics;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::Json;
+use serde::Deserialize;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+const DEFAULT_WINDOW_MS: u64 = 2_000;
+
+/// Byte-level diff of recent telemetry frames, for mapping unknown fields
+#[utoipa::path(
+    get,
+    path = "/api/debug/protocol-explorer",
+    responses(
+        (status = 200, description = "Changing unmapped bytes and their correlation with sent commands",
+            body = ExplorerReport)
+    ),
+    tag = "debug"
+)]
+pub async fn get_protocol_explorer(State(state): State<AppState>) -> Json<ExplorerReport> {
+    Json(state.anker.explorer_report().await)
+}
+
+/// Notifications that failed to parse recently, with error context, for bug reports
+#[utoipa::path(
+    get,
+    path = "/api/debug/rejected-frames",
+    responses(
+        (status = 200, description = "Last rejected frames, oldest first", body = [RejectedFrame])
+    ),
+    tag = "debug"
+)]
+pub async fn get_rejected_frames(State(state): State<AppState>) -> Json<Vec<RejectedFrame>> {
+    Json(state.anker.rejected_frames())
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RawCommandRequest {
+    /// Command type byte
+    pub command_type: u8,
+    /// Parameter bytes as hex (whitespace allowed)
+    #[serde(default)]
+    pub parameters: String,
+    /// How long to collect notifications, in milliseconds (default 2000, max 10000)
+    #[serde(default = "default_window_ms")]
+    pub window_ms: u64,
+    /// Also return telemetry frames received in the window
+    #[serde(default)]
+    pub include_telemetry: bool,
+}
+
+fn default_window_ms() -> u64 {
+    DEFAULT_WINDOW_MS
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> (StatusCode, Json<ApiError>) {
+    (
+        status,
+        Json(ApiError {
+            error: message.into(),
+        }),
+    )
+}
+
+/// Send an arbitrary command type and parameters, framed like every other command.
+///
+/// Disabled unless the server is started with `ANKER_RAW_COMMAND_TOKEN`;
+/// requests must carry `Authorization: Bearer <token>`. The body is only
+/// parsed after the token is checked.
+#[utoipa::path(
+    post,
+    path = "/api/debug/raw-command",
+    request_body = RawCommandRequest,
+    responses(
+        (status = 200, description = "Command sent; notifications received in the window", body = ProbeResult),
+        (status = 400, description = "Invalid parameters", body = ApiError),
+        (status = 401, description = "Missing or wrong token", body = ApiError),
+        (status = 404, description = "Raw commands are disabled", body = ApiError),
+        (status = 503, description = "Not connected", body = ApiError)
+    ),
+    tag = "debug"
+)]
+pub async fn send_raw_command(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ProbeResult>, (StatusCode, Json<ApiError>)> {
+    let token = state
+        .raw_command_token
+        .as_deref()
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Raw commands are disabled"))?;
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|provided| constant_time_eq(provided.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return Err(error(StatusCode::UNAUTHORIZED, "Missing or wrong token"));
+    }
+
+    // Only parsed once the caller is authorized, so a disabled or guarded
+    // endpoint never echoes body errors back
+    let req: RawCommandRequest = serde_json::from_slice(&body).map_err(|e| {
+        error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid request body: {}", e),
+        )
+    })?;
+
+    let parameters = from_hex(&req.parameters)
+        .ok_or_else(|| error(StatusCode::BAD_REQUEST, "parameters must be hex bytes"))?;
+    let command = RawCommand::new(req.command_type, parameters)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let result = probe::probe(
+        &state.anker,
+        &command,
+        Duration::from_millis(req.window_ms),
+        req.include_telemetry,
+    )
+    .await
+    .map_err(|e| error(StatusCode::SERVICE_UNAVAILABLE, e.to_string()))?;
+
+    metrics::increment_command("raw");
+    Ok(Json(result))
+}
+
+/// Compare without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::BatteryHealthTracker;
+    use crate::ble::AnkerDevice;
+    use axum::http::HeaderValue;
+    use std::sync::Arc;
+
+    async fn state(token: Option<&str>) -> AppState {
+        let (device, _written) = AnkerDevice::fake_connected().await;
+        let state = AppState::new(&device, BatteryHealthTracker::in_memory());
+        match token {
+            Some(token) => state.with_raw_command_token(token),
+            None => state,
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+        headers
+    }
+
+    async fn status(state: AppState, headers: HeaderMap, body: &'static str) -> StatusCode {
+        match send_raw_command(State(state), headers, Bytes::from_static(body.as_bytes())).await {
+            Ok(_) => StatusCode::OK,
+            Err((status, _)) => status,
+        }
+    }
+
+    #[tokio::test]
+    async fn disabled_without_a_token() {
+        let state = state(None).await;
+        assert_eq!(
+            status(state.clone(), bearer("secret"), r#"{"command_type":66}"#).await,
+            StatusCode::NOT_FOUND
+        );
+        // The body isn't looked at
+        assert_eq!(
+            status(state, HeaderMap::new(), "not json").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn wrong_or_missing_token_is_unauthorized() {
+        let state = state(Some("secret")).await;
+        let body = r#"{"command_type":66}"#;
+        assert_eq!(
+            status(state.clone(), HeaderMap::new(), body).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(state.clone(), bearer("secreT"), body).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(state.clone(), bearer("secret2"), body).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let mut basic = HeaderMap::new();
+        basic.insert(header::AUTHORIZATION, HeaderValue::from_static("secret"));
+        assert_eq!(
+            status(state.clone(), basic, body).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // Checked before the body is parsed
+        assert_eq!(
+            status(state, bearer("wrong"), "not json").await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_body_is_rejected_after_auth() {
+        let state = state(Some("secret")).await;
+        assert_eq!(
+            status(state.clone(), bearer("secret"), "not json").await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(
+                state,
+                bearer("secret"),
+                r#"{"command_type":66,"parameters":"0g"}"#
+            )
+            .await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn authorized_command_is_sent() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let state = AppState::new(&device, BatteryHealthTracker::in_memory())
+            .with_raw_command_token(Arc::<str>::from("secret"));
+
+        let body = r#"{"command_type":66,"parameters":"01 02","window_ms":10}"#;
+        let Json(result) = send_raw_command(
+            State(state),
+            bearer("secret"),
+            Bytes::from_static(body.as_bytes()),
+        )
+        .await
+        .unwrap();
+
+        let sent = RawCommand::new(0x42, vec![0x01, 0x02]).unwrap().to_bytes();
+        assert_eq!(*written.lock().unwrap(), vec![sent]);
+        assert!(result.frames.is_empty());
+    }
+
+    #[test]
+    fn constant_time_eq_compares_whole_slices() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+}
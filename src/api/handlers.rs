@@ -1,67 +1,69 @@
 //! API endpoint handlers for Anker PowerHouse 767.
 
+use crate::api::types::{
+    ApiError, ApiSuccess, BoolRequest, BrightnessRequest, LedRequest, SecondsRequest,
+    StatusResponse, WattsRequest,
+};
+use crate::automation::{DutyCycleManager, OutputTimerManager};
+use crate::battery::{BatteryEstimate, BatteryEstimator, BatteryHealth, BatteryHealthTracker};
 use crate::ble::command::{
     AcOutputCommand, AcTimerCommand, LedCommand, PowerSaveCommand, RechargePowerCommand,
     ScreenBrightnessCommand, ScreenTimeoutCommand, TwelveVoltOutputCommand, TwelveVoltTimerCommand,
 };
-use crate::ble::{send_command, AnkerCommand, ConnectionState, DeviceState, SetState, Telemetry};
+use crate::ble::telemetry::VersionedTelemetry;
+use crate::ble::{
+    AnkerCommand, AnkerDevice, ConnectionState, DeviceError, DeviceEvent, DeviceSettingsReport,
+    DeviceState,
+};
 use crate::metrics;
 use axum::extract::State;
 use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::IntoResponse;
 use axum::Json;
-use serde::{Deserialize, Serialize};
+use futures::Stream;
 use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio::sync::RwLock;
-use utoipa::ToSchema;
-
-pub type AppState = Arc<RwLock<DeviceState>>;
-
-#[derive(Debug, Serialize, ToSchema)]
-pub struct ApiError {
-    pub error: String,
-}
-
-#[derive(Debug, Serialize, ToSchema)]
-pub struct ApiSuccess {
-    pub success: bool,
-}
-
-#[derive(Debug, Serialize, ToSchema)]
-pub struct StatusResponse {
-    pub connected: bool,
-    pub state: String,
-}
-
-// Request types
-
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct BoolRequest {
-    pub is_on: bool,
-}
-
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct BrightnessRequest {
-    /// Brightness level (0-3)
-    pub level: u8,
-}
 
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct LedRequest {
-    /// LED level (0-4, where 4 is SOS mode)
-    pub level: u8,
+/// Shared state for all API handlers
+#[derive(Clone)]
+pub struct AppState {
+    pub device: Arc<RwLock<DeviceState>>,
+    pub duty_cycles: Arc<DutyCycleManager>,
+    pub output_timers: Arc<OutputTimerManager>,
+    pub estimator: Arc<RwLock<BatteryEstimator>>,
+    pub battery_health: Arc<RwLock<BatteryHealthTracker>>,
+    pub events: broadcast::Sender<DeviceEvent>,
+    /// Connection commands are sent over
+    pub anker: Arc<AnkerDevice>,
+    /// Bearer token for the raw command endpoint; `None` disables it
+    pub raw_command_token: Option<Arc<str>>,
 }
 
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct WattsRequest {
-    /// Recharge power in watts (200-1440)
-    pub watts: u16,
-}
+impl AppState {
+    pub fn new(anker: &Arc<AnkerDevice>, battery_health: BatteryHealthTracker) -> Self {
+        let output_timers = Arc::new(OutputTimerManager::new(Arc::clone(anker)));
+        Self {
+            device: anker.state(),
+            events: anker.events(),
+            raw_command_token: None,
+            duty_cycles: Arc::new(DutyCycleManager::new(
+                Arc::clone(anker),
+                Arc::clone(&output_timers),
+            )),
+            output_timers,
+            estimator: Arc::new(RwLock::new(BatteryEstimator::new())),
+            battery_health: Arc::new(RwLock::new(battery_health)),
+            anker: Arc::clone(anker),
+        }
+    }
 
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct SecondsRequest {
-    /// Timeout/timer in seconds (0-65535)
-    pub seconds: u16,
+    /// Enable the raw command endpoint, guarded by `token`
+    pub fn with_raw_command_token(mut self, token: impl Into<Arc<str>>) -> Self {
+        self.raw_command_token = Some(token.into());
+        self
+    }
 }
 
 // Handler implementations
@@ -76,17 +78,12 @@ pub struct SecondsRequest {
     tag = "status"
 )]
 pub async fn get_status(State(state): State<AppState>) -> Json<StatusResponse> {
-    let state = state.read().await;
-    let state_str = match state.connection_state {
-        ConnectionState::Disconnected => "disconnected",
-        ConnectionState::Scanning => "scanning",
-        ConnectionState::Connecting => "connecting",
-        ConnectionState::Connected => "connected",
-    };
+    let state = state.device.read().await;
 
     Json(StatusResponse {
         connected: state.connection_state == ConnectionState::Connected,
-        state: state_str.to_string(),
+        state: state.connection_state.as_str().to_string(),
+        model: state.model.map(|m| m.model.to_string()),
     })
 }
 
@@ -95,20 +92,20 @@ pub async fn get_status(State(state): State<AppState>) -> Json<StatusResponse> {
     get,
     path = "/api/telemetry",
     responses(
-        (status = 200, description = "Current telemetry", body = Telemetry),
+        (status = 200, description = "Current telemetry", body = VersionedTelemetry),
         (status = 503, description = "No telemetry available", body = ApiError)
     ),
     tag = "telemetry"
 )]
 pub async fn get_telemetry(
     State(state): State<AppState>,
-) -> Result<Json<Telemetry>, (StatusCode, Json<ApiError>)> {
-    let state = state.read().await;
+) -> Result<Json<VersionedTelemetry>, (StatusCode, Json<ApiError>)> {
+    let state = state.device.read().await;
 
     state
         .last_telemetry
         .clone()
-        .map(Json)
+        .map(|t| Json(t.into()))
         .ok_or_else(|| {
             (
                 StatusCode::SERVICE_UNAVAILABLE,
@@ -119,18 +116,91 @@ pub async fn get_telemetry(
         })
 }
 
-/// Get current device state (last set values)
+/// Get battery time-to-empty / time-to-full estimates
+#[utoipa::path(
+    get,
+    path = "/api/telemetry/estimate",
+    responses(
+        (status = 200, description = "Battery runtime estimate", body = BatteryEstimate),
+        (status = 503, description = "No telemetry in the last minute", body = ApiError)
+    ),
+    tag = "telemetry"
+)]
+pub async fn get_battery_estimate(
+    State(state): State<AppState>,
+) -> Result<Json<BatteryEstimate>, (StatusCode, Json<ApiError>)> {
+    state
+        .estimator
+        .read()
+        .await
+        .estimate()
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ApiError {
+                    error: "No recent telemetry".to_string(),
+                }),
+            )
+        })
+}
+
+/// Get the long-term battery health report
+#[utoipa::path(
+    get,
+    path = "/api/battery-health",
+    responses(
+        (status = 200, description = "Battery wear counters per pack", body = BatteryHealth)
+    ),
+    tag = "telemetry"
+)]
+pub async fn get_battery_health(State(state): State<AppState>) -> Json<BatteryHealth> {
+    Json(state.battery_health.read().await.health().clone())
+}
+
+/// Stream device events (expansion battery attach/detach) as server-sent events
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    responses(
+        (status = 200, description = "One JSON event per message", body = DeviceEvent,
+            content_type = "text/event-stream")
+    ),
+    tag = "telemetry"
+)]
+pub async fn get_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let rx = state.events.subscribe();
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((Event::default().json_data(event), rx)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Get current device settings, read back from the device where possible.
+///
+/// Screen brightness, screen timeout, recharge power and the AC timer are not
+/// read back: their `device` value is always null and `source` is `last_set`
+/// or `unknown`.
 #[utoipa::path(
     get,
     path = "/api/device-state",
     responses(
-        (status = 200, description = "Current device state", body = SetState)
+        (status = 200, description = "Device-reported and last set values per setting",
+            body = DeviceSettingsReport)
     ),
     tag = "telemetry"
 )]
-pub async fn get_device_state(State(state): State<AppState>) -> Json<SetState> {
-    let state = state.read().await;
-    Json(state.set_state.clone())
+pub async fn get_device_state(State(state): State<AppState>) -> Json<DeviceSettingsReport> {
+    Json(state.device.read().await.settings())
 }
 
 /// Toggle power save mode
@@ -149,9 +219,7 @@ pub async fn set_power_save(
     Json(req): Json<BoolRequest>,
 ) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
     let cmd = AnkerCommand::PowerSave(PowerSaveCommand::new(req.is_on));
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.power_save = Some(req.is_on);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Toggle AC output
@@ -170,9 +238,7 @@ pub async fn set_ac_output(
     Json(req): Json<BoolRequest>,
 ) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
     let cmd = AnkerCommand::AcOutput(AcOutputCommand::new(req.is_on));
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.ac_output = Some(req.is_on);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Toggle 12V output
@@ -191,9 +257,7 @@ pub async fn set_twelve_volt_output(
     Json(req): Json<BoolRequest>,
 ) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
     let cmd = AnkerCommand::TwelveVoltOutput(TwelveVoltOutputCommand::new(req.is_on));
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.twelve_volt_output = Some(req.is_on);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Set screen brightness
@@ -221,9 +285,7 @@ pub async fn set_screen_brightness(
         )
     })?;
     let cmd = AnkerCommand::ScreenBrightness(inner);
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.screen_brightness = Some(req.level);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Set LED level
@@ -251,9 +313,7 @@ pub async fn set_led(
         )
     })?;
     let cmd = AnkerCommand::Led(inner);
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.led_level = Some(req.level);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Set recharge power
@@ -281,9 +341,7 @@ pub async fn set_recharge_power(
         )
     })?;
     let cmd = AnkerCommand::RechargePower(inner);
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.recharge_power = Some(req.watts);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Set screen timeout
@@ -302,9 +360,7 @@ pub async fn set_screen_timeout(
     Json(req): Json<SecondsRequest>,
 ) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
     let cmd = AnkerCommand::ScreenTimeout(ScreenTimeoutCommand::new(req.seconds));
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.screen_timeout = Some(req.seconds);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Set AC timer
@@ -323,9 +379,7 @@ pub async fn set_ac_timer(
     Json(req): Json<SecondsRequest>,
 ) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
     let cmd = AnkerCommand::AcTimer(AcTimerCommand::new(req.seconds));
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.ac_timer = Some(req.seconds);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Set 12V timer
@@ -344,9 +398,7 @@ pub async fn set_twelve_volt_timer(
     Json(req): Json<SecondsRequest>,
 ) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
     let cmd = AnkerCommand::TwelveVoltTimer(TwelveVoltTimerCommand::new(req.seconds));
-    let result = send_and_track(cmd).await?;
-    state.write().await.set_state.twelve_volt_timer = Some(req.seconds);
-    Ok(result)
+    send_and_track(&state.anker, cmd).await
 }
 
 /// Prometheus metrics endpoint
@@ -355,13 +407,18 @@ pub async fn get_metrics() -> impl IntoResponse {
 }
 
 async fn send_and_track(
+    anker: &AnkerDevice,
     cmd: AnkerCommand,
 ) -> Result<Json<ApiSuccess>, (StatusCode, Json<ApiError>)> {
     let cmd_type = cmd.command_type().as_str().to_string();
 
-    send_command(cmd).await.map_err(|e| {
+    anker.send_command(cmd).await.map_err(|e| {
+        let status = match e {
+            DeviceError::UnsupportedCommand { .. } => StatusCode::BAD_REQUEST,
+            _ => StatusCode::SERVICE_UNAVAILABLE,
+        };
         (
-            StatusCode::SERVICE_UNAVAILABLE,
+            status,
             Json(ApiError {
                 error: e.to_string(),
             }),
@@ -1,3 +1,19 @@
+//! REST API: server handlers, the shared request/response types and a client.
+
+#[cfg(feature = "server")]
+pub mod automation;
+#[cfg(feature = "cli")]
+pub mod client;
+#[cfg(feature = "server")]
+pub mod debug;
+#[cfg(feature = "server")]
 pub mod handlers;
+pub mod types;
 
+#[cfg(feature = "server")]
+pub use automation::*;
+#[cfg(feature = "server")]
+pub use debug::*;
+#[cfg(feature = "server")]
 pub use handlers::*;
+pub use types::*;
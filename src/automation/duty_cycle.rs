@@ -0,0 +1,378 @@
+//! Duty-cycle mode for the AC and 12V outputs: on for X minutes, off for Y
+//! minutes, optionally only while the battery is discharging. Starting a cycle
+//! cancels any output timer pending on the same output.
+
+use crate::automation::{
+    supervise, switch_output, AutomationError, OutputPort, OutputTimerManager,
+};
+use crate::ble::telemetry::BatteryState;
+use crate::ble::units::Seconds;
+use crate::ble::AnkerDevice;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Instant};
+use tracing::info;
+
+/// How often the runner re-checks the battery state within a phase
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DutyCycleConfig {
+    /// Minutes the output stays on per cycle (>= 1)
+    pub on_minutes: u32,
+    /// Minutes the output stays off per cycle (>= 1)
+    pub off_minutes: u32,
+    /// Only cycle while the battery is discharging; the output is left on otherwise
+    #[serde(default)]
+    pub only_when_discharging: bool,
+}
+
+impl DutyCycleConfig {
+    pub fn validate(&self) -> Result<(), AutomationError> {
+        if self.on_minutes == 0 || self.off_minutes == 0 {
+            return Err(AutomationError::InvalidValue(format!(
+                "on_minutes and off_minutes must be at least 1, got {}/{}",
+                self.on_minutes, self.off_minutes
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum DutyCyclePhase {
+    /// Waiting for the first command to reach the device
+    Pending,
+    On,
+    Off,
+    /// Battery is not discharging, output held on
+    Suspended,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DutyCycleStatus {
+    pub output: OutputPort,
+    pub config: DutyCycleConfig,
+    pub phase: DutyCyclePhase,
+    /// Time until the next on/off switch (absent while pending or suspended)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase_remaining: Option<Seconds>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Progress {
+    phase: DutyCyclePhase,
+    phase_ends_at: Option<Instant>,
+}
+
+struct DutyCycleTask {
+    config: DutyCycleConfig,
+    progress: Arc<RwLock<Progress>>,
+    handle: JoinHandle<()>,
+}
+
+impl Drop for DutyCycleTask {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Owns the running duty cycles, at most one per output
+pub struct DutyCycleManager {
+    device: Arc<AnkerDevice>,
+    /// Timers on a cycled output would fight the cycle, so starting one cancels them
+    timers: Arc<OutputTimerManager>,
+    cycles: Mutex<HashMap<OutputPort, DutyCycleTask>>,
+}
+
+impl DutyCycleManager {
+    pub fn new(device: Arc<AnkerDevice>, timers: Arc<OutputTimerManager>) -> Self {
+        Self {
+            device,
+            timers,
+            cycles: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start a duty cycle on `port`, replacing any cycle already running there
+    /// and cancelling any output timer pending on it
+    pub async fn start(
+        &self,
+        port: OutputPort,
+        config: DutyCycleConfig,
+    ) -> Result<DutyCycleStatus, AutomationError> {
+        config.validate()?;
+
+        // Stop the old cycle before the new one can switch the output
+        let mut cycles = self.cycles.lock().await;
+        if let Some(mut old) = cycles.remove(&port) {
+            old.handle.abort();
+            let _ = (&mut old.handle).await;
+        }
+        if self.timers.cancel(port).await {
+            info!("Duty cycle replaces the {} timer", port.as_str());
+        }
+
+        let progress = Arc::new(RwLock::new(Progress {
+            phase: DutyCyclePhase::Pending,
+            phase_ends_at: None,
+        }));
+
+        let device = Arc::clone(&self.device);
+        let task_config = config.clone();
+        let task_progress = Arc::clone(&progress);
+        let handle = supervise(format!("duty cycle ({})", port.as_str()), move || {
+            run(
+                Arc::clone(&device),
+                port,
+                task_config.clone(),
+                Arc::clone(&task_progress),
+            )
+        });
+
+        info!(
+            "Starting {} duty cycle: {}m on / {}m off{}",
+            port.as_str(),
+            config.on_minutes,
+            config.off_minutes,
+            if config.only_when_discharging {
+                " (only when discharging)"
+            } else {
+                ""
+            }
+        );
+
+        let task = DutyCycleTask {
+            config,
+            progress,
+            handle,
+        };
+        let status = task.status(port).await;
+        cycles.insert(port, task);
+        Ok(status)
+    }
+
+    /// Stop the duty cycle on `port`, leaving the output in its current state.
+    /// Returns false if no cycle was running.
+    pub async fn cancel(&self, port: OutputPort) -> bool {
+        let removed = self.cycles.lock().await.remove(&port);
+        if removed.is_some() {
+            info!("Cancelled {} duty cycle", port.as_str());
+        }
+        removed.is_some()
+    }
+
+    pub async fn status(&self) -> Vec<DutyCycleStatus> {
+        let cycles = self.cycles.lock().await;
+        let mut statuses = Vec::with_capacity(cycles.len());
+        for (port, task) in cycles.iter() {
+            statuses.push(task.status(*port).await);
+        }
+        statuses.sort_by_key(|s| s.output.as_str());
+        statuses
+    }
+}
+
+impl DutyCycleTask {
+    async fn status(&self, port: OutputPort) -> DutyCycleStatus {
+        let progress = *self.progress.read().await;
+        DutyCycleStatus {
+            output: port,
+            config: self.config.clone(),
+            phase: progress.phase,
+            phase_remaining: progress
+                .phase_ends_at
+                .map(|at| at.saturating_duration_since(Instant::now()).into()),
+        }
+    }
+}
+
+async fn is_discharging(device: &AnkerDevice) -> bool {
+    device
+        .state()
+        .read()
+        .await
+        .last_telemetry
+        .as_ref()
+        .is_some_and(|t| t.battery_state == BatteryState::Discharging)
+}
+
+async fn run(
+    device: Arc<AnkerDevice>,
+    port: OutputPort,
+    config: DutyCycleConfig,
+    progress: Arc<RwLock<Progress>>,
+) {
+    let on_duration = Duration::from_secs(config.on_minutes as u64 * 60);
+    let off_duration = Duration::from_secs(config.off_minutes as u64 * 60);
+
+    let mut phase = DutyCyclePhase::Pending;
+    let mut phase_ends_at = Instant::now();
+
+    loop {
+        let now = Instant::now();
+        let active = !config.only_when_discharging || is_discharging(&device).await;
+
+        let next = match (active, phase) {
+            (false, DutyCyclePhase::Suspended) => None,
+            (false, _) => Some(DutyCyclePhase::Suspended),
+            (true, DutyCyclePhase::On) if now < phase_ends_at => None,
+            (true, DutyCyclePhase::On) => Some(DutyCyclePhase::Off),
+            (true, DutyCyclePhase::Off) if now < phase_ends_at => None,
+            (true, DutyCyclePhase::Off) => Some(DutyCyclePhase::On),
+            (true, DutyCyclePhase::Pending | DutyCyclePhase::Suspended) => Some(DutyCyclePhase::On),
+        };
+
+        if let Some(next) = next {
+            switch_output(&device, port, next != DutyCyclePhase::Off).await;
+
+            phase = next;
+            phase_ends_at = match next {
+                DutyCyclePhase::On => Instant::now() + on_duration,
+                DutyCyclePhase::Off => Instant::now() + off_duration,
+                _ => Instant::now(),
+            };
+
+            *progress.write().await = Progress {
+                phase,
+                phase_ends_at: matches!(phase, DutyCyclePhase::On | DutyCyclePhase::Off)
+                    .then_some(phase_ends_at),
+            };
+        }
+
+        let wait = match phase {
+            DutyCyclePhase::On | DutyCyclePhase::Off => phase_ends_at
+                .saturating_duration_since(Instant::now())
+                .min(POLL_INTERVAL),
+            _ => POLL_INTERVAL,
+        };
+        sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(on_minutes: u32, off_minutes: u32) -> DutyCycleConfig {
+        DutyCycleConfig {
+            on_minutes,
+            off_minutes,
+            only_when_discharging: false,
+        }
+    }
+
+    #[test]
+    fn validate_needs_at_least_one_minute_per_phase() {
+        assert!(config(0, 0).validate().is_err());
+        assert!(config(0, 5).validate().is_err());
+        assert!(config(5, 0).validate().is_err());
+        assert!(config(1, 1).validate().is_ok());
+        assert!(config(u32::MAX, u32::MAX).validate().is_ok());
+    }
+
+    fn manager(device: Arc<AnkerDevice>) -> DutyCycleManager {
+        let timers = Arc::new(OutputTimerManager::new(Arc::clone(&device)));
+        DutyCycleManager::new(device, timers)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn phases_alternate_on_schedule() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let cycles = manager(device);
+        let on = OutputPort::Ac.command(true).to_bytes();
+        let off = OutputPort::Ac.command(false).to_bytes();
+
+        let start = Instant::now();
+        cycles.start(OutputPort::Ac, config(1, 2)).await.unwrap();
+
+        // On at 0s, off at 60s, on again at 180s, off at 240s
+        for (at_secs, expected) in [
+            (30, vec![on.clone()]),
+            (90, vec![on.clone(), off.clone()]),
+            (170, vec![on.clone(), off.clone()]),
+            (200, vec![on.clone(), off.clone(), on.clone()]),
+            (250, vec![on.clone(), off.clone(), on.clone(), off.clone()]),
+        ] {
+            tokio::time::sleep_until(start + Duration::from_secs(at_secs)).await;
+            assert_eq!(*written.lock().unwrap(), expected, "at {}s", at_secs);
+        }
+
+        let status = cycles.status().await;
+        assert_eq!(status[0].phase, DutyCyclePhase::Off);
+        assert!(cycles.cancel(OutputPort::Ac).await);
+        assert!(cycles.status().await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn output_is_held_on_while_not_discharging() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let cycles = manager(device);
+        let config = DutyCycleConfig {
+            only_when_discharging: true,
+            ..config(1, 1)
+        };
+
+        cycles.start(OutputPort::Ac, config).await.unwrap();
+        sleep(Duration::from_secs(300)).await;
+
+        assert_eq!(
+            *written.lock().unwrap(),
+            vec![OutputPort::Ac.command(true).to_bytes()]
+        );
+        assert_eq!(cycles.status().await[0].phase, DutyCyclePhase::Suspended);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replaced_cycle_stops_switching() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let cycles = manager(device);
+        let on = OutputPort::Ac.command(true).to_bytes();
+
+        cycles.start(OutputPort::Ac, config(1, 1)).await.unwrap();
+        sleep(Duration::from_secs(30)).await;
+        cycles.start(OutputPort::Ac, config(5, 5)).await.unwrap();
+
+        // The first cycle would have switched off at 60s
+        sleep(Duration::from_secs(60)).await;
+        assert_eq!(*written.lock().unwrap(), vec![on.clone(), on]);
+        assert_eq!(cycles.status().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn starting_a_cycle_cancels_the_output_timer() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let timers = Arc::new(OutputTimerManager::new(Arc::clone(&device)));
+        let cycles = DutyCycleManager::new(device, Arc::clone(&timers));
+
+        timers
+            .start(
+                OutputPort::Ac,
+                crate::automation::OutputTimerRequest {
+                    seconds: Some(600),
+                    at: None,
+                },
+            )
+            .await
+            .unwrap();
+        // Wait for the device timer to be armed
+        while written.lock().unwrap().is_empty() {
+            sleep(Duration::from_millis(5)).await;
+        }
+
+        cycles.start(OutputPort::Ac, config(1, 1)).await.unwrap();
+        assert!(timers.status().await.is_empty());
+        assert!(written
+            .lock()
+            .unwrap()
+            .contains(&OutputPort::Ac.timer_command(0).to_bytes()));
+    }
+}
@@ -0,0 +1,138 @@
+//! Server-side automation for the switchable outputs.
+//! Tasks here outlive individual BLE connections and retry commands until the
+//! device is reachable again.
+
+pub mod duty_cycle;
+pub mod timer;
+
+pub use duty_cycle::{DutyCycleConfig, DutyCycleManager, DutyCyclePhase, DutyCycleStatus};
+pub use timer::{OutputTimerManager, OutputTimerRequest, OutputTimerStatus};
+
+use crate::ble::command::{
+    AcOutputCommand, AcTimerCommand, TwelveVoltOutputCommand, TwelveVoltTimerCommand,
+};
+use crate::ble::{AnkerCommand, AnkerDevice};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tracing::{debug, error, warn};
+
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum AutomationError {
+    #[error("Invalid value: {0}")]
+    InvalidValue(String),
+}
+
+/// Outputs that can be switched on and off by automation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum OutputPort {
+    Ac,
+    TwelveVolt,
+}
+
+impl OutputPort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputPort::Ac => "ac",
+            OutputPort::TwelveVolt => "twelve_volt",
+        }
+    }
+
+    /// Build the output on/off command for this port
+    pub fn command(&self, is_on: bool) -> AnkerCommand {
+        match self {
+            OutputPort::Ac => AnkerCommand::AcOutput(AcOutputCommand::new(is_on)),
+            OutputPort::TwelveVolt => {
+                AnkerCommand::TwelveVoltOutput(TwelveVoltOutputCommand::new(is_on))
+            }
+        }
+    }
+
+    /// Build the device auto-off timer command for this port
+    pub fn timer_command(&self, seconds: u16) -> AnkerCommand {
+        match self {
+            OutputPort::Ac => AnkerCommand::AcTimer(AcTimerCommand::new(seconds)),
+            OutputPort::TwelveVolt => {
+                AnkerCommand::TwelveVoltTimer(TwelveVoltTimerCommand::new(seconds))
+            }
+        }
+    }
+}
+
+/// Send a command, retrying until the device accepts the write
+pub(crate) async fn send_until_accepted(device: &AnkerDevice, cmd: AnkerCommand) {
+    send_until_accepted_with(device, || Some(cmd.clone())).await;
+}
+
+/// Send the command `next` builds, retrying until the device accepts the write.
+/// `next` runs before every attempt, so the command can account for the time
+/// spent retrying; returning None gives up. Returns whether a write was accepted.
+pub(crate) async fn send_until_accepted_with(
+    device: &AnkerDevice,
+    mut next: impl FnMut() -> Option<AnkerCommand>,
+) -> bool {
+    loop {
+        let Some(cmd) = next() else {
+            return false;
+        };
+        let cmd_type = cmd.command_type().as_str();
+
+        match device.send_command(cmd).await {
+            Ok(()) => {
+                debug!("{} accepted by device", cmd_type);
+                #[cfg(feature = "metrics")]
+                metrics::increment_command(cmd_type);
+                return true;
+            }
+            Err(e) => {
+                warn!("{} failed: {}, retrying in {:?}", cmd_type, e, RETRY_DELAY);
+                sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Switch an output, retrying until the device accepts the write
+pub(crate) async fn switch_output(device: &AnkerDevice, port: OutputPort, is_on: bool) {
+    send_until_accepted(device, port.command(is_on)).await;
+}
+
+/// Aborts the wrapped task when dropped, so aborting a supervisor also stops its child
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Spawn a long-running task and restart it if it panics.
+/// Aborting the returned handle stops the task for good.
+pub(crate) fn supervise<F, Fut>(name: String, task: F) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            let mut child = AbortOnDrop(tokio::spawn(task()));
+            match (&mut child.0).await {
+                Ok(()) => break,
+                Err(e) if e.is_panic() => {
+                    error!("{} task panicked, restarting in {:?}", name, RETRY_DELAY);
+                    sleep(RETRY_DELAY).await;
+                }
+                Err(_) => break,
+            }
+        }
+    })
+}
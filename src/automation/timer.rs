@@ -0,0 +1,410 @@
+//! Server-side output timers. Unlike `AcTimerCommand`/`TwelveVoltTimerCommand`
+//! these are not capped at 65535 seconds and can target a wall-clock time.
+//! The device timer is armed for the final stretch (again after every
+//! reconnect) and an explicit off command is sent when the timer is due, in
+//! case the device timer was lost.
+
+use crate::automation::{
+    send_until_accepted_with, supervise, switch_output, AutomationError, OutputPort,
+};
+use crate::ble::units::Seconds;
+use crate::ble::{AnkerDevice, ConnectionState};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// Longest timer the device itself can hold
+const DEVICE_TIMER_MAX: Duration = Duration::from_secs(u16::MAX as u64);
+/// Upper bound on a single sleep, so wall-clock adjustments are picked up
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OutputTimerRequest {
+    /// Turn the output off after this many seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seconds: Option<u64>,
+    /// Turn the output off at this wall-clock time (RFC 3339, e.g. "2026-10-18T22:00:00+02:00")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<String>,
+}
+
+impl OutputTimerRequest {
+    /// Resolve the request to the wall-clock time the output should turn off
+    pub fn off_at(&self) -> Result<SystemTime, AutomationError> {
+        let off_at = match (self.seconds, &self.at) {
+            (Some(seconds), None) => SystemTime::now()
+                .checked_add(Duration::from_secs(seconds))
+                .ok_or_else(|| {
+                    AutomationError::InvalidValue(format!("{} seconds is too long", seconds))
+                })?,
+            (None, Some(at)) => DateTime::parse_from_rfc3339(at)
+                .map_err(|e| {
+                    AutomationError::InvalidValue(format!("invalid time {:?}: {}", at, e))
+                })?
+                .with_timezone(&Utc)
+                .into(),
+            _ => {
+                return Err(AutomationError::InvalidValue(
+                    "exactly one of seconds or at must be given".to_string(),
+                ))
+            }
+        };
+
+        if off_at <= SystemTime::now() {
+            return Err(AutomationError::InvalidValue(
+                "timer must end in the future".to_string(),
+            ));
+        }
+        Ok(off_at)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OutputTimerStatus {
+    pub output: OutputPort,
+    /// Wall-clock time the output turns off (RFC 3339, UTC)
+    pub off_at: String,
+    pub remaining: Seconds,
+    /// Whether the device's own timer has been armed for the final stretch
+    pub device_timer_armed: bool,
+    /// Remaining time reported by the device itself. Only the 12V group has a
+    /// timer field in telemetry, so this is always absent for the AC output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_time_remaining: Option<Seconds>,
+}
+
+struct OutputTimerTask {
+    off_at: SystemTime,
+    device_timer_armed: Arc<RwLock<bool>>,
+    handle: JoinHandle<()>,
+}
+
+impl Drop for OutputTimerTask {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Owns the pending output timers, at most one per output
+pub struct OutputTimerManager {
+    device: Arc<AnkerDevice>,
+    timers: Mutex<HashMap<OutputPort, OutputTimerTask>>,
+}
+
+impl OutputTimerManager {
+    pub fn new(device: Arc<AnkerDevice>) -> Self {
+        Self {
+            device,
+            timers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start a timer on `port`, replacing any timer already pending there
+    pub async fn start(
+        &self,
+        port: OutputPort,
+        req: OutputTimerRequest,
+    ) -> Result<OutputTimerStatus, AutomationError> {
+        let off_at = req.off_at()?;
+
+        // Stop the old timer first so disarming it can't undo the new one
+        let mut timers = self.timers.lock().await;
+        if let Some(old) = timers.remove(&port) {
+            self.stop(port, old).await;
+        }
+
+        let device_timer_armed = Arc::new(RwLock::new(false));
+
+        let device = Arc::clone(&self.device);
+        let task_armed = Arc::clone(&device_timer_armed);
+        let handle = supervise(format!("output timer ({})", port.as_str()), move || {
+            run(Arc::clone(&device), port, off_at, Arc::clone(&task_armed))
+        });
+
+        info!(
+            "Starting {} timer, off at {}",
+            port.as_str(),
+            format_time(off_at)
+        );
+
+        let task = OutputTimerTask {
+            off_at,
+            device_timer_armed,
+            handle,
+        };
+        let status = self.task_status(port, &task).await;
+        timers.insert(port, task);
+        Ok(status)
+    }
+
+    /// Cancel the timer on `port`, disarming the device timer if we armed it.
+    /// Returns false if no timer was pending.
+    pub async fn cancel(&self, port: OutputPort) -> bool {
+        let Some(task) = self.timers.lock().await.remove(&port) else {
+            return false;
+        };
+        let stopped = self.stop(port, task).await;
+        if stopped {
+            info!("Cancelled {} timer", port.as_str());
+        }
+        stopped
+    }
+
+    /// Abort a pending timer and disarm the device timer if it armed it.
+    /// Returns false if the timer had already elapsed.
+    async fn stop(&self, port: OutputPort, mut task: OutputTimerTask) -> bool {
+        if task.handle.is_finished() {
+            return false;
+        }
+        task.handle.abort();
+        let _ = (&mut task.handle).await;
+
+        if *task.device_timer_armed.read().await {
+            if let Err(e) = self.device.send_command(port.timer_command(0)).await {
+                warn!("Failed to disarm {} device timer: {}", port.as_str(), e);
+            }
+        }
+        true
+    }
+
+    pub async fn status(&self) -> Vec<OutputTimerStatus> {
+        let mut timers = self.timers.lock().await;
+        timers.retain(|_, task| !task.handle.is_finished());
+
+        let mut statuses = Vec::with_capacity(timers.len());
+        for (port, task) in timers.iter() {
+            statuses.push(self.task_status(*port, task).await);
+        }
+        statuses.sort_by_key(|s| s.output.as_str());
+        statuses
+    }
+
+    async fn task_status(&self, port: OutputPort, task: &OutputTimerTask) -> OutputTimerStatus {
+        let device_time_remaining = self
+            .device
+            .state()
+            .read()
+            .await
+            .last_telemetry
+            .as_ref()
+            .and_then(|t| match port {
+                // AC telemetry has no timer field
+                OutputPort::Ac => None,
+                OutputPort::TwelveVolt => t.twelve_volt.first()?.time_remaining,
+            });
+
+        OutputTimerStatus {
+            output: port,
+            off_at: format_time(task.off_at),
+            remaining: remaining(task.off_at).into(),
+            device_timer_armed: *task.device_timer_armed.read().await,
+            device_time_remaining,
+        }
+    }
+}
+
+fn remaining(off_at: SystemTime) -> Duration {
+    off_at
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO)
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Arm the device timer for whatever is left until `off_at`, recomputed on
+/// every attempt so retries don't overshoot. Returns false if `off_at` passed first.
+async fn arm_device_timer(device: &AnkerDevice, port: OutputPort, off_at: SystemTime) -> bool {
+    send_until_accepted_with(device, || {
+        let left = remaining(off_at);
+        (!left.is_zero()).then(|| port.timer_command(left.as_secs().max(1) as u16))
+    })
+    .await
+}
+
+async fn run(
+    device: Arc<AnkerDevice>,
+    port: OutputPort,
+    off_at: SystemTime,
+    device_timer_armed: Arc<RwLock<bool>>,
+) {
+    let mut states = device.subscribe_state();
+    let mut armed = false;
+
+    loop {
+        let left = remaining(off_at);
+        if left.is_zero() {
+            break;
+        }
+
+        // Hand the final stretch to the device so it still turns off if we lose the link
+        if !armed && left <= DEVICE_TIMER_MAX {
+            // Any reconnect from here on may have lost the timer we're about to arm
+            states.borrow_and_update();
+            armed = arm_device_timer(&device, port, off_at).await;
+            if armed {
+                *device_timer_armed.write().await = true;
+            }
+            continue;
+        }
+
+        let until_next_step = if armed { left } else { left - DEVICE_TIMER_MAX };
+        tokio::select! {
+            _ = sleep(until_next_step.min(POLL_INTERVAL)) => {}
+            Ok(()) = states.changed() => {
+                // The device may have restarted or dropped the timer while we were away
+                if *states.borrow_and_update() == ConnectionState::Connected && armed {
+                    info!("Reconnected, re-arming {} device timer", port.as_str());
+                    armed = false;
+                }
+            }
+        }
+    }
+
+    switch_output(&device, port, false).await;
+    info!("{} timer elapsed, output turned off", port.as_str());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ble::command::{CommandType, RawCommand};
+
+    type Written = Arc<std::sync::Mutex<Vec<Vec<u8>>>>;
+
+    fn request(seconds: Option<u64>, at: Option<&str>) -> OutputTimerRequest {
+        OutputTimerRequest {
+            seconds,
+            at: at.map(str::to_string),
+        }
+    }
+
+    /// Seconds of each AC timer command written so far
+    fn ac_timers(written: &Written) -> Vec<u16> {
+        written
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|frame| RawCommand::from_bytes(frame).unwrap())
+            .filter(|raw| raw.known_type() == Some(CommandType::AcTimer))
+            .map(|raw| u16::from_le_bytes([raw.parameters[1], raw.parameters[2]]))
+            .collect()
+    }
+
+    async fn wait_for_timers(written: &Written, count: usize) -> Vec<u16> {
+        for _ in 0..200 {
+            let timers = ac_timers(written);
+            if timers.len() >= count {
+                return timers;
+            }
+            sleep(Duration::from_millis(5)).await;
+        }
+        panic!(
+            "expected {} timer commands, got {:?}",
+            count,
+            ac_timers(written)
+        );
+    }
+
+    #[test]
+    fn off_at_needs_exactly_one_of_seconds_or_at() {
+        assert!(request(None, None).off_at().is_err());
+        assert!(request(Some(60), Some("2099-01-01T00:00:00Z"))
+            .off_at()
+            .is_err());
+
+        let off_at = request(Some(60), None).off_at().unwrap();
+        assert!(remaining(off_at) > Duration::from_secs(58));
+        assert!(request(None, Some("2099-01-01T00:00:00Z")).off_at().is_ok());
+    }
+
+    #[test]
+    fn off_at_rejects_invalid_times() {
+        assert!(request(Some(0), None).off_at().is_err());
+        assert!(request(None, Some("2000-01-01T00:00:00Z"))
+            .off_at()
+            .is_err());
+        assert!(request(None, Some("tomorrow")).off_at().is_err());
+        assert!(matches!(
+            request(Some(u64::MAX), None).off_at(),
+            Err(AutomationError::InvalidValue(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn replacing_a_timer_disarms_the_old_device_timer() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let timers = OutputTimerManager::new(device);
+
+        timers
+            .start(OutputPort::Ac, request(Some(600), None))
+            .await
+            .unwrap();
+        wait_for_timers(&written, 1).await;
+
+        timers
+            .start(OutputPort::Ac, request(Some(1200), None))
+            .await
+            .unwrap();
+        let sent = wait_for_timers(&written, 3).await;
+
+        assert!((599..=600).contains(&sent[0]));
+        assert_eq!(sent[1], 0);
+        assert!((1199..=1200).contains(&sent[2]));
+        assert_eq!(timers.status().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_timer_disarms_the_device_timer() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let timers = OutputTimerManager::new(device);
+
+        timers
+            .start(OutputPort::Ac, request(Some(600), None))
+            .await
+            .unwrap();
+        wait_for_timers(&written, 1).await;
+
+        assert!(timers.cancel(OutputPort::Ac).await);
+        assert_eq!(ac_timers(&written).last(), Some(&0));
+        assert!(timers.status().await.is_empty());
+        assert!(!timers.cancel(OutputPort::Ac).await);
+    }
+
+    #[tokio::test]
+    async fn device_timer_is_re_armed_after_a_reconnect() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let timers = OutputTimerManager::new(Arc::clone(&device));
+
+        timers
+            .start(OutputPort::Ac, request(Some(600), None))
+            .await
+            .unwrap();
+        wait_for_timers(&written, 1).await;
+
+        device.disconnect().await.unwrap();
+        device.fake_reconnect(&written).await;
+        let sent = wait_for_timers(&written, 2).await;
+
+        assert!((599..=600).contains(&sent[1]));
+        assert_eq!(timers.status().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn device_timer_is_not_armed_once_due() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let past = SystemTime::now() - Duration::from_secs(1);
+
+        assert!(!arm_device_timer(&device, OutputPort::Ac, past).await);
+        assert!(written.lock().unwrap().is_empty());
+    }
+}
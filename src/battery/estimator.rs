@@ -0,0 +1,302 @@
+//! Time-to-empty / time-to-full estimation from recent telemetry history.
+//!
+//! Net power is smoothed with an exponential moving average; the confidence
+//! band comes from the spread of net power over the history window plus the
+//! 1% resolution of `total_battery_percentage`.
+
+use crate::battery::PACK_CAPACITY_WH;
+use crate::ble::Telemetry;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How much history feeds the confidence band
+const HISTORY_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Time constant of the net power moving average, in seconds
+const SMOOTHING_TIME_CONSTANT: f64 = 120.0;
+/// Net power below this is treated as idle (no estimate)
+const IDLE_THRESHOLD_WATTS: f64 = 5.0;
+/// Half a percent either way, from the integer battery percentage
+const PERCENTAGE_UNCERTAINTY: f64 = 0.5;
+/// Telemetry arrives about once a second; no sample for this long means the
+/// device is gone and the last estimate no longer applies
+const MAX_SAMPLE_AGE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TimeEstimate {
+    /// Best estimate in seconds
+    pub seconds: u64,
+    /// Lower bound of the confidence band in seconds
+    pub min_seconds: u64,
+    /// Upper bound of the confidence band in seconds (absent when net power may be near zero)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BatteryEstimate {
+    /// Smoothed net battery power in watts (positive = charging)
+    pub net_watts: f64,
+    /// Standard deviation of net power over the history window
+    pub net_watts_stddev: f64,
+    pub stored_wh: f64,
+    pub capacity_wh: f64,
+    pub battery_packs: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_empty: Option<TimeEstimate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_full: Option<TimeEstimate>,
+    /// Telemetry samples in the history window
+    pub samples: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    net_watts: f64,
+    percentage: u8,
+    packs: u8,
+}
+
+/// Keeps a short telemetry history and derives runtime estimates from it
+#[derive(Debug, Default)]
+pub struct BatteryEstimator {
+    samples: VecDeque<Sample>,
+    smoothed_net_watts: Option<f64>,
+}
+
+impl BatteryEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, telemetry: &Telemetry) {
+        self.update_at(telemetry, Instant::now());
+    }
+
+    pub fn update_at(&mut self, telemetry: &Telemetry, at: Instant) {
+        let net_watts =
+            telemetry.total_input_watts.0 as f64 - telemetry.total_output_watts.0 as f64;
+
+        let smoothed = match (self.smoothed_net_watts, self.samples.back()) {
+            (Some(previous), Some(last)) => {
+                let dt = at.saturating_duration_since(last.at).as_secs_f64();
+                let alpha = 1.0 - (-dt / SMOOTHING_TIME_CONSTANT).exp();
+                previous + alpha * (net_watts - previous)
+            }
+            _ => net_watts,
+        };
+        self.smoothed_net_watts = Some(smoothed);
+
+        self.samples.push_back(Sample {
+            at,
+            net_watts,
+            percentage: telemetry.total_battery_percentage.0,
+            packs: telemetry.battery_packs(),
+        });
+        while self
+            .samples
+            .front()
+            .is_some_and(|s| at.saturating_duration_since(s.at) > HISTORY_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn estimate(&self) -> Option<BatteryEstimate> {
+        self.estimate_at(Instant::now())
+    }
+
+    /// Estimate as of `now`; `None` once the latest sample is too old
+    pub fn estimate_at(&self, now: Instant) -> Option<BatteryEstimate> {
+        let latest = self.samples.back()?;
+        if now.saturating_duration_since(latest.at) > MAX_SAMPLE_AGE {
+            return None;
+        }
+        let net_watts = self.smoothed_net_watts?;
+
+        let count = self.samples.len() as f64;
+        let mean = self.samples.iter().map(|s| s.net_watts).sum::<f64>() / count;
+        let variance = self
+            .samples
+            .iter()
+            .map(|s| (s.net_watts - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        let stddev = variance.sqrt();
+
+        let capacity_wh = PACK_CAPACITY_WH * latest.packs as f64;
+        let stored_wh = capacity_wh * latest.percentage as f64 / 100.0;
+        let energy_uncertainty_wh = capacity_wh * PERCENTAGE_UNCERTAINTY / 100.0;
+
+        let time_to_empty = (net_watts < -IDLE_THRESHOLD_WATTS)
+            .then(|| time_estimate(stored_wh, -net_watts, stddev, energy_uncertainty_wh));
+        let time_to_full = (net_watts > IDLE_THRESHOLD_WATTS).then(|| {
+            time_estimate(
+                capacity_wh - stored_wh,
+                net_watts,
+                stddev,
+                energy_uncertainty_wh,
+            )
+        });
+
+        Some(BatteryEstimate {
+            net_watts,
+            net_watts_stddev: stddev,
+            stored_wh,
+            capacity_wh,
+            battery_packs: latest.packs,
+            time_to_empty,
+            time_to_full,
+            samples: self.samples.len(),
+        })
+    }
+}
+
+fn time_estimate(energy_wh: f64, watts: f64, stddev: f64, uncertainty_wh: f64) -> TimeEstimate {
+    let seconds = |wh: f64, w: f64| (wh.max(0.0) / w * 3600.0).round() as u64;
+    let slowest_watts = watts - stddev;
+
+    TimeEstimate {
+        seconds: seconds(energy_wh, watts),
+        min_seconds: seconds(energy_wh - uncertainty_wh, watts + stddev),
+        max_seconds: (slowest_watts > IDLE_THRESHOLD_WATTS)
+            .then(|| seconds(energy_wh + uncertainty_wh, slowest_watts)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::sample_telemetry;
+
+    /// Feed `(seconds, percentage, input, output)` samples starting at `start`
+    fn feed(estimator: &mut BatteryEstimator, start: Instant, samples: &[(u64, u8, u16, u16)]) {
+        for &(seconds, percentage, input, output) in samples {
+            estimator.update_at(
+                &sample_telemetry(percentage, input, output),
+                start + Duration::from_secs(seconds),
+            );
+        }
+    }
+
+    #[test]
+    fn no_estimate_without_telemetry() {
+        assert!(BatteryEstimator::new().estimate().is_none());
+    }
+
+    #[test]
+    fn stale_telemetry_has_no_estimate() {
+        let mut estimator = BatteryEstimator::new();
+        let start = Instant::now();
+        feed(&mut estimator, start, &[(0, 50, 0, 500)]);
+
+        assert!(estimator.estimate_at(start + MAX_SAMPLE_AGE).is_some());
+        assert!(estimator
+            .estimate_at(start + MAX_SAMPLE_AGE + Duration::from_secs(1))
+            .is_none());
+
+        // Fresh telemetry brings it back
+        feed(&mut estimator, start, &[(120, 50, 0, 500)]);
+        assert!(estimator
+            .estimate_at(start + Duration::from_secs(121))
+            .is_some());
+    }
+
+    #[test]
+    fn net_power_is_smoothed_over_time() {
+        let mut estimator = BatteryEstimator::new();
+        let start = Instant::now();
+
+        feed(&mut estimator, start, &[(0, 50, 0, 500)]);
+        assert_eq!(estimator.estimate().unwrap().net_watts, -500.0);
+
+        // One time constant after a step to zero, 1/e of the old value remains
+        feed(&mut estimator, start, &[(120, 50, 0, 0)]);
+        let net_watts = estimator.estimate().unwrap().net_watts;
+        assert!((net_watts + 500.0 / std::f64::consts::E).abs() < 0.01);
+    }
+
+    #[test]
+    fn discharging_estimates_time_to_empty() {
+        let mut estimator = BatteryEstimator::new();
+        feed(
+            &mut estimator,
+            Instant::now(),
+            &[(0, 50, 0, 500), (10, 50, 0, 500), (20, 50, 0, 500)],
+        );
+
+        let estimate = estimator.estimate().unwrap();
+        assert_eq!(estimate.stored_wh, 1024.0);
+        assert!(estimate.time_to_full.is_none());
+        let empty = estimate.time_to_empty.unwrap();
+        // 1024 Wh at 500 W, ±0.5% of capacity
+        assert_eq!(empty.seconds, 7373);
+        assert_eq!(empty.min_seconds, 7299);
+        assert_eq!(empty.max_seconds, Some(7447));
+    }
+
+    #[test]
+    fn charging_estimates_time_to_full() {
+        let mut estimator = BatteryEstimator::new();
+        feed(
+            &mut estimator,
+            Instant::now(),
+            &[(0, 50, 1100, 100), (10, 50, 1100, 100)],
+        );
+
+        let estimate = estimator.estimate().unwrap();
+        assert!(estimate.time_to_empty.is_none());
+        // 1024 Wh left to fill at 1000 W
+        assert_eq!(estimate.time_to_full.unwrap().seconds, 3686);
+    }
+
+    #[test]
+    fn idle_has_no_time_estimates() {
+        let mut estimator = BatteryEstimator::new();
+        feed(
+            &mut estimator,
+            Instant::now(),
+            &[(0, 80, 0, 3), (10, 80, 3, 0)],
+        );
+
+        let estimate = estimator.estimate().unwrap();
+        assert!(estimate.time_to_empty.is_none());
+        assert!(estimate.time_to_full.is_none());
+    }
+
+    #[test]
+    fn noisy_power_widens_the_band() {
+        let mut estimator = BatteryEstimator::new();
+        feed(
+            &mut estimator,
+            Instant::now(),
+            &[
+                (0, 50, 0, 300),
+                (10, 50, 0, 700),
+                (20, 50, 0, 300),
+                (30, 50, 0, 700),
+            ],
+        );
+
+        let estimate = estimator.estimate().unwrap();
+        assert_eq!(estimate.net_watts_stddev, 200.0);
+        let empty = estimate.time_to_empty.unwrap();
+        assert!(empty.min_seconds < empty.seconds);
+        assert!(empty.max_seconds.unwrap() > empty.seconds);
+    }
+
+    #[test]
+    fn old_samples_leave_the_history_window() {
+        let mut estimator = BatteryEstimator::new();
+        feed(
+            &mut estimator,
+            Instant::now(),
+            &[(0, 50, 0, 500), (300, 50, 0, 500), (700, 50, 0, 500)],
+        );
+
+        assert_eq!(estimator.estimate().unwrap().samples, 2);
+    }
+}
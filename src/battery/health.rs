@@ -0,0 +1,381 @@
+//! Long-term battery wear tracking: equivalent full cycles, depth-of-discharge
+//! histogram, time at high state-of-charge and time above temperature
+//! thresholds, per pack. Counters are persisted as JSON so they survive restarts.
+
+use crate::ble::telemetry::Battery;
+use crate::ble::Telemetry;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// State-of-charge at or above this counts as "high"
+pub const HIGH_SOC_PERCENT: u8 = 90;
+/// Temperatures (°C) we accumulate time above
+pub const TEMPERATURE_THRESHOLDS: [u8; 2] = [35, 45];
+/// Depth-of-discharge histogram bucket width in percent
+pub const DOD_BUCKET_PERCENT: u8 = 10;
+/// Rise in percentage that ends a discharge session (filters jitter)
+const SESSION_HYSTERESIS: u8 = 2;
+/// Longer gaps between frames (e.g. disconnected) are not counted as time
+const MAX_SAMPLE_GAP: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TemperatureExposure {
+    pub threshold_celsius: u8,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PackHealth {
+    /// Total percentage points discharged / 100
+    pub equivalent_full_cycles: f64,
+    /// Total percentage points discharged
+    pub discharged_percent_total: u64,
+    /// Completed discharge sessions by depth, in 10% buckets (0-10%, 10-20%, ... 90-100%)
+    pub depth_of_discharge_histogram: Vec<u64>,
+    /// Time spent at or above the high state-of-charge threshold
+    pub high_soc_seconds: f64,
+    pub temperature_exposure: Vec<TemperatureExposure>,
+    /// Highest percentage of the discharge session in progress
+    pub session_peak_percent: Option<u8>,
+    /// Lowest percentage of the discharge session in progress
+    pub session_trough_percent: Option<u8>,
+    /// Previous sample; the time until the next one is credited to its state
+    #[serde(skip)]
+    last_battery: Option<Battery>,
+}
+
+impl Default for PackHealth {
+    fn default() -> Self {
+        Self {
+            equivalent_full_cycles: 0.0,
+            discharged_percent_total: 0,
+            depth_of_discharge_histogram: vec![0; (100 / DOD_BUCKET_PERCENT) as usize],
+            high_soc_seconds: 0.0,
+            temperature_exposure: TEMPERATURE_THRESHOLDS
+                .iter()
+                .map(|&threshold_celsius| TemperatureExposure {
+                    threshold_celsius,
+                    seconds: 0.0,
+                })
+                .collect(),
+            session_peak_percent: None,
+            session_trough_percent: None,
+            last_battery: None,
+        }
+    }
+}
+
+impl PackHealth {
+    /// Fill in histogram buckets and temperature thresholds missing from a
+    /// file written with other settings
+    fn normalize(&mut self) {
+        self.depth_of_discharge_histogram
+            .resize((100 / DOD_BUCKET_PERCENT) as usize, 0);
+        for threshold_celsius in TEMPERATURE_THRESHOLDS {
+            if !self
+                .temperature_exposure
+                .iter()
+                .any(|e| e.threshold_celsius == threshold_celsius)
+            {
+                self.temperature_exposure.push(TemperatureExposure {
+                    threshold_celsius,
+                    seconds: 0.0,
+                });
+            }
+        }
+    }
+
+    /// Take a new sample; `elapsed` is the time since the previous one, if
+    /// short enough to count
+    fn update(&mut self, battery: &Battery, elapsed: Option<Duration>) {
+        let percentage = battery.percentage.0.min(100);
+
+        if let Some(last) = self.last_battery.take() {
+            let last_percentage = last.percentage.0.min(100);
+            if percentage < last_percentage {
+                self.discharged_percent_total += (last_percentage - percentage) as u64;
+                self.equivalent_full_cycles = self.discharged_percent_total as f64 / 100.0;
+            }
+            // The pack was in its previous state for the interval that just ended
+            if let Some(elapsed) = elapsed {
+                self.credit(&last, elapsed);
+            }
+        }
+        self.last_battery = Some(battery.clone());
+        self.track_session(percentage);
+    }
+
+    fn credit(&mut self, battery: &Battery, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if battery.percentage.0 >= HIGH_SOC_PERCENT {
+            self.high_soc_seconds += seconds;
+        }
+        for exposure in &mut self.temperature_exposure {
+            if battery.temperature.0 > exposure.threshold_celsius {
+                exposure.seconds += seconds;
+            }
+        }
+    }
+
+    /// A session runs from a peak down to a trough; it completes once the
+    /// percentage climbs back above the trough.
+    fn track_session(&mut self, percentage: u8) {
+        let (peak, trough) = match (self.session_peak_percent, self.session_trough_percent) {
+            (Some(peak), Some(trough)) => (peak, trough),
+            _ => (percentage, percentage),
+        };
+
+        let (peak, trough) = if percentage < trough {
+            (peak, percentage)
+        } else if peak == trough {
+            (percentage, percentage)
+        } else if percentage >= trough + SESSION_HYSTERESIS {
+            let depth = peak - trough;
+            let bucket = (depth / DOD_BUCKET_PERCENT).min(100 / DOD_BUCKET_PERCENT - 1);
+            self.depth_of_discharge_histogram[bucket as usize] += 1;
+            (percentage, percentage)
+        } else {
+            (peak, trough)
+        };
+
+        self.session_peak_percent = Some(peak);
+        self.session_trough_percent = Some(trough);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BatteryHealth {
+    /// Unix time (seconds) when tracking started
+    pub tracking_since: u64,
+    pub internal_battery: PackHealth,
+    pub external_battery: PackHealth,
+}
+
+impl Default for BatteryHealth {
+    fn default() -> Self {
+        Self {
+            tracking_since: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            internal_battery: PackHealth::default(),
+            external_battery: PackHealth::default(),
+        }
+    }
+}
+
+/// Accumulates `BatteryHealth` from telemetry and persists it
+pub struct BatteryHealthTracker {
+    path: Option<PathBuf>,
+    health: BatteryHealth,
+    last_update: Option<Instant>,
+    dirty: bool,
+}
+
+impl BatteryHealthTracker {
+    /// Tracker that is never persisted
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            health: BatteryHealth::default(),
+            last_update: None,
+            dirty: false,
+        }
+    }
+
+    /// Load counters from `path`, starting fresh if it doesn't exist or can't be parsed
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let health = match std::fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<BatteryHealth>(&bytes) {
+                Ok(mut health) => {
+                    info!("Loaded battery health from {}", path.display());
+                    health.internal_battery.normalize();
+                    health.external_battery.normalize();
+                    health
+                }
+                Err(e) => {
+                    warn!(
+                        "Ignoring unreadable battery health file {}: {}",
+                        path.display(),
+                        e
+                    );
+                    BatteryHealth::default()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BatteryHealth::default(),
+            Err(e) => {
+                warn!(
+                    "Failed to read battery health file {}: {}",
+                    path.display(),
+                    e
+                );
+                BatteryHealth::default()
+            }
+        };
+
+        Self {
+            path: Some(path),
+            health,
+            last_update: None,
+            dirty: false,
+        }
+    }
+
+    pub fn update(&mut self, telemetry: &Telemetry) {
+        let now = Instant::now();
+        let elapsed = self
+            .last_update
+            .map(|last| now.saturating_duration_since(last))
+            .filter(|elapsed| *elapsed <= MAX_SAMPLE_GAP);
+        self.last_update = Some(now);
+
+        self.health
+            .internal_battery
+            .update(&telemetry.internal_battery, elapsed);
+        match &telemetry.external_battery {
+            Some(external) => self.health.external_battery.update(external, elapsed),
+            // Don't credit the time it was detached to its last state
+            None => self.health.external_battery.last_battery = None,
+        }
+        self.dirty = true;
+    }
+
+    pub fn health(&self) -> &BatteryHealth {
+        &self.health
+    }
+
+    /// Write counters to disk if anything changed since the last save
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let json = serde_json::to_vec_pretty(&self.health)?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::sample_telemetry;
+    use crate::ble::units::{Celsius, Percentage};
+
+    fn battery(percentage: u8, temperature: u8) -> Battery {
+        Battery {
+            temperature: Celsius(temperature),
+            percentage: Percentage(percentage),
+        }
+    }
+
+    /// Feed percentages one second apart
+    fn feed(pack: &mut PackHealth, percentages: &[u8]) {
+        for &percentage in percentages {
+            pack.update(&battery(percentage, 25), Some(Duration::from_secs(1)));
+        }
+    }
+
+    #[test]
+    fn only_discharge_counts_toward_cycles() {
+        let mut pack = PackHealth::default();
+        feed(&mut pack, &[100, 80, 90, 60, 100, 50]);
+
+        assert_eq!(pack.discharged_percent_total, 100);
+        assert_eq!(pack.equivalent_full_cycles, 1.0);
+    }
+
+    #[test]
+    fn session_completes_once_charge_climbs_past_hysteresis() {
+        let mut pack = PackHealth::default();
+        feed(&mut pack, &[90, 80, 70, 71]);
+        assert_eq!(pack.session_peak_percent, Some(90));
+        assert_eq!(pack.session_trough_percent, Some(70));
+        assert_eq!(pack.depth_of_discharge_histogram.iter().sum::<u64>(), 0);
+
+        feed(&mut pack, &[72]);
+        // 20% deep, so the 20-30% bucket
+        assert_eq!(pack.depth_of_discharge_histogram[2], 1);
+        assert_eq!(pack.session_peak_percent, Some(72));
+        assert_eq!(pack.session_trough_percent, Some(72));
+    }
+
+    #[test]
+    fn full_discharge_lands_in_the_last_bucket() {
+        let mut pack = PackHealth::default();
+        feed(&mut pack, &[100, 50, 0, 5]);
+
+        assert_eq!(pack.depth_of_discharge_histogram[9], 1);
+        assert_eq!(pack.depth_of_discharge_histogram.iter().sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn time_at_high_charge_and_temperature_accumulates() {
+        let mut pack = PackHealth::default();
+        // Each interval counts toward the sample it started from
+        pack.update(&battery(95, 40), Some(Duration::from_secs(30)));
+        pack.update(&battery(89, 50), Some(Duration::from_secs(10)));
+        pack.update(&battery(95, 30), Some(Duration::from_secs(5)));
+        // Too long a gap to count
+        pack.update(&battery(95, 50), None);
+
+        assert_eq!(pack.high_soc_seconds, 10.0);
+        assert_eq!(pack.temperature_exposure[0].seconds, 15.0);
+        assert_eq!(pack.temperature_exposure[1].seconds, 5.0);
+    }
+
+    #[test]
+    fn tracker_follows_the_expansion_pack_only_while_attached() {
+        let mut tracker = BatteryHealthTracker::in_memory();
+        let mut telemetry = sample_telemetry(80, 0, 500);
+        telemetry.external_battery = Some(battery(60, 25));
+        tracker.update(&telemetry);
+        telemetry.internal_battery.percentage = Percentage(70);
+        telemetry.external_battery = Some(battery(40, 25));
+        tracker.update(&telemetry);
+        telemetry.external_battery = None;
+        tracker.update(&telemetry);
+
+        let health = tracker.health();
+        assert_eq!(health.internal_battery.discharged_percent_total, 10);
+        assert_eq!(health.external_battery.discharged_percent_total, 20);
+    }
+
+    #[test]
+    fn load_fills_in_missing_histogram_buckets() {
+        let path =
+            std::env::temp_dir().join(format!("anker-battery-health-{}.json", std::process::id()));
+        let mut health = BatteryHealth::default();
+        health.internal_battery.depth_of_discharge_histogram = vec![3, 1];
+        health.internal_battery.temperature_exposure.clear();
+        std::fs::write(&path, serde_json::to_vec(&health).unwrap()).unwrap();
+
+        let mut tracker = BatteryHealthTracker::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        for percentage in [100, 0, 5] {
+            tracker.update(&sample_telemetry(percentage, 0, 500));
+        }
+
+        let pack = &tracker.health().internal_battery;
+        assert_eq!(
+            pack.depth_of_discharge_histogram,
+            [3, 1, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(
+            pack.temperature_exposure.len(),
+            TEMPERATURE_THRESHOLDS.len()
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Battery analytics derived from the telemetry stream.
+
+pub mod estimator;
+pub mod health;
+
+pub use estimator::{BatteryEstimate, BatteryEstimator, TimeEstimate};
+pub use health::{BatteryHealth, BatteryHealthTracker, PackHealth, TemperatureExposure};
+
+/// Usable capacity of one F2000 battery pack (internal or BP2000 expansion)
+pub const PACK_CAPACITY_WH: f64 = 2048.0;
+
+/// Discharging (or charging) telemetry with only the internal pack, at `percentage`
+#[cfg(test)]
+pub(crate) fn sample_telemetry(
+    percentage: u8,
+    input_watts: u16,
+    output_watts: u16,
+) -> crate::ble::Telemetry {
+    use crate::ble::telemetry::{Battery, BatteryState, Output, Telemetry};
+    use crate::ble::units::{Celsius, Percentage, Seconds, Watts};
+
+    let off = Output {
+        is_on: false,
+        watts: Watts(0),
+        time_remaining: None,
+    };
+    Telemetry {
+        battery_remaining: Seconds(0),
+        ac_outlet: Output {
+            is_on: output_watts > 0,
+            watts: Watts(output_watts),
+            time_remaining: None,
+        },
+        twelve_volt: vec![off.clone(); 2],
+        usb_c: vec![off.clone(); 3],
+        usb_a: vec![off; 2],
+        total_output_watts: Watts(output_watts),
+        ac_input_watts: Watts(input_watts),
+        solar_input_watts: Watts(0),
+        total_input_watts: Watts(input_watts),
+        internal_battery: Battery {
+            temperature: Celsius(25),
+            percentage: Percentage(percentage),
+        },
+        external_battery: None,
+        battery_state: if input_watts > output_watts {
+            BatteryState::Charging
+        } else {
+            BatteryState::Discharging
+        },
+        total_battery_percentage: Percentage(percentage),
+        device_serial: "AZV1F2000A000000".to_string(),
+    }
+}
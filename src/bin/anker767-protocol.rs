@@ -0,0 +1,386 @@
+//! Protocol research tools for the Anker PowerHouse 767.
+
+use anker_767_ble_webserver::ble::btsnoop::{self, AttFrame};
+use anker_767_ble_webserver::ble::capture::{
+    from_hex, read_capture, to_hex, CaptureRecord, CaptureWriter, Direction,
+};
+use anker_767_ble_webserver::ble::command::{AnkerCommand, RawCommand};
+use anker_767_ble_webserver::ble::explorer::ProtocolExplorer;
+use anker_767_ble_webserver::ble::framing::FrameDecoder;
+use anker_767_ble_webserver::ble::model;
+use anker_767_ble_webserver::ble::probe;
+use anker_767_ble_webserver::ble::telemetry::NotificationPacket;
+use anker_767_ble_webserver::client::AnkerClient;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Parser)]
+#[command(
+    name = "anker767-protocol",
+    about = "Protocol research tools for the Anker PowerHouse 767"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Decode Anker app traffic from an Android btsnoop_hci.log
+    Btsnoop {
+        /// Path to btsnoop_hci.log
+        file: PathBuf,
+        /// ATT value handle of the 0x7777 write characteristic, if discovery isn't in the log
+        #[arg(long, value_parser = parse_u16)]
+        write_handle: Option<u16>,
+        /// ATT value handle of the 0x8888 notify characteristic, if discovery isn't in the log
+        #[arg(long, value_parser = parse_u16)]
+        notify_handle: Option<u16>,
+        /// Also write the extracted frames as a capture file (for ANKER_REPLAY_FILE)
+        #[arg(long)]
+        capture: Option<PathBuf>,
+        /// Only print frames the current parser can't understand
+        #[arg(long)]
+        unknown_only: bool,
+    },
+    /// Show which unmapped telemetry bytes change, and which commands changed them
+    Explore {
+        /// Capture file (ANKER_CAPTURE_FILE output) or btsnoop_hci.log
+        file: PathBuf,
+        /// Only list the N most active bytes
+        #[arg(long, default_value_t = 32)]
+        top: usize,
+    },
+    /// Connect to the device, send a raw command and print the notifications that follow
+    Send {
+        /// Command type byte (decimal or 0x-prefixed hex)
+        #[arg(value_parser = parse_u8)]
+        command_type: u8,
+        /// Parameter bytes as hex
+        #[arg(default_value = "")]
+        parameters: String,
+        /// How long to listen after sending, in milliseconds
+        #[arg(long, default_value_t = 2000)]
+        window_ms: u64,
+        /// Also print telemetry frames received in the window
+        #[arg(long)]
+        include_telemetry: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Commands::Btsnoop {
+            file,
+            write_handle,
+            notify_handle,
+            capture,
+            unknown_only,
+        } => run_btsnoop(file, write_handle, notify_handle, capture, unknown_only),
+        Commands::Explore { file, top } => run_explore(file, top),
+        Commands::Send {
+            command_type,
+            parameters,
+            window_ms,
+            include_telemetry,
+        } => run_send(command_type, &parameters, window_ms, include_telemetry),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_btsnoop(
+    file: PathBuf,
+    write_handle: Option<u16>,
+    notify_handle: Option<u16>,
+    capture: Option<PathBuf>,
+    unknown_only: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read(&file)?;
+    let mut extractor = btsnoop::extract(&data)?;
+
+    if write_handle.is_some() {
+        extractor.write_handle = write_handle;
+    }
+    if notify_handle.is_some() {
+        extractor.notify_handle = notify_handle;
+    }
+    println!(
+        "write handle: {}, notify handle: {}",
+        format_handle(extractor.write_handle),
+        format_handle(extractor.notify_handle)
+    );
+
+    let frames = extractor.anker_frames();
+    let writer = capture.as_ref().map(CaptureWriter::create).transpose()?;
+
+    // Notifications may be fragmented; decode whole frames, as `explore` does
+    let mut decoder = FrameDecoder::new();
+    let mut unknown = 0;
+    for frame in &frames {
+        if let Some(writer) = &writer {
+            writer.write(&frame.into())?;
+        }
+
+        let decoded = match frame.direction {
+            Direction::Tx => vec![(frame.clone(), decode(frame))],
+            Direction::Rx => {
+                decoder.push(&frame.value);
+                std::iter::from_fn(|| decoder.next_frame())
+                    .map(|result| match result {
+                        Ok(value) => {
+                            let whole = AttFrame {
+                                value,
+                                ..frame.clone()
+                            };
+                            let decoded = decode(&whole);
+                            (whole, decoded)
+                        }
+                        Err(e) => (frame.clone(), Err(format!("dropped: {}", e))),
+                    })
+                    .collect()
+            }
+        };
+
+        for (frame, decoded) in decoded {
+            if decoded.is_err() {
+                unknown += 1;
+            } else if unknown_only {
+                continue;
+            }
+            print_frame(&frame, decoded);
+        }
+    }
+    if decoder.buffered() > 0 {
+        unknown += 1;
+        println!(
+            "?? {} bytes of an incomplete frame at the end",
+            decoder.buffered()
+        );
+    }
+
+    let writes = frames
+        .iter()
+        .filter(|f| f.direction == Direction::Tx)
+        .count();
+    println!(
+        "\n{} writes, {} notifications, {} not understood",
+        writes,
+        frames.len() - writes,
+        unknown
+    );
+    if let Some(path) = capture {
+        println!("capture written to {}", path.display());
+    }
+    Ok(())
+}
+
+fn run_explore(file: PathBuf, top: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read(&file)?;
+    let records: Vec<CaptureRecord> = if data.starts_with(b"btsnoop\0") {
+        let frames = btsnoop::extract(&data)?.anker_frames();
+        frames.iter().map(CaptureRecord::from).collect()
+    } else {
+        read_capture(&file)?
+    };
+
+    // Notifications may be fragmented; explore whole frames
+    let mut explorer = ProtocolExplorer::new();
+    let mut decoder = FrameDecoder::new();
+    for record in &records {
+        match record.direction {
+            Direction::Tx => explorer.observe(record),
+            Direction::Rx => {
+                decoder.push(&record.data);
+                while let Some(result) = decoder.next_frame() {
+                    if let Ok(frame) = result {
+                        explorer.observe(&CaptureRecord {
+                            data: frame,
+                            ..record.clone()
+                        });
+                    }
+                }
+            }
+        }
+    }
+    let report = explorer.report(model::default_model());
+
+    println!(
+        "{} telemetry frames, {} commands\n",
+        report.frames, report.commands
+    );
+    println!("offset  changes  distinct  min   max   last");
+    for byte in report.changing_unmapped_bytes.iter().take(top) {
+        println!(
+            "{:>6}  {:>7}  {:>8}  0x{:02x}  0x{:02x}  0x{:02x}",
+            byte.offset, byte.changes, byte.distinct_values, byte.min, byte.max, byte.last
+        );
+    }
+    if report.changing_unmapped_bytes.is_empty() {
+        println!("    (no unmapped byte changed)");
+    }
+
+    println!();
+    for effect in &report.command_effects {
+        let changes: Vec<String> = effect
+            .changes
+            .iter()
+            .map(|c| format!("[{}] {:02x}->{:02x}", c.offset, c.before, c.after))
+            .collect();
+        println!(
+            "{} {} params={}: {}",
+            format_time(effect.timestamp_ms),
+            effect.command,
+            effect.parameters,
+            if changes.is_empty() {
+                "no unmapped changes".to_string()
+            } else {
+                changes.join(" ")
+            }
+        );
+    }
+    Ok(())
+}
+
+fn run_send(
+    command_type: u8,
+    parameters: &str,
+    window_ms: u64,
+    include_telemetry: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let parameters = from_hex(parameters).ok_or("parameters must be hex bytes")?;
+    let command = RawCommand::new(command_type, parameters)?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let client = AnkerClient::new();
+
+        println!("waiting for the device...");
+        tokio::time::timeout(CONNECT_TIMEOUT, client.wait_connected())
+            .await
+            .map_err(|_| "timed out waiting for the device")??;
+
+        let result = probe::probe(
+            client.device(),
+            &command,
+            Duration::from_millis(window_ms),
+            include_telemetry,
+        )
+        .await?;
+
+        println!("TX {}", result.sent);
+        for frame in &result.frames {
+            let ack = frame
+                .acknowledged_type
+                .map(|t| format!(" 0x{:02x}", t))
+                .unwrap_or_default();
+            println!(
+                "RX +{}ms {:?}{}\n    {}",
+                frame.elapsed_ms, frame.kind, ack, frame.data
+            );
+        }
+        if result.frames.is_empty() {
+            println!("no notifications within {}ms", window_ms);
+        }
+        Ok(())
+    })
+}
+
+/// Decode a frame with the crate's parsers; Err describes why it isn't understood
+fn decode(frame: &AttFrame) -> Result<String, String> {
+    match frame.direction {
+        Direction::Tx => {
+            let command = RawCommand::from_bytes(&frame.value).map_err(|e| e.to_string())?;
+            let params = to_hex(&command.parameters);
+            match command.known_type() {
+                Some(command_type) => match AnkerCommand::from_bytes(&frame.value) {
+                    Ok(decoded) => Ok(format!("{:?}", decoded)),
+                    Err(e) => Err(format!(
+                        "{} with unexpected params={}: {}",
+                        command_type.as_str(),
+                        params,
+                        e
+                    )),
+                },
+                None => Err(format!(
+                    "unknown command type 0x{:02x} params={}",
+                    command.command_type, params
+                )),
+            }
+        }
+        Direction::Rx => match NotificationPacket::from_bytes(&frame.value) {
+            Ok(NotificationPacket::Telemetry(t)) => Ok(format!(
+                "telemetry battery={} in={} out={} state={:?}",
+                t.total_battery_percentage,
+                t.total_input_watts,
+                t.total_output_watts,
+                t.battery_state
+            )),
+            Ok(NotificationPacket::StateAck(ack)) => Ok(format!("state_ack {:?}", ack)),
+            Ok(NotificationPacket::CommandAck(ack)) => {
+                Ok(format!("command_ack {}", ack.command_type.as_str()))
+            }
+            Err(e) => Err(e.to_string()),
+        },
+    }
+}
+
+fn print_frame(frame: &AttFrame, decoded: Result<String, String>) {
+    let (marker, description) = match decoded {
+        Ok(description) => ("  ", description),
+        Err(reason) => ("??", reason),
+    };
+    println!(
+        "{} {} 0x{:04x} {} {}\n    {}",
+        format_time(frame.timestamp_ms),
+        match frame.direction {
+            Direction::Tx => "TX",
+            Direction::Rx => "RX",
+        },
+        frame.handle,
+        marker,
+        description,
+        to_hex(&frame.value)
+    );
+}
+
+fn format_time(timestamp_ms: u64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(timestamp_ms as i64)
+        .map(|t| t.format("%H:%M:%S%.3f").to_string())
+        .unwrap_or_else(|| timestamp_ms.to_string())
+}
+
+fn format_handle(handle: Option<u16>) -> String {
+    handle
+        .map(|h| format!("0x{:04x}", h))
+        .unwrap_or_else(|| "not in log".to_string())
+}
+
+fn parse_u8(s: &str) -> Result<u8, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| e.to_string())
+}
+
+fn parse_u16(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| e.to_string())
+}
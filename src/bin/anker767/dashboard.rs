@@ -0,0 +1,670 @@
+//! Terminal dashboard: battery, ports, inputs and recent events, with keys to
+//! switch outputs. Reads from the server (polling plus the event stream) or
+//! straight from the device over BLE.
+
+use anker_767_ble_webserver::api::client::ApiClient;
+use anker_767_ble_webserver::ble::telemetry::{BatteryState, LedState, Output, Telemetry};
+use anker_767_ble_webserver::ble::units::Percentage;
+use anker_767_ble_webserver::ble::{ConnectionState, DeviceEvent, DeviceSettingsReport};
+use anker_767_ble_webserver::client::AnkerClient;
+use futures::StreamExt;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, LineGauge, List, ListItem, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const EVENTS_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_EVENTS: usize = 50;
+
+/// Where the dashboard gets its data and sends commands
+pub enum Source {
+    Api(ApiClient),
+    Ble(AnkerClient),
+}
+
+enum Update {
+    Telemetry(Box<Telemetry>),
+    Settings(Box<DeviceSettingsReport>),
+    Connection {
+        state: String,
+        model: Option<String>,
+    },
+    Event(String),
+    Key(KeyEvent),
+}
+
+/// A change requested from the keyboard
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Ac(bool),
+    TwelveVolt(bool),
+    PowerSave(bool),
+    Led(LedState),
+}
+
+impl Action {
+    fn describe(&self) -> String {
+        match self {
+            Action::Ac(is_on) => format!("AC {}", on_off(*is_on)),
+            Action::TwelveVolt(is_on) => format!("12V {}", on_off(*is_on)),
+            Action::PowerSave(is_on) => format!("power save {}", on_off(*is_on)),
+            Action::Led(state) => format!("LED {}", led_name(*state)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Dashboard {
+    title: String,
+    connection: String,
+    model: Option<String>,
+    telemetry: Option<Telemetry>,
+    /// Device-reported settings merged with the last set values
+    settings: Option<DeviceSettingsReport>,
+    /// Newest first
+    events: VecDeque<String>,
+}
+
+/// Run until the user quits
+pub async fn run(source: Arc<Source>, title: String) -> std::io::Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let feeds = spawn_feeds(&source, &tx);
+    spawn_key_reader(tx.clone());
+
+    let mut dashboard = Dashboard {
+        title,
+        connection: "connecting".to_string(),
+        ..Dashboard::default()
+    };
+    let mut terminal = ratatui::init();
+    let result = async {
+        draw(&mut terminal, &dashboard)?;
+        while let Some(update) = rx.recv().await {
+            match update {
+                Update::Telemetry(telemetry) => dashboard.telemetry = Some(*telemetry),
+                Update::Settings(settings) => dashboard.settings = Some(*settings),
+                Update::Connection { state, model } => {
+                    if state != dashboard.connection {
+                        dashboard.push_event(state.clone());
+                    }
+                    dashboard.connection = state;
+                    dashboard.model = model.or(dashboard.model.take());
+                }
+                Update::Event(message) => dashboard.push_event(message),
+                Update::Key(key) if is_quit(&key) => break,
+                Update::Key(key) => match dashboard.action_for(key.code) {
+                    Some(Ok(action)) => spawn_action(&source, action, &tx),
+                    Some(Err(message)) => dashboard.push_event(message),
+                    None => continue,
+                },
+            }
+            draw(&mut terminal, &dashboard)?;
+        }
+        Ok(())
+    }
+    .await;
+    ratatui::restore();
+
+    for feed in feeds {
+        feed.abort();
+    }
+    result
+}
+
+fn draw(terminal: &mut DefaultTerminal, dashboard: &Dashboard) -> std::io::Result<()> {
+    terminal.draw(|frame| dashboard.render(frame))?;
+    Ok(())
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => true,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+/// Tasks that turn the source's data into updates
+fn spawn_feeds(
+    source: &Arc<Source>,
+    tx: &mpsc::UnboundedSender<Update>,
+) -> Vec<tokio::task::JoinHandle<()>> {
+    match source.as_ref() {
+        Source::Api(client) => vec![
+            tokio::spawn(poll_api(client.clone(), tx.clone())),
+            tokio::spawn(forward_api_events(client.clone(), tx.clone())),
+        ],
+        Source::Ble(client) => {
+            let device = Arc::clone(client.device());
+            let settings_source = Arc::clone(source);
+            let mut states = device.subscribe_state();
+            let mut telemetry = device.subscribe_telemetry();
+            let mut events = device.subscribe_events();
+            let (tx_state, tx_telemetry, tx_events) = (tx.clone(), tx.clone(), tx.clone());
+
+            vec![
+                tokio::spawn(async move {
+                    loop {
+                        let state = *states.borrow_and_update();
+                        let model = device.state().read().await.model;
+                        let _ = tx_state.send(Update::Connection {
+                            state: state.as_str().to_string(),
+                            model: model.map(|m| m.model.to_string()),
+                        });
+                        if states.changed().await.is_err() {
+                            return;
+                        }
+                    }
+                }),
+                tokio::spawn(async move {
+                    loop {
+                        match telemetry.recv().await {
+                            Ok(t) => {
+                                let _ = tx_telemetry.send(Update::Telemetry(Box::new(t)));
+                                send_settings(&settings_source, &tx_telemetry).await;
+                            }
+                            Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => return,
+                        }
+                    }
+                }),
+                tokio::spawn(async move {
+                    loop {
+                        match events.recv().await {
+                            Ok(event) => {
+                                let _ = tx_events.send(Update::Event(describe_event(&event)));
+                            }
+                            Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => return,
+                        }
+                    }
+                }),
+            ]
+        }
+    }
+}
+
+async fn poll_api(client: ApiClient, tx: mpsc::UnboundedSender<Update>) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let status = match client.status().await {
+            Ok(status) => status,
+            Err(e) => {
+                let state = if e.is_connect() {
+                    "server unreachable".to_string()
+                } else {
+                    format!("server error: {}", e)
+                };
+                let _ = tx.send(Update::Connection { state, model: None });
+                continue;
+            }
+        };
+        let connected = status.connected;
+        let _ = tx.send(Update::Connection {
+            state: status.state,
+            model: status.model,
+        });
+        if connected {
+            if let Ok(versioned) = client.telemetry().await {
+                let _ = tx.send(Update::Telemetry(Box::new(versioned.telemetry)));
+            }
+            if let Ok(settings) = client.device_state().await {
+                let _ = tx.send(Update::Settings(Box::new(settings)));
+            }
+        }
+    }
+}
+
+/// Follow the server's event stream, reconnecting when it drops
+async fn forward_api_events(client: ApiClient, tx: mpsc::UnboundedSender<Update>) {
+    loop {
+        if let Ok(events) = client.events().await {
+            let mut events = Box::pin(events);
+            while let Some(event) = events.next().await {
+                match event {
+                    Ok(event) => {
+                        let _ = tx.send(Update::Event(describe_event(&event)));
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Update::Event(format!("event stream: {}", e)));
+                    }
+                }
+            }
+        }
+        tokio::time::sleep(EVENTS_RETRY_DELAY).await;
+    }
+}
+
+/// Read keys on a thread of their own; crossterm's `read` blocks
+fn spawn_key_reader(tx: mpsc::UnboundedSender<Update>) {
+    std::thread::spawn(move || loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                if tx.send(Update::Key(key)).is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(_) => return,
+        }
+    });
+}
+
+fn spawn_action(source: &Arc<Source>, action: Action, tx: &mpsc::UnboundedSender<Update>) {
+    let source = Arc::clone(source);
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let result = match source.as_ref() {
+            Source::Api(client) => match action {
+                Action::Ac(is_on) => client.set_ac_output(is_on).await,
+                Action::TwelveVolt(is_on) => client.set_twelve_volt_output(is_on).await,
+                Action::PowerSave(is_on) => client.set_power_save(is_on).await,
+                Action::Led(state) => client.set_led(state.into()).await,
+            }
+            .map_err(|e| e.to_string()),
+            Source::Ble(client) => match action {
+                Action::Ac(is_on) => client.set_ac_output(is_on).await,
+                Action::TwelveVolt(is_on) => client.set_twelve_volt_output(is_on).await,
+                Action::PowerSave(is_on) => client.set_power_save(is_on).await,
+                Action::Led(state) => client.set_led(state).await,
+            }
+            .map_err(|e| e.to_string()),
+        };
+        let message = match result {
+            Ok(()) => action.describe(),
+            Err(e) => format!("{} failed: {}", action.describe(), e),
+        };
+        let _ = tx.send(Update::Event(message));
+        // Pick up the new last-set value right away, e.g. for the next LED press
+        send_settings(&source, &tx).await;
+    });
+}
+
+/// Fetch the merged settings report from the source
+async fn send_settings(source: &Source, tx: &mpsc::UnboundedSender<Update>) {
+    let settings = match source {
+        Source::Api(client) => match client.device_state().await {
+            Ok(settings) => settings,
+            Err(_) => return,
+        },
+        Source::Ble(client) => client.settings().await,
+    };
+    let _ = tx.send(Update::Settings(Box::new(settings)));
+}
+
+fn describe_event(event: &DeviceEvent) -> String {
+    match event {
+        DeviceEvent::ExpansionBatteryAttached => "expansion battery attached".to_string(),
+        DeviceEvent::ExpansionBatteryDetached => "expansion battery detached".to_string(),
+    }
+}
+
+impl Dashboard {
+    fn push_event(&mut self, message: String) {
+        let time = chrono::Local::now().format("%H:%M:%S");
+        self.events.push_front(format!("{}  {}", time, message));
+        self.events.truncate(MAX_EVENTS);
+    }
+
+    /// The change a key asks for, based on the last settings report. `Err`
+    /// explains why it can't be sent yet.
+    fn action_for(&self, key: KeyCode) -> Option<Result<Action, String>> {
+        let KeyCode::Char(c) = key else {
+            return None;
+        };
+        let settings = self.settings.as_ref();
+        let current = |name: &str, value: Option<Option<bool>>| {
+            value.flatten().ok_or_else(|| match settings {
+                Some(_) => format!("{} state unknown", name),
+                None => "no settings yet".to_string(),
+            })
+        };
+        let action = match c {
+            'a' => current("AC", settings.map(|s| s.ac_output.value)).map(|on| Action::Ac(!on)),
+            't' => current("12V", settings.map(|s| s.twelve_volt_output.value))
+                .map(|on| Action::TwelveVolt(!on)),
+            'p' => current("power save", settings.map(|s| s.power_save.value))
+                .map(|on| Action::PowerSave(!on)),
+            'l' => {
+                let level = settings.and_then(|s| s.led_level.value);
+                Ok(Action::Led(next_led(level.map(LedState::from_byte))))
+            }
+            's' => Ok(Action::Led(LedState::Sos)),
+            _ => return None,
+        };
+        Some(action)
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let battery_height = match &self.telemetry {
+            Some(t) if t.external_battery.is_some() => 6,
+            _ => 5,
+        };
+        let [header, battery, middle, events, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(battery_height),
+            Constraint::Min(11),
+            Constraint::Min(4),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.render_header(frame, header);
+        match &self.telemetry {
+            Some(t) => {
+                let [outputs, side] =
+                    Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                        .areas(middle);
+                let [inputs, settings] =
+                    Layout::vertical([Constraint::Length(5), Constraint::Min(5)]).areas(side);
+                render_battery(frame, battery, t);
+                render_outputs(frame, outputs, t);
+                render_inputs(frame, inputs, t);
+                render_settings(frame, settings, self.settings.as_ref());
+            }
+            None => {
+                let waiting = Paragraph::new("waiting for telemetry...")
+                    .block(Block::bordered().title(" Battery "));
+                frame.render_widget(waiting, battery);
+            }
+        }
+        self.render_events(frame, events);
+
+        let keys = Line::from(vec![
+            key("a"),
+            " AC  ".into(),
+            key("t"),
+            " 12V  ".into(),
+            key("p"),
+            " power save  ".into(),
+            key("l"),
+            " LED level  ".into(),
+            key("s"),
+            " SOS  ".into(),
+            key("q"),
+            " quit".into(),
+        ]);
+        frame.render_widget(Paragraph::new(keys), help);
+    }
+
+    fn render_header(&self, frame: &mut Frame, area: Rect) {
+        let connected = self.connection == ConnectionState::Connected.as_str();
+        let state_style = if connected {
+            Style::new().fg(Color::Green)
+        } else {
+            Style::new().fg(Color::Yellow)
+        };
+        let model = self
+            .model
+            .as_deref()
+            .map(|m| format!("PowerHouse {}", m))
+            .unwrap_or_else(|| "PowerHouse".to_string());
+        let header = Line::from(vec![
+            Span::styled(model, Style::new().add_modifier(Modifier::BOLD)),
+            "  ".into(),
+            Span::styled(self.connection.clone(), state_style),
+            "  ".into(),
+            Span::styled(self.title.clone(), Style::new().fg(Color::DarkGray)),
+        ]);
+        frame.render_widget(Paragraph::new(header), area);
+    }
+
+    fn render_events(&self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .events
+            .iter()
+            .map(|e| ListItem::new(e.as_str()))
+            .collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" Events ")),
+            area,
+        );
+    }
+}
+
+fn render_battery(frame: &mut Frame, area: Rect, t: &Telemetry) {
+    let block = Block::bordered().title(" Battery ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let mut gauges = vec![("Total", t.total_battery_percentage, None)];
+    gauges.push((
+        "Internal",
+        t.internal_battery.percentage,
+        Some(t.internal_battery.temperature),
+    ));
+    if let Some(external) = &t.external_battery {
+        gauges.push(("Expansion", external.percentage, Some(external.temperature)));
+    }
+
+    let mut rows = vec![Constraint::Length(1); gauges.len() + 1];
+    rows.push(Constraint::Min(0));
+    let areas = Layout::vertical(rows).split(inner);
+
+    for (i, (name, percentage, temperature)) in gauges.into_iter().enumerate() {
+        let label = match temperature {
+            Some(temperature) => format!("{:<9} {:>4} {:>6}", name, percentage, temperature),
+            None => format!("{:<9} {:>4} {:>6}", name, percentage, ""),
+        };
+        let gauge = LineGauge::default()
+            .label(label)
+            .filled_style(Style::new().fg(charge_color(percentage)))
+            .ratio(f64::from(percentage.0.min(100)) / 100.0);
+        frame.render_widget(gauge, areas[i]);
+    }
+
+    let state = match t.battery_state {
+        BatteryState::Idle => "idle".to_string(),
+        BatteryState::Discharging => {
+            format!("discharging, {:.1} h left", t.battery_remaining.hours())
+        }
+        BatteryState::Charging => "charging".to_string(),
+        BatteryState::Unknown(value) => format!("unknown state {}", value),
+    };
+    frame.render_widget(Paragraph::new(state), areas[areas.len() - 2]);
+}
+
+fn render_outputs(frame: &mut Frame, area: Rect, t: &Telemetry) {
+    let mut rows = vec![port_row("AC".to_string(), &t.ac_outlet)];
+    for (name, ports) in [
+        ("12V", &t.twelve_volt),
+        ("USB-C", &t.usb_c),
+        ("USB-A", &t.usb_a),
+    ] {
+        for (i, port) in ports.iter().enumerate() {
+            rows.push(port_row(format!("{} {}", name, i + 1), port));
+        }
+    }
+    rows.push(
+        Row::new(vec![
+            "Total".to_string(),
+            String::new(),
+            t.total_output_watts.to_string(),
+        ])
+        .bold(),
+    );
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Length(4),
+            Constraint::Min(6),
+        ],
+    )
+    .block(Block::bordered().title(" Outputs "));
+    frame.render_widget(table, area);
+}
+
+fn port_row(name: String, port: &Output) -> Row<'static> {
+    let state = if port.is_on {
+        Span::styled("on", Style::new().fg(Color::Green))
+    } else {
+        Span::styled("off", Style::new().fg(Color::DarkGray))
+    };
+    Row::new(vec![
+        Line::from(name),
+        Line::from(state),
+        Line::from(port.watts.to_string()),
+    ])
+}
+
+fn render_inputs(frame: &mut Frame, area: Rect, t: &Telemetry) {
+    let rows = vec![
+        Row::new(vec!["AC".to_string(), t.ac_input_watts.to_string()]),
+        Row::new(vec!["Solar".to_string(), t.solar_input_watts.to_string()]),
+        Row::new(vec!["Total".to_string(), t.total_input_watts.to_string()]).bold(),
+    ];
+    let table = Table::new(rows, [Constraint::Length(8), Constraint::Min(6)])
+        .block(Block::bordered().title(" Inputs "));
+    frame.render_widget(table, area);
+}
+
+fn render_settings(frame: &mut Frame, area: Rect, settings: Option<&DeviceSettingsReport>) {
+    let unknown = || "?".to_string();
+    let rows = vec![
+        Row::new(vec![
+            "Power save".to_string(),
+            settings
+                .and_then(|s| s.power_save.value)
+                .map(|on| on_off(on).to_string())
+                .unwrap_or_else(unknown),
+        ]),
+        Row::new(vec![
+            "LED".to_string(),
+            settings
+                .and_then(|s| s.led_level.value)
+                .map(|level| led_name(LedState::from_byte(level)).to_string())
+                .unwrap_or_else(unknown),
+        ]),
+        Row::new(vec![
+            "Recharge".to_string(),
+            settings
+                .and_then(|s| s.recharge_power.value)
+                .map(|w| format!("{} W", w))
+                .unwrap_or_else(unknown),
+        ]),
+    ];
+    let table = Table::new(rows, [Constraint::Length(11), Constraint::Min(6)])
+        .block(Block::bordered().title(" Settings "));
+    frame.render_widget(table, area);
+}
+
+fn charge_color(percentage: Percentage) -> Color {
+    match percentage.0 {
+        0..=19 => Color::Red,
+        20..=49 => Color::Yellow,
+        _ => Color::Green,
+    }
+}
+
+/// Off, low, mid, high and back to off; SOS and unknown levels go to off
+fn next_led(current: Option<LedState>) -> LedState {
+    match current {
+        Some(LedState::Off) => LedState::Low,
+        Some(LedState::Low) => LedState::Mid,
+        Some(LedState::Mid) => LedState::High,
+        _ => LedState::Off,
+    }
+}
+
+fn led_name(state: LedState) -> &'static str {
+    match state {
+        LedState::Off => "off",
+        LedState::Low => "low",
+        LedState::Mid => "mid",
+        LedState::High => "high",
+        LedState::Sos => "SOS",
+        LedState::Unknown(_) => "unknown",
+    }
+}
+
+fn on_off(is_on: bool) -> &'static str {
+    if is_on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn key(name: &str) -> Span<'_> {
+    Span::styled(name, Style::new().add_modifier(Modifier::BOLD))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anker_767_ble_webserver::ble::{ReportedSetting, SettingSource};
+
+    /// Device-reported when known
+    fn setting<T: Copy>(value: Option<T>) -> ReportedSetting<T> {
+        ReportedSetting {
+            value,
+            source: match value {
+                Some(_) => SettingSource::Device,
+                None => SettingSource::Unknown,
+            },
+            device: value,
+            last_set: None,
+        }
+    }
+
+    fn settings(power_save: Option<bool>, led_level: Option<u8>) -> DeviceSettingsReport {
+        DeviceSettingsReport {
+            ac_output: setting(Some(true)),
+            twelve_volt_output: setting(Some(false)),
+            power_save: setting(power_save),
+            led_level: setting(led_level),
+            screen_brightness: setting(None),
+            screen_timeout: setting(None),
+            recharge_power: setting(None),
+            ac_timer: setting(None),
+            twelve_volt_timer: setting(None),
+        }
+    }
+
+    fn action(dashboard: &Dashboard, c: char) -> Result<String, String> {
+        dashboard
+            .action_for(KeyCode::Char(c))
+            .unwrap()
+            .map(|a| a.describe())
+    }
+
+    #[test]
+    fn keys_toggle_from_the_settings_report() {
+        let dashboard = Dashboard {
+            settings: Some(settings(Some(true), Some(LedState::Low.into()))),
+            ..Dashboard::default()
+        };
+        assert_eq!(action(&dashboard, 'a'), Ok("AC off".to_string()));
+        assert_eq!(action(&dashboard, 't'), Ok("12V on".to_string()));
+        assert_eq!(action(&dashboard, 'p'), Ok("power save off".to_string()));
+        assert_eq!(action(&dashboard, 'l'), Ok("LED mid".to_string()));
+        assert!(dashboard.action_for(KeyCode::Char('x')).is_none());
+    }
+
+    #[test]
+    fn keys_explain_missing_settings() {
+        let dashboard = Dashboard::default();
+        assert_eq!(action(&dashboard, 'p'), Err("no settings yet".to_string()));
+
+        let dashboard = Dashboard {
+            settings: Some(settings(None, None)),
+            ..Dashboard::default()
+        };
+        assert_eq!(
+            action(&dashboard, 'p'),
+            Err("power save state unknown".to_string())
+        );
+        assert_eq!(action(&dashboard, 'l'), Ok("LED off".to_string()));
+    }
+}
@@ -0,0 +1,731 @@
+//! Command-line client for a PowerHouse, through a running
+//! anker_767_ble_webserver or, with `--ble`, directly over Bluetooth.
+//!
+//! With the `tui` feature, `dashboard` shows live telemetry in the terminal.
+//!
+//! Exit codes: 0 on success, 1 when the server or device rejects the request
+//! (e.g. not connected, no ack), 2 on usage errors and 3 when the server or
+//! device can't be reached.
+
+#[cfg(feature = "tui")]
+mod dashboard;
+
+use anker_767_ble_webserver::api::client::{ApiClient, ApiClientError};
+use anker_767_ble_webserver::api::StatusResponse;
+use anker_767_ble_webserver::automation::{OutputPort, OutputTimerRequest, OutputTimerStatus};
+use anker_767_ble_webserver::ble::command::{
+    AnkerCommand, CommandError, LedCommand, PowerSaveCommand, RechargePowerCommand,
+    ScreenBrightnessCommand, ScreenTimeoutCommand,
+};
+use anker_767_ble_webserver::ble::telemetry::{
+    BatteryState, LedState, Output, Telemetry, VersionedTelemetry,
+};
+use anker_767_ble_webserver::ble::{
+    self, model, AnkerDevice, ConnectionState, DeviceError, DeviceSettingsReport, ReportedSetting,
+    SettingSource,
+};
+use anker_767_ble_webserver::client::{AnkerClient, ClientError};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::fmt::Display;
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::time::timeout;
+
+const EXIT_API_ERROR: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_UNREACHABLE: u8 = 3;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+const TELEMETRY_TIMEOUT: Duration = Duration::from_secs(10);
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest delay the device's own output timers accept
+const MAX_DEVICE_TIMER: u64 = u16::MAX as u64;
+
+#[derive(Parser)]
+#[command(
+    name = "anker767",
+    about = "Control an Anker PowerHouse through a running anker_767_ble_webserver"
+)]
+struct Cli {
+    /// Server address
+    #[arg(long, env = "ANKER_URL", default_value = "http://localhost:3000")]
+    url: String,
+    /// Print JSON instead of a table
+    #[arg(long, global = true)]
+    json: bool,
+    /// Talk to the PowerHouse over Bluetooth instead of through the server
+    #[arg(long, global = true)]
+    ble: bool,
+    /// With --ble, only connect to this model (e.g. 767)
+    #[arg(long, env = "ANKER_MODEL", global = true)]
+    model: Option<String>,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// List nearby Bluetooth devices, PowerHouses only unless --all
+    Scan {
+        /// How long to listen for advertisements
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        duration: Duration,
+        /// Also list devices that aren't a known PowerHouse
+        #[arg(long)]
+        all: bool,
+    },
+    /// Live dashboard with keys to switch outputs
+    #[cfg(feature = "tui")]
+    Dashboard,
+    /// Connection status and detected model
+    Status,
+    /// Current telemetry
+    Telemetry {
+        /// Keep printing telemetry until interrupted
+        #[arg(long)]
+        watch: bool,
+        /// Time between updates with --watch, e.g. 5s
+        #[arg(long, default_value = "2s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+    /// Current settings, device-reported where possible
+    Settings,
+    /// Switch the AC outlets
+    Ac { state: Switch },
+    /// Switch the 12V outputs
+    #[command(name = "12v", alias = "twelve-volt")]
+    TwelveVolt { state: Switch },
+    /// Switch power save mode
+    PowerSave { state: Switch },
+    /// Set the LED strip
+    Led { level: Led },
+    /// Set the screen brightness (0-3)
+    Brightness {
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=3))]
+        level: u8,
+    },
+    /// Set the recharge power in watts (200-1440)
+    Recharge {
+        #[arg(value_parser = clap::value_parser!(u16).range(200..=1440))]
+        watts: u16,
+    },
+    /// Set the screen timeout, e.g. 30s or 5m
+    ScreenTimeout {
+        #[arg(value_parser = parse_duration)]
+        timeout: Duration,
+    },
+    /// Turn an output off after a delay, or cancel with "off". Uses the
+    /// server's timer, or with --ble the device's own (at most 18h).
+    Timer {
+        output: Port,
+        /// Delay such as 2h, 90m or 1h30m, or "off" to cancel
+        duration: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Switch {
+    On,
+    Off,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Led {
+    Off,
+    Low,
+    Mid,
+    High,
+    Sos,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Port {
+    Ac,
+    #[value(name = "12v", alias = "twelve-volt")]
+    TwelveVolt,
+}
+
+impl From<Led> for LedState {
+    fn from(led: Led) -> Self {
+        match led {
+            Led::Off => LedState::Off,
+            Led::Low => LedState::Low,
+            Led::Mid => LedState::Mid,
+            Led::High => LedState::High,
+            Led::Sos => LedState::Sos,
+        }
+    }
+}
+
+impl From<Port> for OutputPort {
+    fn from(port: Port) -> Self {
+        match port {
+            Port::Ac => OutputPort::Ac,
+            Port::TwelveVolt => OutputPort::TwelveVolt,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum CliError {
+    Api(ApiClientError),
+    Device(ClientError),
+    Usage(String),
+    /// Nothing answered within a timeout
+    Timeout(&'static str),
+    #[cfg(feature = "tui")]
+    Terminal(std::io::Error),
+}
+
+impl From<ApiClientError> for CliError {
+    fn from(e: ApiClientError) -> Self {
+        CliError::Api(e)
+    }
+}
+
+impl From<ClientError> for CliError {
+    fn from(e: ClientError) -> Self {
+        CliError::Device(e)
+    }
+}
+
+impl From<DeviceError> for CliError {
+    fn from(e: DeviceError) -> Self {
+        CliError::Device(e.into())
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = runtime.block_on(async {
+        match cli.command {
+            Commands::Scan { duration, all } => run_scan(duration, all, cli.json).await,
+            #[cfg(feature = "tui")]
+            Commands::Dashboard => run_dashboard(&cli.url, cli.ble, cli.model.as_deref()).await,
+            command if cli.ble => run_ble(command, cli.model.as_deref(), cli.json).await,
+            command => run_api(&ApiClient::new(&cli.url), command, cli.json).await,
+        }
+    });
+
+    let (code, message) = match result {
+        Ok(()) => return ExitCode::SUCCESS,
+        Err(CliError::Usage(message)) => (EXIT_USAGE, message),
+        Err(CliError::Api(e)) if e.is_connect() => {
+            (EXIT_UNREACHABLE, format!("can't reach {}: {}", cli.url, e))
+        }
+        Err(CliError::Api(e)) => (EXIT_API_ERROR, e.to_string()),
+        Err(CliError::Device(ClientError::Device(
+            e @ (DeviceError::NotFound | DeviceError::Ble(_)),
+        ))) => (EXIT_UNREACHABLE, e.to_string()),
+        Err(CliError::Device(e)) => (EXIT_API_ERROR, e.to_string()),
+        Err(CliError::Timeout(message)) => (EXIT_UNREACHABLE, message.to_string()),
+        #[cfg(feature = "tui")]
+        Err(CliError::Terminal(e)) => (EXIT_API_ERROR, e.to_string()),
+    };
+    eprintln!("error: {}", message);
+    ExitCode::from(code)
+}
+
+async fn run_api(client: &ApiClient, command: Commands, json: bool) -> Result<(), CliError> {
+    match command {
+        Commands::Scan { .. } => unreachable!("handled in main"),
+        #[cfg(feature = "tui")]
+        Commands::Dashboard => unreachable!("handled in main"),
+        Commands::Status => print_status(&client.status().await?, json),
+        Commands::Telemetry { watch, interval } => loop {
+            print_telemetry(&client.telemetry().await?, json);
+            if !watch {
+                break;
+            }
+            tokio::time::sleep(interval).await;
+            if !json {
+                println!();
+            }
+        },
+        Commands::Settings => print_settings(&client.device_state().await?, json),
+        Commands::Ac { state } => {
+            client.set_ac_output(is_on(state)).await?;
+            print_ok(json);
+        }
+        Commands::TwelveVolt { state } => {
+            client.set_twelve_volt_output(is_on(state)).await?;
+            print_ok(json);
+        }
+        Commands::PowerSave { state } => {
+            client.set_power_save(is_on(state)).await?;
+            print_ok(json);
+        }
+        Commands::Led { level } => {
+            client.set_led(LedState::from(level).into()).await?;
+            print_ok(json);
+        }
+        Commands::Brightness { level } => {
+            client.set_screen_brightness(level).await?;
+            print_ok(json);
+        }
+        Commands::Recharge { watts } => {
+            client.set_recharge_power(watts).await?;
+            print_ok(json);
+        }
+        Commands::ScreenTimeout { timeout } => {
+            client
+                .set_screen_timeout(screen_timeout_seconds(timeout)?)
+                .await?;
+            print_ok(json);
+        }
+        Commands::Timer { output, duration } => {
+            let port = OutputPort::from(output);
+            match parse_timer(&duration)? {
+                None => {
+                    client.cancel_output_timer(port).await?;
+                    print_ok(json);
+                }
+                Some(delay) => {
+                    let req = OutputTimerRequest {
+                        seconds: Some(delay.as_secs()),
+                        at: None,
+                    };
+                    let status = client.start_output_timer(port, &req).await?;
+                    if json {
+                        print_json(&status);
+                    } else {
+                        print_timer(&status);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_scan(duration: Duration, all: bool, json: bool) -> Result<(), CliError> {
+    if !json {
+        eprintln!("scanning for {}s...", duration.as_secs());
+    }
+    let mut devices = ble::scan(duration).await?;
+    if !all {
+        devices.retain(|d| d.model.is_some());
+    }
+
+    if json {
+        print_json(&devices);
+    } else if devices.is_empty() {
+        println!("no PowerHouse found");
+    } else {
+        println!("{:<24} {:<18} {:>5}  MODEL", "NAME", "ADDRESS", "RSSI");
+        for device in &devices {
+            let rssi = device.rssi.map(|r| r.to_string()).unwrap_or_default();
+            println!(
+                "{:<24} {:<18} {:>5}  {}",
+                device.name,
+                device.address,
+                rssi,
+                device.model.unwrap_or("-")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// What to do once connected over BLE
+enum BleAction {
+    Status,
+    Telemetry { watch: bool, interval: Duration },
+    Settings,
+    Send(AnkerCommand),
+}
+
+/// Connect, run one command, then disconnect even if the command failed
+async fn run_ble(command: Commands, model: Option<&str>, json: bool) -> Result<(), CliError> {
+    // Validate everything before spending time on the connection
+    let action = ble_action(command)?;
+    let client = AnkerClient::with_device(ble_device(model)?);
+
+    if !json {
+        eprintln!("connecting...");
+    }
+    let connected = timeout(CONNECT_TIMEOUT, client.wait_connected()).await;
+    let result = match connected {
+        Ok(Ok(())) => run_ble_action(&client, action, json).await,
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err(CliError::Timeout("no PowerHouse found")),
+    };
+
+    let disconnected = client.disconnect().await;
+    result?;
+    Ok(disconnected?)
+}
+
+/// Device to connect to; `model` skips detection
+fn ble_device(model: Option<&str>) -> Result<AnkerDevice, CliError> {
+    let Some(name) = model else {
+        return Ok(AnkerDevice::new());
+    };
+    let layout = model::by_model(name).ok_or_else(|| {
+        let known: Vec<_> = model::MODELS.iter().map(|m| m.model).collect();
+        CliError::Usage(format!(
+            "unknown model {}, expected one of {}",
+            name,
+            known.join(", ")
+        ))
+    })?;
+    Ok(AnkerDevice::new().with_model(layout))
+}
+
+fn ble_action(command: Commands) -> Result<BleAction, CliError> {
+    let invalid = |e: CommandError| CliError::Usage(e.to_string());
+    let command = match command {
+        Commands::Scan { .. } => unreachable!("handled in main"),
+        #[cfg(feature = "tui")]
+        Commands::Dashboard => unreachable!("handled in main"),
+        Commands::Status => return Ok(BleAction::Status),
+        Commands::Telemetry { watch, interval } => {
+            return Ok(BleAction::Telemetry { watch, interval })
+        }
+        Commands::Settings => return Ok(BleAction::Settings),
+        Commands::Ac { state } => OutputPort::Ac.command(is_on(state)),
+        Commands::TwelveVolt { state } => OutputPort::TwelveVolt.command(is_on(state)),
+        Commands::PowerSave { state } => {
+            AnkerCommand::PowerSave(PowerSaveCommand::new(is_on(state)))
+        }
+        Commands::Led { level } => {
+            AnkerCommand::Led(LedCommand::new(LedState::from(level).into()).map_err(invalid)?)
+        }
+        Commands::Brightness { level } => {
+            AnkerCommand::ScreenBrightness(ScreenBrightnessCommand::new(level).map_err(invalid)?)
+        }
+        Commands::Recharge { watts } => {
+            AnkerCommand::RechargePower(RechargePowerCommand::new(watts).map_err(invalid)?)
+        }
+        Commands::ScreenTimeout { timeout } => {
+            AnkerCommand::ScreenTimeout(ScreenTimeoutCommand::new(screen_timeout_seconds(timeout)?))
+        }
+        Commands::Timer { output, duration } => {
+            let seconds = match parse_timer(&duration)? {
+                None => 0,
+                Some(delay) if delay.as_secs() <= MAX_DEVICE_TIMER => delay.as_secs() as u16,
+                Some(_) => {
+                    return Err(CliError::Usage(
+                        "the device timer is limited to 18h; use the server for longer timers"
+                            .to_string(),
+                    ))
+                }
+            };
+            OutputPort::from(output).timer_command(seconds)
+        }
+    };
+    Ok(BleAction::Send(command))
+}
+
+async fn run_ble_action(
+    client: &AnkerClient,
+    action: BleAction,
+    json: bool,
+) -> Result<(), CliError> {
+    match action {
+        BleAction::Status => {
+            let model = client.device().state().read().await.model;
+            let state = client.connection_state();
+            print_status(
+                &StatusResponse {
+                    connected: state == ConnectionState::Connected,
+                    state: state.as_str().to_string(),
+                    model: model.map(|m| m.model.to_string()),
+                },
+                json,
+            );
+        }
+        BleAction::Telemetry { watch, interval } => loop {
+            let telemetry = next_telemetry(client).await?;
+            print_telemetry(&VersionedTelemetry::from(telemetry), json);
+            if !watch {
+                break;
+            }
+            tokio::time::sleep(interval).await;
+            if !json {
+                println!();
+            }
+        },
+        BleAction::Settings => {
+            // Settings are only device-reported once telemetry has arrived
+            next_telemetry(client).await?;
+            print_settings(&client.settings().await, json);
+        }
+        BleAction::Send(command) => {
+            client.send_acked(command, ACK_TIMEOUT).await?;
+            print_ok(json);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+async fn run_dashboard(url: &str, ble: bool, model: Option<&str>) -> Result<(), CliError> {
+    use dashboard::Source;
+    use std::sync::Arc;
+
+    let (source, title) = if ble {
+        let client = AnkerClient::with_device(ble_device(model)?);
+        (Source::Ble(client), "Bluetooth".to_string())
+    } else {
+        (Source::Api(ApiClient::new(url)), url.to_string())
+    };
+    let source = Arc::new(source);
+    dashboard::run(Arc::clone(&source), title)
+        .await
+        .map_err(CliError::Terminal)?;
+
+    // Commands still in flight keep the client alive; it stops when they finish
+    if let Ok(Source::Ble(client)) = Arc::try_unwrap(source) {
+        client.disconnect().await?;
+    }
+    Ok(())
+}
+
+async fn next_telemetry(client: &AnkerClient) -> Result<Telemetry, CliError> {
+    timeout(TELEMETRY_TIMEOUT, client.next_telemetry())
+        .await
+        .map_err(|_| CliError::Timeout("no telemetry received"))?
+        .map_err(CliError::from)
+}
+
+fn screen_timeout_seconds(timeout: Duration) -> Result<u16, CliError> {
+    u16::try_from(timeout.as_secs())
+        .map_err(|_| CliError::Usage("screen timeout must be under 18h".to_string()))
+}
+
+/// `None` for "off", otherwise the delay
+fn parse_timer(duration: &str) -> Result<Option<Duration>, CliError> {
+    if duration == "off" {
+        return Ok(None);
+    }
+    parse_duration(duration).map(Some).map_err(CliError::Usage)
+}
+
+fn is_on(state: Switch) -> bool {
+    matches!(state, Switch::On)
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string(value).unwrap());
+}
+
+fn print_ok(json: bool) {
+    if json {
+        println!(r#"{{"success":true}}"#);
+    } else {
+        println!("ok");
+    }
+}
+
+fn row(name: &str, value: impl Display) {
+    println!("{:<15} {}", name, value);
+}
+
+fn print_status(status: &StatusResponse, json: bool) {
+    if json {
+        print_json(status);
+    } else {
+        row("connected", yes_no(status.connected));
+        row("state", &status.state);
+        row("model", status.model.as_deref().unwrap_or("unknown"));
+    }
+}
+
+fn print_settings(settings: &DeviceSettingsReport, json: bool) {
+    if json {
+        print_json(settings);
+        return;
+    }
+    row("ac", setting(&settings.ac_output, on_off));
+    row("12v", setting(&settings.twelve_volt_output, on_off));
+    row("power save", setting(&settings.power_save, on_off));
+    row("led", setting(&settings.led_level, |l| l));
+    row("brightness", setting(&settings.screen_brightness, |l| l));
+    row("screen timeout", setting(&settings.screen_timeout, seconds));
+    row("recharge", setting(&settings.recharge_power, watts));
+    row("ac timer", setting(&settings.ac_timer, seconds));
+    row("12v timer", setting(&settings.twelve_volt_timer, seconds));
+}
+
+fn print_telemetry(telemetry: &VersionedTelemetry, json: bool) {
+    if json {
+        print_json(telemetry);
+    } else {
+        print_telemetry_table(&telemetry.telemetry);
+    }
+}
+
+fn print_telemetry_table(t: &Telemetry) {
+    let battery_state = match t.battery_state {
+        BatteryState::Idle => "idle".to_string(),
+        BatteryState::Discharging => "discharging".to_string(),
+        BatteryState::Charging => "charging".to_string(),
+        BatteryState::Unknown(value) => format!("unknown ({})", value),
+    };
+    row(
+        "battery",
+        format!("{} {}", t.total_battery_percentage, battery_state),
+    );
+    row(
+        "internal",
+        format!(
+            "{} {}",
+            t.internal_battery.percentage, t.internal_battery.temperature
+        ),
+    );
+    if let Some(external) = &t.external_battery {
+        row(
+            "expansion",
+            format!("{} {}", external.percentage, external.temperature),
+        );
+    }
+    row("remaining", format!("{:.1} h", t.battery_remaining.hours()));
+    row(
+        "input",
+        format!(
+            "{} (AC {}, solar {})",
+            t.total_input_watts, t.ac_input_watts, t.solar_input_watts
+        ),
+    );
+    row("output", t.total_output_watts);
+    row("ac", ports(std::slice::from_ref(&t.ac_outlet)));
+    row("12v", ports(&t.twelve_volt));
+    row("usb-c", ports(&t.usb_c));
+    row("usb-a", ports(&t.usb_a));
+}
+
+fn ports(outputs: &[Output]) -> String {
+    outputs
+        .iter()
+        .map(|o| format!("{} {}", on_off(o.is_on), o.watts))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+fn print_timer(status: &OutputTimerStatus) {
+    row("output", status.output.as_str());
+    row("off at", &status.off_at);
+    row("remaining", seconds(status.remaining.0 as u64));
+}
+
+/// "on (device)", "800 W (last set)" or "unknown"
+fn setting<T: Copy, D: Display>(setting: &ReportedSetting<T>, format: impl Fn(T) -> D) -> String {
+    let Some(value) = setting.value else {
+        return "unknown".to_string();
+    };
+    let source = match setting.source {
+        SettingSource::Device => "device",
+        SettingSource::LastSet => "last set",
+        SettingSource::Unknown => "unknown",
+    };
+    format!("{} ({})", format(value), source)
+}
+
+fn on_off(is_on: bool) -> &'static str {
+    if is_on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn watts(watts: u16) -> String {
+    format!("{} W", watts)
+}
+
+fn seconds(seconds: impl Into<u64>) -> String {
+    let seconds = seconds.into();
+    match (seconds / 3600, seconds % 3600 / 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, _) => format!("{}h{:02}m", h, m),
+    }
+}
+
+/// Parse "90", "45s", "90m", "2h" or combinations like "1h30m"
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid duration {:?}, expected e.g. 30s, 90m, 2h or 1h30m",
+            s
+        )
+    };
+    if let Ok(seconds) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|v| total.checked_add(v))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || s.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_accepts_bare_seconds_and_units() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1h0m5s"), Ok(Duration::from_secs(3605)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_malformed_input() {
+        for input in ["", "h", "1h30", "m5", "1d", "-5s", "1.5h"] {
+            assert!(parse_duration(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parse_duration_rejects_overflow() {
+        assert!(parse_duration("99999999999999999999").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+        // Fits in a u64 as hours but not as seconds
+        assert!(parse_duration("5124095576030432h").is_err());
+        // Each part fits but the sum doesn't
+        assert!(parse_duration("5124095576030431h18446744073709551615s").is_err());
+    }
+}
@@ -0,0 +1,563 @@
+//! Android `btsnoop_hci.log` parsing.
+//!
+//! Extracts ATT writes to the 0x7777 characteristic and notifications from
+//! 0x8888 so captured Anker app traffic can be decoded with the same parser
+//! the server uses. Handles ACL fragmentation and discovers the characteristic
+//! value handles from GATT discovery when it's in the log.
+
+use crate::ble::capture::{CaptureRecord, Direction};
+use std::collections::HashMap;
+use thiserror::Error;
+
+const MAGIC: &[u8; 8] = b"btsnoop\0";
+const FILE_HEADER_LENGTH: usize = 16;
+const RECORD_HEADER_LENGTH: usize = 24;
+/// Microseconds between 0000-01-01 and the Unix epoch, as used by btsnoop timestamps
+const EPOCH_OFFSET_US: i64 = 0x00dc_ddb3_0f2f_8000;
+
+const DATALINK_HCI_UNENCAPSULATED: u32 = 1001;
+const DATALINK_HCI_UART: u32 = 1002;
+
+const H4_ACL: u8 = 0x02;
+const L2CAP_CID_ATT: u16 = 0x0004;
+
+const ATT_READ_BY_TYPE_RESPONSE: u8 = 0x09;
+const ATT_WRITE_REQUEST: u8 = 0x12;
+const ATT_HANDLE_VALUE_NOTIFICATION: u8 = 0x1b;
+const ATT_HANDLE_VALUE_INDICATION: u8 = 0x1d;
+const ATT_WRITE_COMMAND: u8 = 0x52;
+
+const WRITE_UUID16: u16 = 0x7777;
+const NOTIFY_UUID16: u16 = 0x8888;
+/// Bluetooth base UUID, little-endian, with the 16-bit slot (bytes 12..14) zeroed
+const BASE_UUID_LE: [u8; 16] = [
+    0xfb, 0x34, 0x9b, 0x5f, 0x80, 0x00, 0x00, 0x80, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[derive(Debug, Error)]
+pub enum BtsnoopError {
+    #[error("Not a btsnoop file")]
+    BadMagic,
+    #[error("Unsupported btsnoop datalink type {0}")]
+    UnsupportedDatalink(u32),
+    #[error("Truncated record at offset {0}")]
+    Truncated(usize),
+    #[error("Invalid timestamp in record at offset {0}")]
+    InvalidTimestamp(usize),
+}
+
+/// One HCI packet from the log
+#[derive(Debug, Clone)]
+pub struct HciRecord {
+    /// Unix time in milliseconds
+    pub timestamp_ms: u64,
+    /// Controller to host
+    pub received: bool,
+    /// ACL data payload (HCI header included), or None for commands/events
+    pub acl: Option<Vec<u8>>,
+}
+
+/// An ATT write or notification
+#[derive(Debug, Clone)]
+pub struct AttFrame {
+    pub timestamp_ms: u64,
+    /// `Tx` for writes from the phone, `Rx` for notifications from the device
+    pub direction: Direction,
+    pub handle: u16,
+    pub value: Vec<u8>,
+}
+
+impl From<&AttFrame> for CaptureRecord {
+    fn from(frame: &AttFrame) -> Self {
+        CaptureRecord {
+            timestamp_ms: frame.timestamp_ms,
+            direction: frame.direction,
+            data: frame.value.clone(),
+        }
+    }
+}
+
+/// Parse the btsnoop container into HCI records
+pub fn parse_records(data: &[u8]) -> Result<Vec<HciRecord>, BtsnoopError> {
+    if data.len() < FILE_HEADER_LENGTH || &data[..8] != MAGIC {
+        return Err(BtsnoopError::BadMagic);
+    }
+    let datalink = u32::from_be_bytes([data[12], data[13], data[14], data[15]]);
+    if datalink != DATALINK_HCI_UNENCAPSULATED && datalink != DATALINK_HCI_UART {
+        return Err(BtsnoopError::UnsupportedDatalink(datalink));
+    }
+
+    let mut records = Vec::new();
+    let mut offset = FILE_HEADER_LENGTH;
+
+    while offset < data.len() {
+        let header = data
+            .get(offset..offset + RECORD_HEADER_LENGTH)
+            .ok_or(BtsnoopError::Truncated(offset))?;
+        let be32 =
+            |i: usize| u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let included_length = be32(4) as usize;
+        let flags = be32(8);
+        let timestamp_us = i64::from_be_bytes(header[16..24].try_into().unwrap())
+            .checked_sub(EPOCH_OFFSET_US)
+            .ok_or(BtsnoopError::InvalidTimestamp(offset))?;
+
+        let start = offset + RECORD_HEADER_LENGTH;
+        let packet = data
+            .get(start..start + included_length)
+            .ok_or(BtsnoopError::Truncated(offset))?;
+        offset = start + included_length;
+
+        let acl = match datalink {
+            DATALINK_HCI_UART => match packet.split_first() {
+                Some((&H4_ACL, rest)) => Some(rest.to_vec()),
+                _ => None,
+            },
+            // Flag bit 1 set means command/event, clear means data
+            _ => (flags & 0x02 == 0).then(|| packet.to_vec()),
+        };
+
+        records.push(HciRecord {
+            timestamp_ms: timestamp_us.max(0) as u64 / 1000,
+            received: flags & 0x01 != 0,
+            acl,
+        });
+    }
+
+    Ok(records)
+}
+
+/// Reassembles L2CAP from ACL fragments and collects ATT traffic
+#[derive(Debug, Default)]
+pub struct AttExtractor {
+    /// Partial L2CAP PDUs keyed by (connection handle, received)
+    partial: HashMap<(u16, bool), Vec<u8>>,
+    frames: Vec<AttFrame>,
+    /// Value handle of the 0x7777 write characteristic, if seen in discovery
+    pub write_handle: Option<u16>,
+    /// Value handle of the 0x8888 notify characteristic, if seen in discovery
+    pub notify_handle: Option<u16>,
+}
+
+impl AttExtractor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, record: &HciRecord) {
+        let Some(acl) = &record.acl else {
+            return;
+        };
+        if acl.len() < 4 {
+            return;
+        }
+
+        let handle_flags = u16::from_le_bytes([acl[0], acl[1]]);
+        let connection = handle_flags & 0x0fff;
+        let continuation = (handle_flags >> 12) & 0x03 == 0x01;
+        let payload = &acl[4..];
+        let key = (connection, record.received);
+
+        let buffer = self.partial.entry(key).or_default();
+        if !continuation {
+            buffer.clear();
+        }
+        buffer.extend_from_slice(payload);
+
+        if buffer.len() < 4 {
+            return;
+        }
+        let l2cap_length = u16::from_le_bytes([buffer[0], buffer[1]]) as usize;
+        if buffer.len() < 4 + l2cap_length {
+            return;
+        }
+
+        let pdu = std::mem::take(buffer);
+        let cid = u16::from_le_bytes([pdu[2], pdu[3]]);
+        if cid == L2CAP_CID_ATT {
+            self.handle_att(record, &pdu[4..4 + l2cap_length]);
+        }
+    }
+
+    fn handle_att(&mut self, record: &HciRecord, att: &[u8]) {
+        let Some((&opcode, body)) = att.split_first() else {
+            return;
+        };
+
+        match opcode {
+            ATT_WRITE_COMMAND | ATT_WRITE_REQUEST if !record.received && body.len() >= 2 => {
+                self.frames.push(AttFrame {
+                    timestamp_ms: record.timestamp_ms,
+                    direction: Direction::Tx,
+                    handle: u16::from_le_bytes([body[0], body[1]]),
+                    value: body[2..].to_vec(),
+                });
+            }
+            ATT_HANDLE_VALUE_NOTIFICATION | ATT_HANDLE_VALUE_INDICATION
+                if record.received && body.len() >= 2 =>
+            {
+                self.frames.push(AttFrame {
+                    timestamp_ms: record.timestamp_ms,
+                    direction: Direction::Rx,
+                    handle: u16::from_le_bytes([body[0], body[1]]),
+                    value: body[2..].to_vec(),
+                });
+            }
+            ATT_READ_BY_TYPE_RESPONSE if record.received => self.handle_discovery(body),
+            _ => {}
+        }
+    }
+
+    /// Characteristic declarations: handle, properties, value handle, UUID
+    fn handle_discovery(&mut self, body: &[u8]) {
+        let Some((&entry_length, entries)) = body.split_first() else {
+            return;
+        };
+        if entry_length != 7 && entry_length != 21 {
+            return;
+        }
+
+        for entry in entries.chunks_exact(entry_length as usize) {
+            let value_handle = u16::from_le_bytes([entry[3], entry[4]]);
+            let uuid = &entry[5..];
+            let uuid16 = match uuid.len() {
+                2 => u16::from_le_bytes([uuid[0], uuid[1]]),
+                16 if uuid[..12] == BASE_UUID_LE[..12] && uuid[14..] == BASE_UUID_LE[14..] => {
+                    u16::from_le_bytes([uuid[12], uuid[13]])
+                }
+                _ => continue,
+            };
+
+            match uuid16 {
+                WRITE_UUID16 => self.write_handle = Some(value_handle),
+                NOTIFY_UUID16 => self.notify_handle = Some(value_handle),
+                _ => {}
+            }
+        }
+    }
+
+    /// ATT frames on the Anker characteristics.
+    ///
+    /// Uses the discovered (or overridden) value handles. When discovery isn't in
+    /// the log, writes are matched on the command header and the write handle is
+    /// inferred from them; all notifications are kept in that case.
+    pub fn anker_frames(&self) -> Vec<AttFrame> {
+        let write_handle = self.write_handle.or_else(|| {
+            self.frames
+                .iter()
+                .find(|f| f.direction == Direction::Tx && f.value.starts_with(&[0x08, 0xee]))
+                .map(|f| f.handle)
+        });
+
+        self.frames
+            .iter()
+            .filter(|f| match f.direction {
+                Direction::Tx => Some(f.handle) == write_handle,
+                Direction::Rx => self.notify_handle.is_none_or(|h| h == f.handle),
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Parse a btsnoop log and return the Anker ATT traffic with the extractor state
+pub fn extract(data: &[u8]) -> Result<AttExtractor, BtsnoopError> {
+    let mut extractor = AttExtractor::new();
+    for record in parse_records(data)? {
+        extractor.push(&record);
+    }
+    Ok(extractor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2023-11-14T22:13:20Z
+    const TIMESTAMP_MS: u64 = 1_700_000_000_000;
+    const SENT: u32 = 0x00;
+    const RECEIVED: u32 = 0x01;
+    const EVENT: u32 = 0x03;
+    const WRITE_HANDLE: u16 = 0x0010;
+    const NOTIFY_HANDLE: u16 = 0x0013;
+
+    struct Record {
+        flags: u32,
+        timestamp_us: i64,
+        packet: Vec<u8>,
+    }
+
+    fn record(flags: u32, packet: Vec<u8>) -> Record {
+        Record {
+            flags,
+            timestamp_us: EPOCH_OFFSET_US + TIMESTAMP_MS as i64 * 1000,
+            packet,
+        }
+    }
+
+    fn btsnoop(datalink: u32, records: &[Record]) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&datalink.to_be_bytes());
+        for record in records {
+            let length = record.packet.len() as u32;
+            data.extend_from_slice(&length.to_be_bytes());
+            data.extend_from_slice(&length.to_be_bytes());
+            data.extend_from_slice(&record.flags.to_be_bytes());
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data.extend_from_slice(&record.timestamp_us.to_be_bytes());
+            data.extend_from_slice(&record.packet);
+        }
+        data
+    }
+
+    /// ACL packet on connection 0x0040; `start` clear marks a continuation fragment
+    fn acl(start: bool, payload: &[u8]) -> Vec<u8> {
+        let handle_flags: u16 = 0x0040 | if start { 0x2000 } else { 0x1000 };
+        let mut packet = handle_flags.to_le_bytes().to_vec();
+        packet.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// L2CAP PDU on the ATT channel
+    fn att(opcode: u8, handle: u16, value: &[u8]) -> Vec<u8> {
+        let mut pdu = (3 + value.len() as u16).to_le_bytes().to_vec();
+        pdu.extend_from_slice(&L2CAP_CID_ATT.to_le_bytes());
+        pdu.push(opcode);
+        pdu.extend_from_slice(&handle.to_le_bytes());
+        pdu.extend_from_slice(value);
+        pdu
+    }
+
+    fn h4(packet: Vec<u8>) -> Vec<u8> {
+        [vec![H4_ACL], packet].concat()
+    }
+
+    fn notification(value: &[u8]) -> Vec<u8> {
+        acl(
+            true,
+            &att(ATT_HANDLE_VALUE_NOTIFICATION, NOTIFY_HANDLE, value),
+        )
+    }
+
+    fn write(handle: u16, value: &[u8]) -> Vec<u8> {
+        acl(true, &att(ATT_WRITE_COMMAND, handle, value))
+    }
+
+    /// Read By Type response declaring the Anker characteristics
+    fn discovery(uuid128: bool) -> Vec<u8> {
+        let uuid = |uuid16: u16| -> Vec<u8> {
+            if uuid128 {
+                let mut uuid = BASE_UUID_LE;
+                uuid[12..14].copy_from_slice(&uuid16.to_le_bytes());
+                uuid.to_vec()
+            } else {
+                uuid16.to_le_bytes().to_vec()
+            }
+        };
+        let mut body = vec![if uuid128 { 21 } else { 7 }];
+        for (value_handle, uuid16) in [(WRITE_HANDLE, WRITE_UUID16), (NOTIFY_HANDLE, NOTIFY_UUID16)]
+        {
+            body.extend_from_slice(&(value_handle - 1).to_le_bytes());
+            body.push(0x1a);
+            body.extend_from_slice(&value_handle.to_le_bytes());
+            body.extend_from_slice(&uuid(uuid16));
+        }
+
+        let mut pdu = (1 + body.len() as u16).to_le_bytes().to_vec();
+        pdu.extend_from_slice(&L2CAP_CID_ATT.to_le_bytes());
+        pdu.push(ATT_READ_BY_TYPE_RESPONSE);
+        pdu.extend_from_slice(&body);
+        acl(true, &pdu)
+    }
+
+    fn values(extractor: &AttExtractor) -> Vec<(Direction, Vec<u8>)> {
+        extractor
+            .anker_frames()
+            .into_iter()
+            .map(|f| (f.direction, f.value))
+            .collect()
+    }
+
+    #[test]
+    fn h4_and_unencapsulated_logs_give_the_same_frames() {
+        let command = [0x08, 0xee, 0x00, 0x00, 0x00];
+        let telemetry = [0x09, 0xff, 0x00, 0x01];
+        let unencapsulated = btsnoop(
+            DATALINK_HCI_UNENCAPSULATED,
+            &[
+                record(EVENT, vec![0x0e, 0x04, 0x01, 0x00, 0x00, 0x00]),
+                record(SENT, write(WRITE_HANDLE, &command)),
+                record(RECEIVED, notification(&telemetry)),
+            ],
+        );
+        let uart = btsnoop(
+            DATALINK_HCI_UART,
+            &[
+                record(RECEIVED, vec![0x04, 0x0e, 0x04, 0x01, 0x00, 0x00, 0x00]),
+                record(SENT, h4(write(WRITE_HANDLE, &command))),
+                record(RECEIVED, h4(notification(&telemetry))),
+            ],
+        );
+
+        let expected = vec![
+            (Direction::Tx, command.to_vec()),
+            (Direction::Rx, telemetry.to_vec()),
+        ];
+        assert_eq!(values(&extract(&unencapsulated).unwrap()), expected);
+        assert_eq!(values(&extract(&uart).unwrap()), expected);
+
+        let frames = extract(&uart).unwrap().anker_frames();
+        assert_eq!(frames[0].timestamp_ms, TIMESTAMP_MS);
+        assert_eq!(frames[1].handle, NOTIFY_HANDLE);
+    }
+
+    #[test]
+    fn fragmented_notifications_are_reassembled() {
+        let value: Vec<u8> = (0..40).collect();
+        let pdu = att(ATT_HANDLE_VALUE_NOTIFICATION, NOTIFY_HANDLE, &value);
+        let (first, second) = pdu.split_at(20);
+        let log = btsnoop(
+            DATALINK_HCI_UNENCAPSULATED,
+            &[
+                record(RECEIVED, acl(true, first)),
+                record(RECEIVED, acl(false, second)),
+            ],
+        );
+
+        assert_eq!(values(&extract(&log).unwrap()), [(Direction::Rx, value)]);
+    }
+
+    #[test]
+    fn new_fragment_drops_an_incomplete_pdu() {
+        let pdu = att(ATT_HANDLE_VALUE_NOTIFICATION, NOTIFY_HANDLE, &[0xaa; 30]);
+        let log = btsnoop(
+            DATALINK_HCI_UNENCAPSULATED,
+            &[
+                record(RECEIVED, acl(true, &pdu[..10])),
+                record(RECEIVED, notification(&[0x01, 0x02])),
+            ],
+        );
+
+        assert_eq!(
+            values(&extract(&log).unwrap()),
+            [(Direction::Rx, vec![0x01, 0x02])]
+        );
+    }
+
+    #[test]
+    fn discovery_finds_the_value_handles() {
+        for uuid128 in [false, true] {
+            let log = btsnoop(
+                DATALINK_HCI_UNENCAPSULATED,
+                &[
+                    record(RECEIVED, discovery(uuid128)),
+                    record(SENT, write(0x0020, &[0x01])),
+                    record(SENT, write(WRITE_HANDLE, &[0x02])),
+                    record(
+                        RECEIVED,
+                        acl(true, &att(ATT_HANDLE_VALUE_NOTIFICATION, 0x0030, &[0x03])),
+                    ),
+                    record(RECEIVED, notification(&[0x04])),
+                ],
+            );
+
+            let extractor = extract(&log).unwrap();
+            assert_eq!(extractor.write_handle, Some(WRITE_HANDLE));
+            assert_eq!(extractor.notify_handle, Some(NOTIFY_HANDLE));
+            assert_eq!(
+                values(&extractor),
+                [(Direction::Tx, vec![0x02]), (Direction::Rx, vec![0x04])]
+            );
+        }
+    }
+
+    #[test]
+    fn write_handle_is_inferred_without_discovery() {
+        let log = btsnoop(
+            DATALINK_HCI_UNENCAPSULATED,
+            &[
+                record(SENT, write(0x0020, &[0x01])),
+                record(SENT, write(WRITE_HANDLE, &[0x08, 0xee, 0x00])),
+                record(SENT, write(WRITE_HANDLE, &[0x05])),
+            ],
+        );
+
+        let extractor = extract(&log).unwrap();
+        assert_eq!(extractor.write_handle, None);
+        assert_eq!(
+            values(&extractor),
+            [
+                (Direction::Tx, vec![0x08, 0xee, 0x00]),
+                (Direction::Tx, vec![0x05])
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_garbage_and_unsupported_logs() {
+        assert!(matches!(parse_records(b""), Err(BtsnoopError::BadMagic)));
+        assert!(matches!(
+            parse_records(b"not a btsnoop log at all"),
+            Err(BtsnoopError::BadMagic)
+        ));
+        assert!(matches!(
+            parse_records(&btsnoop(1003, &[])),
+            Err(BtsnoopError::UnsupportedDatalink(1003))
+        ));
+        assert!(parse_records(&btsnoop(DATALINK_HCI_UART, &[]))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn rejects_truncated_records() {
+        let log = btsnoop(
+            DATALINK_HCI_UART,
+            &[record(RECEIVED, h4(notification(&[1])))],
+        );
+
+        // Cut inside the record header, then inside the packet
+        for cut in [FILE_HEADER_LENGTH + 10, log.len() - 1] {
+            assert!(matches!(
+                parse_records(&log[..cut]),
+                Err(BtsnoopError::Truncated(FILE_HEADER_LENGTH))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_timestamps_that_overflow() {
+        let log = btsnoop(
+            DATALINK_HCI_UART,
+            &[Record {
+                timestamp_us: i64::MIN,
+                ..record(RECEIVED, vec![])
+            }],
+        );
+
+        assert!(matches!(
+            parse_records(&log),
+            Err(BtsnoopError::InvalidTimestamp(FILE_HEADER_LENGTH))
+        ));
+    }
+
+    #[test]
+    fn garbage_acl_payloads_are_ignored() {
+        let log = btsnoop(
+            DATALINK_HCI_UNENCAPSULATED,
+            &[
+                record(RECEIVED, vec![0x40]),
+                record(RECEIVED, acl(true, &[0xff, 0xff, 0x04, 0x00, 0x1b])),
+                record(RECEIVED, acl(true, &[0x01, 0x00, 0x04, 0x00, 0x1b])),
+                record(
+                    RECEIVED,
+                    acl(true, &[0x03, 0x00, 0x05, 0x00, 0x1b, 0x13, 0x00]),
+                ),
+            ],
+        );
+
+        assert!(extract(&log).unwrap().anker_frames().is_empty());
+    }
+}
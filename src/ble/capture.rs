@@ -0,0 +1,256 @@
+//! Raw frame capture and replay.
+//!
+//! Captures are JSON lines, one frame per line:
+//! `{"timestamp_ms":1760000000000,"direction":"rx","data":"08ee00..."}`.
+//! `rx` frames are notifications from the device, `tx` frames are commands we wrote.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid capture record on line {line}: {source}")]
+    InvalidRecord {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Notification received from the device
+    Rx,
+    /// Command written to the device
+    Tx,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Unix time in milliseconds
+    pub timestamp_ms: u64,
+    pub direction: Direction,
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    pub fn now(direction: Direction, data: &[u8]) -> Self {
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            direction,
+            data: data.to_vec(),
+        }
+    }
+}
+
+/// Appends capture records to a file
+pub struct CaptureWriter {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl CaptureWriter {
+    /// Open `path` for appending, creating it if needed
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn write(&self, record: &CaptureRecord) -> Result<(), CaptureError> {
+        let line = serde_json::to_string(record).map_err(std::io::Error::from)?;
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(writer, "{}", line)?;
+        // Flush per frame so a crash still leaves a usable capture
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read every record from a capture file
+pub fn read_capture(path: impl AsRef<Path>) -> Result<Vec<CaptureRecord>, CaptureError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|source| CaptureError::InvalidRecord {
+            line: i + 1,
+            source,
+        })?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Lowercase hex string for a byte slice
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse a hex string (whitespace allowed between bytes)
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    // `from_str_radix` alone would also take a leading sign
+    if !digits.len().is_multiple_of(2) || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        super::from_hex(&hex).ok_or_else(|| serde::de::Error::custom("invalid hex string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp_ms: u64, direction: Direction, data: &[u8]) -> CaptureRecord {
+        CaptureRecord {
+            timestamp_ms,
+            direction,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn written_capture_reads_back() {
+        let path = std::env::temp_dir().join(format!("anker-capture-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let records = [
+            record(1_000, Direction::Tx, &[0x08, 0xee, 0x00]),
+            record(1_250, Direction::Rx, &[0xab, 0xcd]),
+            record(1_500, Direction::Rx, &[]),
+        ];
+
+        let writer = CaptureWriter::create(&path).unwrap();
+        for r in &records[..2] {
+            writer.write(r).unwrap();
+        }
+        drop(writer);
+        // Appends to an existing capture
+        CaptureWriter::create(&path)
+            .unwrap()
+            .write(&records[2])
+            .unwrap();
+
+        let read = read_capture(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.len(), records.len());
+        for (read, written) in read.iter().zip(&records) {
+            assert_eq!(read.timestamp_ms, written.timestamp_ms);
+            assert_eq!(read.direction, written.direction);
+            assert_eq!(read.data, written.data);
+        }
+        assert!(text.starts_with(r#"{"timestamp_ms":1000,"direction":"tx","data":"08ee00"}"#));
+    }
+
+    #[test]
+    fn invalid_record_reports_its_line() {
+        let path =
+            std::env::temp_dir().join(format!("anker-capture-bad-{}.jsonl", std::process::id()));
+        std::fs::write(
+            &path,
+            "{\"timestamp_ms\":1,\"direction\":\"rx\",\"data\":\"00\"}\n\n{\"timestamp_ms\":2,\"direction\":\"rx\",\"data\":\"0\"}\n",
+        )
+        .unwrap();
+        let result = read_capture(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(CaptureError::InvalidRecord { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn from_hex_accepts_whitespace_between_bytes() {
+        assert_eq!(from_hex("08ee 00\n1F"), Some(vec![0x08, 0xee, 0x00, 0x1f]));
+        assert_eq!(from_hex(""), Some(vec![]));
+        assert_eq!(to_hex(&[0x08, 0xee, 0x00]), "08ee00");
+    }
+
+    #[test]
+    fn from_hex_rejects_odd_length_and_non_hex() {
+        assert_eq!(from_hex("08e"), None);
+        assert_eq!(from_hex("08 e"), None);
+        assert_eq!(from_hex("0g"), None);
+        assert_eq!(from_hex("+1"), None);
+        assert_eq!(from_hex("é0"), None);
+    }
+
+    #[cfg(feature = "ble")]
+    #[tokio::test(start_paused = true)]
+    async fn replay_scales_gaps_by_speed() {
+        use crate::ble::AnkerDevice;
+        use std::sync::Arc;
+        use std::time::Duration;
+        use tokio::time::Instant;
+
+        let frame = crate::battery::sample_telemetry(50, 0, 100).to_bytes();
+        let records = vec![
+            record(10_000, Direction::Rx, &frame),
+            // Commands are skipped and don't reset the clock
+            record(10_400, Direction::Tx, &[0x00]),
+            record(11_000, Direction::Rx, &frame),
+            record(13_000, Direction::Rx, &frame),
+        ];
+
+        let device = Arc::new(AnkerDevice::new());
+        let mut frames = device.subscribe_frames();
+        let start = Instant::now();
+        let replay = tokio::spawn(Arc::clone(&device).run_replay(records, 2.0));
+
+        let mut arrivals = Vec::new();
+        for _ in 0..4 {
+            assert_eq!(frames.recv().await.unwrap(), frame);
+            arrivals.push(start.elapsed());
+        }
+        replay.abort();
+
+        // 1s and 2s gaps at 2x, then a 1s pause before starting over
+        let ms = |ms| Duration::from_millis(ms);
+        assert_eq!(arrivals, vec![ms(0), ms(500), ms(1_500), ms(2_500)]);
+    }
+
+    #[cfg(feature = "ble")]
+    #[tokio::test]
+    async fn replay_needs_notifications() {
+        use crate::ble::{AnkerDevice, DeviceError};
+        use std::sync::Arc;
+
+        let records = vec![record(0, Direction::Tx, &[0x00])];
+        let result = Arc::new(AnkerDevice::new()).run_replay(records, 1.0).await;
+        assert!(matches!(result, Err(DeviceError::EmptyCapture)));
+    }
+}
@@ -1,8 +1,18 @@
-//! BLE device connection manager for Anker PowerHouse 767.
+//! BLE device connection manager for Anker PowerHouse units.
 //! Maintains always-connected state with auto-reconnect.
 
-use crate::ble::command::AnkerCommand;
-use crate::ble::telemetry::{NotificationPacket, StateAck, Telemetry, TelemetryError};
+use crate::ble::capture::{CaptureRecord, CaptureWriter, Direction};
+use crate::ble::command::{AnkerCommand, CommandType, RawCommand};
+use crate::ble::explorer::{ExplorerReport, ProtocolExplorer};
+use crate::ble::framing::FrameDecoder;
+use crate::ble::model::{self, ModelLayout};
+use crate::ble::rejected::{RejectedFrame, RejectedFrames};
+use crate::ble::telemetry::{
+    Battery, BatteryState, Header, LedState, NotificationPacket, ParseError, ParseMode, StateAck,
+    Telemetry, TelemetryError,
+};
+#[cfg(feature = "metrics")]
+use crate::metrics;
 use btleplug::api::{
     Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType,
 };
@@ -11,17 +21,22 @@ use futures::StreamExt;
 use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
-use tokio::sync::{broadcast, watch, RwLock};
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
 use tokio::time::{sleep, timeout};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-const DEVICE_NAME: &str = "767_PowerHouse";
 const WRITE_UUID: Uuid = Uuid::from_u128(0x00007777_0000_1000_8000_00805f9b34fb);
 const NOTIFY_UUID: Uuid = Uuid::from_u128(0x00008888_0000_1000_8000_00805f9b34fb);
 const RECONNECT_DELAY: Duration = Duration::from_secs(5);
 const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
 const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+const SCAN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Supported replay speeds; the server clamps `ANKER_REPLAY_SPEED` to this
+pub const REPLAY_SPEED_RANGE: std::ops::RangeInclusive<f64> = 0.01..=1000.0;
+/// Frames in a row that must agree before the expansion battery counts as
+/// attached or detached
+const EXPANSION_DEBOUNCE_FRAMES: u8 = 3;
 
 #[derive(Debug, Error)]
 pub enum DeviceError {
@@ -37,6 +52,15 @@ pub enum DeviceError {
     Telemetry(#[from] TelemetryError),
     #[error("Write timeout")]
     WriteTimeout,
+    #[error("No acknowledgement for {}", .0.as_str())]
+    NoAck(CommandType),
+    #[error("Capture contains no notifications")]
+    EmptyCapture,
+    #[error("PowerHouse {model} does not support {command:?}")]
+    UnsupportedCommand {
+        model: &'static str,
+        command: CommandType,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,8 +71,73 @@ pub enum ConnectionState {
     Connected,
 }
 
+impl ConnectionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::Scanning => "scanning",
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+        }
+    }
+}
+
+/// A named BLE peripheral seen while scanning
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DiscoveredDevice {
+    pub name: String,
+    pub address: String,
+    pub rssi: Option<i16>,
+    /// Model number, if the name matches a known PowerHouse
+    pub model: Option<&'static str>,
+}
+
+/// List the named peripherals advertising within `window`, strongest signal first.
+/// Fails if another program (e.g. the server) holds the adapter's scan.
+pub async fn scan(window: Duration) -> Result<Vec<DiscoveredDevice>, DeviceError> {
+    let adapter = first_adapter().await?;
+    adapter.start_scan(ScanFilter::default()).await?;
+    sleep(window).await;
+    let peripherals = adapter.peripherals().await;
+    adapter.stop_scan().await?;
+
+    let mut found = Vec::new();
+    for peripheral in peripherals? {
+        let Some(props) = peripheral.properties().await? else {
+            continue;
+        };
+        let Some(name) = props.local_name else {
+            continue;
+        };
+        found.push(DiscoveredDevice {
+            model: model::by_advertised_name(&name).map(|m| m.model),
+            name,
+            address: props.address.to_string(),
+            rssi: props.rssi,
+        });
+    }
+    found.sort_by_key(|d| std::cmp::Reverse(d.rssi));
+    Ok(found)
+}
+
+async fn first_adapter() -> Result<Adapter, DeviceError> {
+    let manager = Manager::new().await?;
+    let adapters = manager.adapters().await?;
+    adapters.into_iter().next().ok_or(DeviceError::NotFound)
+}
+
+/// Notable changes observed on the device
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DeviceEvent {
+    ExpansionBatteryAttached,
+    ExpansionBatteryDetached,
+}
+
 /// Tracks the last values we've set via commands
-#[derive(Debug, Clone, Default, serde::Serialize, utoipa::ToSchema)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SetState {
     pub ac_output: Option<bool>,
     pub twelve_volt_output: Option<bool>,
@@ -61,12 +150,126 @@ pub struct SetState {
     pub twelve_volt_timer: Option<u16>,
 }
 
+impl SetState {
+    /// Remember the value `command` set, once the device has accepted it
+    pub fn record(&mut self, command: &AnkerCommand) {
+        match command {
+            AnkerCommand::PowerSave(c) => self.power_save = Some(c.is_on),
+            AnkerCommand::AcOutput(c) => self.ac_output = Some(c.is_on),
+            AnkerCommand::TwelveVoltOutput(c) => self.twelve_volt_output = Some(c.is_on),
+            AnkerCommand::ScreenBrightness(c) => self.screen_brightness = Some(c.brightness),
+            AnkerCommand::Led(c) => self.led_level = Some(c.level),
+            AnkerCommand::RechargePower(c) => self.recharge_power = Some(c.watts),
+            AnkerCommand::ScreenTimeout(c) => self.screen_timeout = Some(c.seconds),
+            AnkerCommand::AcTimer(c) => self.ac_timer = Some(c.seconds),
+            AnkerCommand::TwelveVoltTimer(c) => self.twelve_volt_timer = Some(c.seconds),
+        }
+    }
+}
+
+/// Where a reported setting value comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    /// Read back from the device
+    Device,
+    /// The last value we set; the device doesn't report it
+    LastSet,
+    /// Neither reported nor set since startup
+    Unknown,
+}
+
+/// One setting with both the device-reported and last-set value
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReportedSetting<T> {
+    /// Device-reported value if available, otherwise the last set value
+    pub value: Option<T>,
+    pub source: SettingSource,
+    pub device: Option<T>,
+    pub last_set: Option<T>,
+}
+
+impl<T: Copy> ReportedSetting<T> {
+    fn new(device: Option<T>, last_set: Option<T>) -> Self {
+        let (value, source) = match (device, last_set) {
+            (Some(v), _) => (Some(v), SettingSource::Device),
+            (None, Some(v)) => (Some(v), SettingSource::LastSet),
+            (None, None) => (None, SettingSource::Unknown),
+        };
+        Self {
+            value,
+            source,
+            device,
+            last_set,
+        }
+    }
+}
+
+/// Current settings, device-reported where possible
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeviceSettingsReport {
+    pub ac_output: ReportedSetting<bool>,
+    pub twelve_volt_output: ReportedSetting<bool>,
+    pub power_save: ReportedSetting<bool>,
+    pub led_level: ReportedSetting<u8>,
+    /// Not read back from the device; last set value only
+    pub screen_brightness: ReportedSetting<u8>,
+    /// Not read back from the device; last set value only
+    pub screen_timeout: ReportedSetting<u16>,
+    /// Not read back from the device; last set value only
+    pub recharge_power: ReportedSetting<u16>,
+    /// Not read back from the device; last set value only (the duration requested)
+    pub ac_timer: ReportedSetting<u16>,
+    /// Device value is the time left, last set value is the duration requested
+    pub twelve_volt_timer: ReportedSetting<u16>,
+}
+
 /// Shared state for the BLE device
 pub struct DeviceState {
     pub connection_state: ConnectionState,
     pub last_telemetry: Option<Telemetry>,
     pub last_state_ack: Option<StateAck>,
     pub set_state: SetState,
+    /// Layout of the connected (or replayed) model, once identified
+    pub model: Option<&'static ModelLayout>,
+    expansion: ExpansionPresence,
+}
+
+impl DeviceState {
+    /// Device-reported settings (telemetry first, it's the most recent) merged
+    /// with the last set values
+    pub fn settings(&self) -> DeviceSettingsReport {
+        let from_telemetry = self.last_telemetry.as_ref().map(Telemetry::settings);
+        let from_state_ack = self.last_state_ack.as_ref().map(StateAck::settings);
+        let device = from_telemetry
+            .unwrap_or_default()
+            .or(from_state_ack.unwrap_or_default());
+        let set = &self.set_state;
+
+        DeviceSettingsReport {
+            ac_output: ReportedSetting::new(device.ac_output, set.ac_output),
+            twelve_volt_output: ReportedSetting::new(
+                device.twelve_volt_output,
+                set.twelve_volt_output,
+            ),
+            power_save: ReportedSetting::new(device.power_save, set.power_save),
+            led_level: ReportedSetting::new(device.led_level, set.led_level),
+            screen_brightness: ReportedSetting::new(
+                device.screen_brightness,
+                set.screen_brightness,
+            ),
+            screen_timeout: ReportedSetting::new(device.screen_timeout, set.screen_timeout),
+            recharge_power: ReportedSetting::new(device.recharge_power, set.recharge_power),
+            ac_timer: ReportedSetting::new(device.ac_timer, set.ac_timer),
+            twelve_volt_timer: ReportedSetting::new(
+                device.twelve_volt_timer,
+                set.twelve_volt_timer,
+            ),
+        }
+    }
 }
 
 impl Default for DeviceState {
@@ -76,7 +279,52 @@ impl Default for DeviceState {
             last_telemetry: None,
             last_state_ack: None,
             set_state: SetState::default(),
+            model: None,
+            expansion: ExpansionPresence::default(),
+        }
+    }
+}
+
+/// Debounced expansion battery presence.
+///
+/// No presence flag is known in telemetry, so a pack is taken as absent when
+/// its temperature and percentage both read zero. That misreads a real pack at
+/// 0% and 0 °C as detached; debouncing only keeps single odd frames from
+/// flapping attach/detach events.
+#[derive(Debug, Default)]
+struct ExpansionPresence {
+    /// Debounced presence, unknown until the first frame
+    present: Option<bool>,
+    /// Frames in a row disagreeing with `present`
+    disagreeing: u8,
+    /// Last reading of the pack, reported while a detach is pending
+    last_reading: Option<Battery>,
+}
+
+impl ExpansionPresence {
+    /// Debounce one frame's expansion battery reading
+    fn filter(&mut self, reading: Option<Battery>) -> Option<Battery> {
+        let present = match self.present {
+            Some(present) if present != reading.is_some() => {
+                self.disagreeing += 1;
+                if self.disagreeing < EXPANSION_DEBOUNCE_FRAMES {
+                    present
+                } else {
+                    self.disagreeing = 0;
+                    !present
+                }
+            }
+            _ => {
+                self.disagreeing = 0;
+                reading.is_some()
+            }
+        };
+        self.present = Some(present);
+
+        if reading.is_some() {
+            self.last_reading = reading;
         }
+        present.then(|| self.last_reading.clone()).flatten()
     }
 }
 
@@ -85,20 +333,62 @@ pub struct AnkerDevice {
     state: Arc<RwLock<DeviceState>>,
     state_tx: watch::Sender<ConnectionState>,
     telemetry_tx: broadcast::Sender<Telemetry>,
+    events_tx: broadcast::Sender<DeviceEvent>,
+    frames_tx: broadcast::Sender<Vec<u8>>,
+    decoder: std::sync::Mutex<FrameDecoder>,
+    /// Peripheral to write commands to while connected
+    connection: Mutex<Option<Connection>>,
+    /// Only connect to this model instead of detecting it
+    forced_model: Option<&'static ModelLayout>,
+    parse_mode: ParseMode,
+    /// Raw frame recording, if enabled
+    recorder: Option<CaptureWriter>,
+    explorer: std::sync::Mutex<ProtocolExplorer>,
+    rejected: RejectedFrames,
 }
 
 impl AnkerDevice {
     pub fn new() -> Self {
         let (state_tx, _) = watch::channel(ConnectionState::Disconnected);
         let (telemetry_tx, _) = broadcast::channel(16);
+        let (events_tx, _) = broadcast::channel(16);
+        let (frames_tx, _) = broadcast::channel(64);
 
         Self {
             state: Arc::new(RwLock::new(DeviceState::default())),
             state_tx,
             telemetry_tx,
+            events_tx,
+            frames_tx,
+            decoder: std::sync::Mutex::new(FrameDecoder::new()),
+            connection: Mutex::new(None),
+            forced_model: None,
+            parse_mode: ParseMode::Strict,
+            recorder: None,
+            explorer: std::sync::Mutex::new(ProtocolExplorer::new()),
+            rejected: RejectedFrames::default(),
         }
     }
 
+    /// Record every raw notification and command to `writer`
+    pub fn with_recorder(mut self, writer: CaptureWriter) -> Self {
+        self.recorder = Some(writer);
+        self
+    }
+
+    /// Parse notifications in `mode`; `Lenient` keeps frames with unknown enum values
+    pub fn with_parse_mode(mut self, mode: ParseMode) -> Self {
+        self.parse_mode = mode;
+        self
+    }
+
+    /// Only connect to peripherals advertising `model`'s name, and decode them
+    /// with its layout instead of the one looked up from the advertised name
+    pub fn with_model(mut self, model: &'static ModelLayout) -> Self {
+        self.forced_model = Some(model);
+        self
+    }
+
     pub fn state(&self) -> Arc<RwLock<DeviceState>> {
         Arc::clone(&self.state)
     }
@@ -111,18 +401,84 @@ impl AnkerDevice {
         self.telemetry_tx.subscribe()
     }
 
+    pub fn subscribe_events(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Sender side of the event channel, for components that subscribe later
+    pub fn events(&self) -> broadcast::Sender<DeviceEvent> {
+        self.events_tx.clone()
+    }
+
+    /// Reassembled notification frames, before parsing
+    pub fn subscribe_frames(&self) -> broadcast::Receiver<Vec<u8>> {
+        self.frames_tx.subscribe()
+    }
+
+    /// Sender side of the raw frame channel, for components that subscribe later
+    pub fn frames(&self) -> broadcast::Sender<Vec<u8>> {
+        self.frames_tx.clone()
+    }
+
+    /// Byte-level diff of the telemetry frames and commands seen so far
+    pub async fn explorer_report(&self) -> ExplorerReport {
+        let model = self.model().await;
+        self.lock_explorer().report(model)
+    }
+
+    /// Notifications that recently failed to parse, oldest first
+    pub fn rejected_frames(&self) -> Vec<RejectedFrame> {
+        self.rejected.recent()
+    }
+
     async fn set_connection_state(&self, state: ConnectionState) {
         let mut device_state = self.state.write().await;
         device_state.connection_state = state;
-        let _ = self.state_tx.send(state);
+        self.state_tx.send_replace(state);
     }
 
-    async fn update_telemetry(&self, telemetry: Telemetry) {
+    async fn update_telemetry(&self, mut telemetry: Telemetry) {
         let mut state = self.state.write().await;
+        telemetry.external_battery = state.expansion.filter(telemetry.external_battery.take());
+
+        let had_external = state
+            .last_telemetry
+            .as_ref()
+            .map(|t| t.external_battery.is_some());
+        let has_external = telemetry.external_battery.is_some();
+        match (had_external, has_external) {
+            (None, true) => info!("Expansion battery present"),
+            (Some(false), true) => {
+                info!("Expansion battery attached");
+                let _ = self.events_tx.send(DeviceEvent::ExpansionBatteryAttached);
+            }
+            (Some(true), false) => {
+                info!("Expansion battery detached");
+                let _ = self.events_tx.send(DeviceEvent::ExpansionBatteryDetached);
+            }
+            _ => {}
+        }
+
         state.last_telemetry = Some(telemetry.clone());
         let _ = self.telemetry_tx.send(telemetry);
     }
 
+    async fn set_model(&self, model: &'static ModelLayout) {
+        self.state.write().await.model = Some(model);
+        if let Some(connection) = self.connection.lock().await.as_mut() {
+            connection.model = model;
+        }
+    }
+
+    /// Layout to parse notifications with
+    async fn model(&self) -> &'static ModelLayout {
+        self.state
+            .read()
+            .await
+            .model
+            .unwrap_or_else(model::default_model)
+    }
+
     async fn update_state_ack(&self, state_ack: StateAck) {
         let mut state = self.state.write().await;
         state.last_state_ack = Some(state_ack);
@@ -148,14 +504,15 @@ impl AnkerDevice {
     async fn connect_and_listen(&self) -> Result<(), DeviceError> {
         self.set_connection_state(ConnectionState::Scanning).await;
 
-        let manager = Manager::new().await?;
-        let adapters = manager.adapters().await?;
-        let adapter = adapters.into_iter().next().ok_or(DeviceError::NotFound)?;
+        let adapter = first_adapter().await?;
 
-        info!("Scanning for {} ...", DEVICE_NAME);
+        match self.forced_model {
+            Some(model) => info!("Scanning for {} ...", model.advertised_name),
+            None => info!("Scanning for a PowerHouse ..."),
+        }
         adapter.start_scan(ScanFilter::default()).await?;
 
-        let peripheral = self.find_device(&adapter).await?;
+        let (peripheral, model) = self.find_device(&adapter).await?;
         adapter.stop_scan().await?;
 
         self.set_connection_state(ConnectionState::Connecting).await;
@@ -168,55 +525,182 @@ impl AnkerDevice {
 
         peripheral.subscribe(&notify_char).await?;
 
-        self.set_connection_state(ConnectionState::Connected).await;
+        let peripheral = Arc::new(peripheral);
+        self.connected(Connection {
+            writer: Writer::Ble {
+                peripheral: Arc::clone(&peripheral),
+                write_char: Arc::new(write_char),
+            },
+            model,
+        })
+        .await;
         info!("Connected and subscribed to notifications");
 
-        // Store peripheral for command sending
-        let peripheral = Arc::new(peripheral);
-        let write_char = Arc::new(write_char);
+        // Listen for notifications, dropping any partial frame from a previous connection
+        self.lock_decoder().clear();
+        let mut notification_stream = peripheral.notifications().await?;
 
-        // Store in state for command sending
-        {
-            let mut state = self.state.write().await;
-            // We'll use a different approach - store the peripheral reference
-            state.connection_state = ConnectionState::Connected;
+        while let Some(data) = notification_stream.next().await {
+            self.handle_notification(&data.value).await;
         }
 
-        CURRENT_PERIPHERAL
-            .lock()
-            .await
-            .replace((Arc::clone(&peripheral), Arc::clone(&write_char)));
+        info!("Notification stream ended");
+        self.connection.lock().await.take();
+        Ok(())
+    }
 
-        // Listen for notifications
-        let mut notification_stream = peripheral.notifications().await?;
+    /// Store the connection for command sending, then announce it, so anyone
+    /// woken by `Connected` can send right away
+    async fn connected(&self, connection: Connection) {
+        let model = connection.model;
+        self.connection.lock().await.replace(connection);
+        self.set_model(model).await;
+        self.set_connection_state(ConnectionState::Connected).await;
+    }
 
-        while let Some(data) = notification_stream.next().await {
-            debug!("Received notification: {} bytes", data.value.len());
+    fn lock_explorer(&self) -> std::sync::MutexGuard<'_, ProtocolExplorer> {
+        self.explorer.lock().unwrap_or_else(|e| e.into_inner())
+    }
 
-            match NotificationPacket::from_bytes(&data.value) {
-                Ok(NotificationPacket::Telemetry(telemetry)) => {
-                    debug!("Telemetry: battery={}%", telemetry.total_battery_percentage);
-                    self.update_telemetry(telemetry).await;
-                }
-                Ok(NotificationPacket::StateAck(state_ack)) => {
-                    debug!("State ack: {:?}", state_ack);
-                    self.update_state_ack(state_ack).await;
-                }
-                Ok(NotificationPacket::CommandAck(cmd_ack)) => {
-                    debug!("Command ack: {:?}", cmd_ack.command_type);
+    /// Write a raw frame to the capture file, if recording
+    fn record(&self, direction: Direction, data: &[u8]) {
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.write(&CaptureRecord::now(direction, data)) {
+                warn!("Failed to write capture record: {}", e);
+            }
+        }
+    }
+
+    fn lock_decoder(&self) -> std::sync::MutexGuard<'_, FrameDecoder> {
+        self.decoder.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Handle one BLE notification, which may hold part of a frame or several frames
+    async fn handle_notification(&self, data: &[u8]) {
+        debug!("Received notification: {} bytes", data.len());
+        self.record(Direction::Rx, data);
+
+        let results: Vec<_> = {
+            let mut decoder = self.lock_decoder();
+            decoder.push(data);
+            std::iter::from_fn(|| decoder.next_frame()).collect()
+        };
+
+        for result in results {
+            match result {
+                Ok(frame) => self.handle_frame(&frame).await,
+                Err(e) => self.report_frame_error(e, data).await,
+            }
+        }
+    }
+
+    async fn handle_frame(&self, data: &[u8]) {
+        self.lock_explorer()
+            .observe(&CaptureRecord::now(Direction::Rx, data));
+        let _ = self.frames_tx.send(data.to_vec());
+        if let Err(e) = Header::verify(data) {
+            self.report_unverified_frame(e, data).await;
+        }
+
+        match NotificationPacket::from_bytes_with(data, self.model().await, self.parse_mode) {
+            Ok(NotificationPacket::Telemetry(telemetry)) => {
+                debug!("Telemetry: battery={}%", telemetry.total_battery_percentage);
+                if let BatteryState::Unknown(value) = telemetry.battery_state {
+                    debug!("Unknown battery state {}", value);
+                    #[cfg(feature = "metrics")]
+                    metrics::increment_unknown_value("battery_state");
                 }
-                Err(e) => {
-                    warn!("Failed to parse notification: {}", e);
+                self.update_telemetry(telemetry).await;
+            }
+            Ok(NotificationPacket::StateAck(state_ack)) => {
+                debug!("State ack: {:?}", state_ack);
+                if let LedState::Unknown(_) = state_ack.led_state {
+                    #[cfg(feature = "metrics")]
+                    metrics::increment_unknown_value("led_state");
                 }
+                self.update_state_ack(state_ack).await;
             }
+            Ok(NotificationPacket::CommandAck(cmd_ack)) => {
+                debug!("Command ack: {:?}", cmd_ack.command_type);
+            }
+            Err(e) => self.report_frame_error(e, data).await,
         }
+    }
 
-        info!("Notification stream ended");
-        CURRENT_PERIPHERAL.lock().await.take();
-        Ok(())
+    /// The header checks aren't confirmed against real captures yet, so a
+    /// mismatch is logged and counted but the frame is still parsed
+    async fn report_unverified_frame(&self, error: TelemetryError, data: &[u8]) {
+        let error = ParseError::new(error, data, self.model().await);
+        warn!(
+            "Notification failed header checks, parsing anyway: {}",
+            error
+        );
+        #[cfg(feature = "metrics")]
+        if let Some(reason) = error.error.corruption() {
+            metrics::increment_corrupt_frame(reason);
+        }
+    }
+
+    async fn report_frame_error(&self, error: TelemetryError, data: &[u8]) {
+        let error = ParseError::new(error, data, self.model().await);
+        match error.error.corruption() {
+            Some(_) => warn!("Dropping corrupt notification: {}", error),
+            None => warn!("Failed to parse notification: {}", error),
+        }
+        #[cfg(feature = "metrics")]
+        {
+            metrics::increment_parse_error(error.error.variant());
+            if let Some(reason) = error.error.corruption() {
+                metrics::increment_corrupt_frame(reason);
+            }
+        }
+        self.rejected.record(&error);
     }
 
-    async fn find_device(&self, adapter: &Adapter) -> Result<Peripheral, DeviceError> {
+    /// Feed captured notifications through the parser instead of a live device.
+    /// `speed` scales the original timing (2.0 = twice as fast). Loops forever.
+    pub async fn run_replay(
+        self: Arc<Self>,
+        records: Vec<CaptureRecord>,
+        speed: f64,
+    ) -> Result<(), DeviceError> {
+        let frames: Vec<CaptureRecord> = records
+            .into_iter()
+            .filter(|r| r.direction == Direction::Rx)
+            .collect();
+        if frames.is_empty() {
+            return Err(DeviceError::EmptyCapture);
+        }
+
+        let model = self.forced_model.unwrap_or_else(model::default_model);
+        self.set_model(model).await;
+        self.set_connection_state(ConnectionState::Connected).await;
+        info!("Replaying {} captured notifications at {}x", frames.len(), speed);
+
+        loop {
+            let mut previous_ms = None;
+            for frame in &frames {
+                if let Some(previous_ms) = previous_ms {
+                    let gap = Duration::from_millis(frame.timestamp_ms.saturating_sub(previous_ms));
+                    // Very slow speeds on long gaps overflow `Duration`
+                    let scaled = Duration::try_from_secs_f64(gap.as_secs_f64() / speed)
+                        .unwrap_or(Duration::MAX);
+                    sleep(scaled).await;
+                }
+                previous_ms = Some(frame.timestamp_ms);
+                self.handle_notification(&frame.data).await;
+            }
+
+            info!("Replay finished, starting over");
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// First peripheral advertising a known model (or the forced one)
+    async fn find_device(
+        &self,
+        adapter: &Adapter,
+    ) -> Result<(Peripheral, &'static ModelLayout), DeviceError> {
         let start = std::time::Instant::now();
 
         loop {
@@ -229,16 +713,139 @@ impl AnkerDevice {
             for peripheral in peripherals {
                 if let Some(props) = peripheral.properties().await? {
                     if let Some(name) = props.local_name {
-                        if name.contains(DEVICE_NAME) {
-                            info!("Found device: {}", name);
-                            return Ok(peripheral);
+                        let model = match self.forced_model {
+                            Some(model) => name.contains(model.advertised_name).then_some(model),
+                            None => model::by_advertised_name(&name),
+                        };
+                        if let Some(model) = model {
+                            info!("Found device: {} ({})", name, model.name);
+                            return Ok((peripheral, model));
+                        }
+                    }
+                }
+            }
+
+            sleep(SCAN_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Send a command to the device, if its model supports it.
+    /// Accepted commands are recorded in `SetState`.
+    pub async fn send_command(&self, command: AnkerCommand) -> Result<(), DeviceError> {
+        let command_type = command.command_type();
+        debug!("send_command: {:?}", command_type);
+        if let Some(connection) = self.connection.lock().await.as_ref() {
+            if !connection.model.supports(command_type) {
+                return Err(DeviceError::UnsupportedCommand {
+                    model: connection.model.model,
+                    command: command_type,
+                });
+            }
+        }
+        self.write_frame(&command.to_bytes()).await?;
+        self.state.write().await.set_state.record(&command);
+        Ok(())
+    }
+
+    /// Send a command and wait up to `wait` for the device to acknowledge it
+    pub async fn send_command_acked(
+        &self,
+        command: AnkerCommand,
+        wait: Duration,
+    ) -> Result<(), DeviceError> {
+        let command_type = command.command_type();
+        // Subscribe before writing so a fast ack isn't missed
+        let mut frames = self.subscribe_frames();
+        self.send_command(command).await?;
+
+        let model = self.model().await;
+        let acked = timeout(wait, async {
+            loop {
+                match frames.recv().await {
+                    Ok(frame) => {
+                        if let Ok(NotificationPacket::CommandAck(ack)) =
+                            NotificationPacket::from_bytes_with(&frame, model, self.parse_mode)
+                        {
+                            if ack.command_type == command_type {
+                                return true;
+                            }
                         }
                     }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return false,
                 }
             }
+        })
+        .await;
+
+        match acked {
+            Ok(true) => Ok(()),
+            _ => Err(DeviceError::NoAck(command_type)),
+        }
+    }
 
-            sleep(Duration::from_millis(500)).await;
+    /// Drop the current connection. `run` reconnects afterwards, so stop it
+    /// first when disconnecting for good.
+    pub async fn disconnect(&self) -> Result<(), DeviceError> {
+        let connection = self.connection.lock().await.take();
+        if let Some(Connection {
+            writer: Writer::Ble { peripheral, .. },
+            ..
+        }) = connection
+        {
+            peripheral.disconnect().await?;
         }
+        self.set_connection_state(ConnectionState::Disconnected)
+            .await;
+        Ok(())
+    }
+
+    /// Send an arbitrary, possibly undocumented, command to the device
+    pub async fn send_raw(&self, command: &RawCommand) -> Result<(), DeviceError> {
+        debug!("send_raw: type 0x{:02x}", command.command_type);
+        self.write_frame(&command.to_bytes()).await
+    }
+
+    async fn write_frame(&self, bytes: &[u8]) -> Result<(), DeviceError> {
+        debug!("write_frame: acquiring mutex...");
+        let lock_start = std::time::Instant::now();
+        let guard = self.connection.lock().await;
+        debug!("write_frame: mutex acquired in {:?}", lock_start.elapsed());
+
+        let connection = guard.as_ref().ok_or(DeviceError::NotConnected)?;
+
+        debug!("write_frame: sending {} bytes: {:02x?}", bytes.len(), bytes);
+
+        self.record(Direction::Tx, bytes);
+        self.lock_explorer()
+            .observe(&CaptureRecord::now(Direction::Tx, bytes));
+
+        let (peripheral, write_char) = match &connection.writer {
+            Writer::Ble {
+                peripheral,
+                write_char,
+            } => (peripheral, write_char),
+            #[cfg(test)]
+            Writer::Fake(written) => {
+                written.lock().unwrap().push(bytes.to_vec());
+                return Ok(());
+            }
+        };
+
+        let write_start = std::time::Instant::now();
+        timeout(
+            WRITE_TIMEOUT,
+            peripheral.write(write_char, bytes, WriteType::WithoutResponse),
+        )
+        .await
+        .map_err(|_| {
+            error!("write_frame: write timed out after {:?}", WRITE_TIMEOUT);
+            DeviceError::WriteTimeout
+        })?
+        .map_err(DeviceError::Ble)?;
+
+        debug!("write_frame: write completed in {:?}", write_start.elapsed());
+        Ok(())
     }
 
     fn find_characteristic(
@@ -263,40 +870,155 @@ impl Default for AnkerDevice {
     }
 }
 
-// Global storage for the current peripheral (for sending commands)
-use tokio::sync::Mutex;
-static CURRENT_PERIPHERAL: Mutex<Option<(Arc<Peripheral>, Arc<Characteristic>)>> =
-    Mutex::const_new(None);
-
-/// Send a command to the device
-pub async fn send_command(command: AnkerCommand) -> Result<(), DeviceError> {
-    debug!("send_command: acquiring mutex...");
-    let lock_start = std::time::Instant::now();
-    let guard = CURRENT_PERIPHERAL.lock().await;
-    debug!("send_command: mutex acquired in {:?}", lock_start.elapsed());
-
-    let (peripheral, write_char) = guard.as_ref().ok_or(DeviceError::NotConnected)?;
-
-    let bytes = command.to_bytes();
-    debug!(
-        "send_command: sending {:?} ({} bytes): {:02x?}",
-        command.command_type(),
-        bytes.len(),
-        bytes
-    );
-
-    let write_start = std::time::Instant::now();
-    timeout(
-        WRITE_TIMEOUT,
-        peripheral.write(write_char, &bytes, WriteType::WithoutResponse),
-    )
-    .await
-    .map_err(|_| {
-        error!("send_command: write timed out after {:?}", WRITE_TIMEOUT);
-        DeviceError::WriteTimeout
-    })?
-    .map_err(DeviceError::Ble)?;
-
-    debug!("send_command: write completed in {:?}", write_start.elapsed());
-    Ok(())
+/// Where commands are written to while connected
+struct Connection {
+    writer: Writer,
+    model: &'static ModelLayout,
+}
+
+enum Writer {
+    /// Peripheral and characteristic of a live BLE connection
+    Ble {
+        peripheral: Arc<Peripheral>,
+        write_char: Arc<Characteristic>,
+    },
+    /// Collects written frames instead of sending them, for tests
+    #[cfg(test)]
+    Fake(Arc<std::sync::Mutex<Vec<Vec<u8>>>>),
+}
+
+#[cfg(test)]
+impl AnkerDevice {
+    /// A device connected to a fake writer, and the frames written to it
+    pub(crate) async fn fake_connected() -> (Arc<Self>, Arc<std::sync::Mutex<Vec<Vec<u8>>>>) {
+        let device = Arc::new(Self::new());
+        let written = Arc::default();
+        device.fake_reconnect(&written).await;
+        (device, written)
+    }
+
+    /// Connect to the fake writer again, as after a dropped link
+    pub(crate) async fn fake_reconnect(&self, written: &Arc<std::sync::Mutex<Vec<Vec<u8>>>>) {
+        self.connected(Connection {
+            writer: Writer::Fake(Arc::clone(written)),
+            model: model::default_model(),
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ble::command::AcOutputCommand;
+    use crate::ble::units::{Celsius, Percentage};
+
+    #[tokio::test]
+    async fn connection_is_stored_before_connected_is_published() {
+        let device = Arc::new(AnkerDevice::new());
+        let written = Arc::default();
+        let connection = Connection {
+            writer: Writer::Fake(Arc::clone(&written)),
+            model: model::default_model(),
+        };
+
+        // Hold the state lock so `connected` stops right before publishing
+        let state = device.state();
+        let guard = state.read().await;
+        let task = tokio::spawn({
+            let device = Arc::clone(&device);
+            async move { device.connected(connection).await }
+        });
+        let stored = async {
+            while device.connection.try_lock().map_or(true, |c| c.is_none()) {
+                tokio::task::yield_now().await;
+            }
+        };
+        timeout(Duration::from_secs(1), stored)
+            .await
+            .expect("connection not stored before publishing Connected");
+        assert_eq!(guard.connection_state, ConnectionState::Disconnected);
+        drop(guard);
+        task.await.unwrap();
+
+        let mut state_rx = device.subscribe_state();
+        assert_eq!(*state_rx.borrow_and_update(), ConnectionState::Connected);
+        device
+            .send_command(AnkerCommand::AcOutput(AcOutputCommand::new(true)))
+            .await
+            .unwrap();
+        assert_eq!(written.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sent_commands_are_written_and_recorded() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let command = AnkerCommand::AcOutput(AcOutputCommand::new(true));
+        device.send_command(command.clone()).await.unwrap();
+
+        assert_eq!(*written.lock().unwrap(), vec![command.to_bytes()]);
+        assert_eq!(device.state().read().await.set_state.ac_output, Some(true));
+    }
+
+    fn pack(percentage: u8) -> Option<Battery> {
+        Some(Battery {
+            temperature: Celsius(20),
+            percentage: Percentage(percentage),
+        })
+    }
+
+    #[test]
+    fn expansion_presence_needs_several_frames_to_change() {
+        let mut presence = ExpansionPresence::default();
+        assert_eq!(presence.filter(pack(50)), pack(50));
+
+        // A detach is reported on the third empty frame, the last reading until then
+        assert_eq!(presence.filter(None), pack(50));
+        assert_eq!(presence.filter(None), pack(50));
+        assert_eq!(presence.filter(None), None);
+
+        // A single reading doesn't attach it again, and resets the count
+        assert_eq!(presence.filter(pack(40)), None);
+        assert_eq!(presence.filter(None), None);
+        assert_eq!(presence.filter(pack(40)), None);
+        assert_eq!(presence.filter(pack(41)), None);
+        assert_eq!(presence.filter(pack(42)), pack(42));
+    }
+
+    #[tokio::test]
+    async fn expansion_events_fire_once_debounced() {
+        let device = AnkerDevice::new();
+        let mut events = device.subscribe_events();
+        let frame = |external: Option<Battery>| {
+            let mut telemetry = crate::battery::sample_telemetry(80, 0, 100);
+            telemetry.external_battery = external;
+            telemetry
+        };
+
+        device.update_telemetry(frame(None)).await;
+        for external in [pack(60), pack(60), None, pack(60), pack(60)] {
+            device.update_telemetry(frame(external)).await;
+        }
+        assert!(events.try_recv().is_err());
+
+        device.update_telemetry(frame(pack(60))).await;
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DeviceEvent::ExpansionBatteryAttached)
+        ));
+        let state = device.state();
+        assert_eq!(
+            state.read().await.last_telemetry.as_ref().unwrap().battery_packs(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn commands_fail_before_connecting() {
+        let device = AnkerDevice::new();
+        let result = device
+            .send_command(AnkerCommand::AcOutput(AcOutputCommand::new(true)))
+            .await;
+        assert!(matches!(result, Err(DeviceError::NotConnected)));
+    }
 }
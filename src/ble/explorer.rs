@@ -0,0 +1,353 @@
+//! Protocol explorer: byte-level diffing of recent telemetry frames.
+//!
+//! Keeps the last raw telemetry frames and commands, reports which unmapped
+//! bytes change over time and which of them flipped right after a command was
+//! sent. Meant to help finish mapping the settings readback.
+
+use crate::ble::capture::{to_hex, CaptureRecord, Direction};
+use crate::ble::command::RawCommand;
+use crate::ble::model::ModelLayout;
+use crate::ble::telemetry::{PacketType, TelemetryType};
+use serde::Serialize;
+use std::collections::{BTreeSet, VecDeque};
+use std::ops::Range;
+
+const MAX_FRAMES: usize = 512;
+const MAX_COMMANDS: usize = 64;
+/// How long after a command we look for the device to reflect it
+const CORRELATION_WINDOW_MS: u64 = 5_000;
+
+#[derive(Debug, Clone)]
+struct TimedBytes {
+    timestamp_ms: u64,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ByteActivity {
+    pub offset: usize,
+    /// Number of frame-to-frame changes
+    pub changes: usize,
+    pub distinct_values: usize,
+    pub min: u8,
+    pub max: u8,
+    pub last: u8,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ByteChange {
+    pub offset: usize,
+    pub before: u8,
+    pub after: u8,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CommandEffect {
+    pub timestamp_ms: u64,
+    /// Command name, or the hex type byte for unknown commands
+    pub command: String,
+    /// Parameter bytes as hex
+    pub parameters: String,
+    /// Unmapped bytes that differ between the last frame before the command
+    /// and the last frame within the correlation window after it
+    pub changes: Vec<ByteChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ExplorerReport {
+    /// Telemetry frames in the buffer
+    pub frames: usize,
+    /// Commands in the buffer
+    pub commands: usize,
+    /// Unmapped bytes that changed at least once, most active first
+    pub changing_unmapped_bytes: Vec<ByteActivity>,
+    pub command_effects: Vec<CommandEffect>,
+    /// Latest telemetry frame as hex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest_frame: Option<String>,
+}
+
+/// Ring buffers of raw telemetry frames and sent commands
+#[derive(Debug, Default)]
+pub struct ProtocolExplorer {
+    frames: VecDeque<TimedBytes>,
+    commands: VecDeque<TimedBytes>,
+}
+
+impl ProtocolExplorer {
+    pub const fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+            commands: VecDeque::new(),
+        }
+    }
+
+    /// Keep telemetry notifications and sent commands, ignore everything else
+    pub fn observe(&mut self, record: &CaptureRecord) {
+        let entry = TimedBytes {
+            timestamp_ms: record.timestamp_ms,
+            data: record.data.clone(),
+        };
+
+        match record.direction {
+            Direction::Rx if is_telemetry(&record.data) => {
+                push_bounded(&mut self.frames, entry, MAX_FRAMES)
+            }
+            Direction::Tx => push_bounded(&mut self.commands, entry, MAX_COMMANDS),
+            Direction::Rx => {}
+        }
+    }
+
+    /// Diff the buffered frames, treating the fields `model` decodes as mapped
+    pub fn report(&self, model: &ModelLayout) -> ExplorerReport {
+        let mapped = model.telemetry.mapped_offsets();
+        let mut changing_unmapped_bytes: Vec<ByteActivity> =
+            unmapped_offsets(&self.frames, &mapped)
+                .filter_map(|offset| self.activity(offset))
+                .filter(|activity| activity.changes > 0)
+                .collect();
+        changing_unmapped_bytes
+            .sort_by(|a, b| b.changes.cmp(&a.changes).then(a.offset.cmp(&b.offset)));
+
+        let command_effects = self
+            .commands
+            .iter()
+            .filter_map(|command| self.effect(command, &mapped))
+            .collect();
+
+        ExplorerReport {
+            frames: self.frames.len(),
+            commands: self.commands.len(),
+            changing_unmapped_bytes,
+            command_effects,
+            latest_frame: self.frames.back().map(|f| to_hex(&f.data)),
+        }
+    }
+
+    fn activity(&self, offset: usize) -> Option<ByteActivity> {
+        let values: Vec<u8> = self
+            .frames
+            .iter()
+            .filter_map(|f| f.data.get(offset).copied())
+            .collect();
+        let last = *values.last()?;
+
+        Some(ByteActivity {
+            offset,
+            changes: values.windows(2).filter(|w| w[0] != w[1]).count(),
+            distinct_values: values.iter().collect::<BTreeSet<_>>().len(),
+            min: *values.iter().min()?,
+            max: *values.iter().max()?,
+            last,
+        })
+    }
+
+    fn effect(&self, command: &TimedBytes, mapped: &[Range<usize>]) -> Option<CommandEffect> {
+        let (name, parameters) = match RawCommand::from_bytes(&command.data) {
+            Ok(raw) => (
+                raw.known_type()
+                    .map(|t| t.as_str().to_string())
+                    .unwrap_or_else(|| format!("0x{:02x}", raw.command_type)),
+                to_hex(&raw.parameters),
+            ),
+            Err(_) => ("unparseable".to_string(), to_hex(&command.data)),
+        };
+
+        let before = self
+            .frames
+            .iter()
+            .rev()
+            .find(|f| f.timestamp_ms <= command.timestamp_ms)?;
+        let after = self.frames.iter().rev().find(|f| {
+            f.timestamp_ms > command.timestamp_ms
+                && f.timestamp_ms <= command.timestamp_ms + CORRELATION_WINDOW_MS
+        })?;
+
+        let changes = (0..before.data.len().min(after.data.len()))
+            .filter(|offset| !is_mapped(mapped, *offset))
+            .filter(|&offset| before.data[offset] != after.data[offset])
+            .map(|offset| ByteChange {
+                offset,
+                before: before.data[offset],
+                after: after.data[offset],
+            })
+            .collect();
+
+        Some(CommandEffect {
+            timestamp_ms: command.timestamp_ms,
+            command: name,
+            parameters,
+            changes,
+        })
+    }
+}
+
+fn is_telemetry(data: &[u8]) -> bool {
+    data.len() > 6
+        && data[5] == PacketType::Telemetry as u8
+        && data[6] == TelemetryType::Telemetry as u8
+}
+
+fn is_mapped(mapped: &[Range<usize>], offset: usize) -> bool {
+    mapped.iter().any(|r| r.contains(&offset))
+}
+
+fn unmapped_offsets<'a>(
+    frames: &VecDeque<TimedBytes>,
+    mapped: &'a [Range<usize>],
+) -> impl Iterator<Item = usize> + 'a {
+    let length = frames.iter().map(|f| f.data.len()).max().unwrap_or(0);
+    (0..length).filter(|offset| !is_mapped(mapped, *offset))
+}
+
+fn push_bounded(buffer: &mut VecDeque<TimedBytes>, entry: TimedBytes, max: usize) {
+    if buffer.len() == max {
+        buffer.pop_front();
+    }
+    buffer.push_back(entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ble::command::{AnkerCommand, LedCommand};
+    use crate::ble::model;
+
+    fn telemetry_frame() -> Vec<u8> {
+        crate::battery::sample_telemetry(50, 0, 100).to_bytes()
+    }
+
+    /// First two offsets the default model doesn't decode
+    fn unmapped_pair() -> (usize, usize) {
+        let mapped = model::default_model().telemetry.mapped_offsets();
+        let mut offsets = (0..telemetry_frame().len()).filter(|o| !is_mapped(&mapped, *o));
+        (offsets.next().unwrap(), offsets.next().unwrap())
+    }
+
+    fn frame_at(timestamp_ms: u64, changes: &[(usize, u8)]) -> CaptureRecord {
+        let mut data = telemetry_frame();
+        for &(offset, value) in changes {
+            data[offset] = value;
+        }
+        CaptureRecord {
+            timestamp_ms,
+            direction: Direction::Rx,
+            data,
+        }
+    }
+
+    fn command_at(timestamp_ms: u64) -> CaptureRecord {
+        CaptureRecord {
+            timestamp_ms,
+            direction: Direction::Tx,
+            data: AnkerCommand::Led(LedCommand::new(1).unwrap()).to_bytes(),
+        }
+    }
+
+    #[test]
+    fn report_skips_mapped_offsets() {
+        let layout = model::default_model();
+        let (unmapped, _) = unmapped_pair();
+        let mapped = layout.telemetry.ac_outlet.watts;
+
+        let mut explorer = ProtocolExplorer::new();
+        explorer.observe(&frame_at(0, &[]));
+        explorer.observe(&frame_at(1_000, &[(unmapped, 0x55), (mapped, 0x55)]));
+
+        let report = explorer.report(layout);
+        let offsets: Vec<usize> = report
+            .changing_unmapped_bytes
+            .iter()
+            .map(|a| a.offset)
+            .collect();
+        assert_eq!(offsets, vec![unmapped]);
+        assert_eq!(report.frames, 2);
+    }
+
+    #[test]
+    fn activity_counts_changes_and_range() {
+        let (offset, _) = unmapped_pair();
+        let mut explorer = ProtocolExplorer::new();
+        for (i, value) in [3, 3, 9, 1, 9].into_iter().enumerate() {
+            explorer.observe(&frame_at(i as u64 * 1_000, &[(offset, value)]));
+        }
+
+        let activity = explorer.activity(offset).unwrap();
+        assert_eq!(activity.changes, 3);
+        assert_eq!(activity.distinct_values, 3);
+        assert_eq!((activity.min, activity.max, activity.last), (1, 9, 9));
+        assert!(ProtocolExplorer::new().activity(offset).is_none());
+    }
+
+    #[test]
+    fn effect_only_looks_inside_the_correlation_window() {
+        let layout = model::default_model();
+        let (first, second) = unmapped_pair();
+
+        let mut explorer = ProtocolExplorer::new();
+        explorer.observe(&frame_at(10_000, &[(first, 0), (second, 0)]));
+        explorer.observe(&command_at(10_500));
+        explorer.observe(&frame_at(11_000, &[(first, 1), (second, 0)]));
+        // Too late to count as an effect of the command
+        explorer.observe(&frame_at(
+            10_500 + CORRELATION_WINDOW_MS + 1,
+            &[(first, 1), (second, 2)],
+        ));
+
+        let report = explorer.report(layout);
+        let [effect] = report.command_effects.as_slice() else {
+            panic!("{:?}", report.command_effects);
+        };
+        assert_eq!(effect.command, "led");
+        let changes: Vec<(usize, u8, u8)> = effect
+            .changes
+            .iter()
+            .map(|c| (c.offset, c.before, c.after))
+            .collect();
+        assert_eq!(changes, vec![(first, 0, 1)]);
+    }
+
+    #[test]
+    fn effect_needs_frames_on_both_sides() {
+        let mut explorer = ProtocolExplorer::new();
+        explorer.observe(&command_at(0));
+        explorer.observe(&frame_at(1_000, &[]));
+        assert!(explorer
+            .report(model::default_model())
+            .command_effects
+            .is_empty());
+    }
+
+    #[test]
+    fn buffers_drop_the_oldest_entries() {
+        let mut explorer = ProtocolExplorer::new();
+        for i in 0..MAX_FRAMES as u64 + 3 {
+            explorer.observe(&frame_at(i, &[]));
+        }
+        for i in 0..MAX_COMMANDS as u64 + 2 {
+            explorer.observe(&command_at(i));
+        }
+
+        assert_eq!(explorer.frames.len(), MAX_FRAMES);
+        assert_eq!(explorer.frames.front().unwrap().timestamp_ms, 3);
+        assert_eq!(explorer.commands.len(), MAX_COMMANDS);
+        assert_eq!(explorer.commands.front().unwrap().timestamp_ms, 2);
+        assert_eq!(
+            explorer.commands.back().unwrap().timestamp_ms,
+            MAX_COMMANDS as u64 + 1
+        );
+    }
+
+    #[test]
+    fn only_telemetry_notifications_are_kept() {
+        let mut explorer = ProtocolExplorer::new();
+        let mut state_ack = frame_at(0, &[]);
+        state_ack.data[6] = TelemetryType::StateAck as u8;
+        explorer.observe(&state_ack);
+        assert_eq!(explorer.frames.len(), 0);
+    }
+}
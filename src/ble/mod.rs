@@ -1,7 +1,18 @@
-pub mod command;
+pub mod btsnoop;
+pub mod capture;
+#[cfg(feature = "ble")]
 pub mod device;
-pub mod telemetry;
+pub mod explorer;
+#[cfg(feature = "ble")]
+pub mod probe;
+pub mod rejected;
+
+pub use anker_767_protocol::{command, framing, model, telemetry, units};
 
 pub use command::{AnkerCommand, CommandType};
-pub use device::{send_command, AnkerDevice, ConnectionState, DeviceError, DeviceState, SetState};
-pub use telemetry::{StateAck, Telemetry};
+#[cfg(feature = "ble")]
+pub use device::{
+    scan, AnkerDevice, ConnectionState, DeviceError, DeviceEvent, DeviceSettingsReport,
+    DeviceState, DiscoveredDevice, ReportedSetting, SetState, SettingSource,
+};
+pub use telemetry::{DeviceSettings, StateAck, Telemetry};
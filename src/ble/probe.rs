@@ -0,0 +1,208 @@
+//! Raw command probing for protocol research.
+//!
+//! Sends an arbitrary command type and parameters with the usual framing and
+//! collects whatever the device notifies within a time window, so undocumented
+//! command types can be tried without adding them to `CommandType` first.
+
+use crate::ble::capture::to_hex;
+use crate::ble::command::RawCommand;
+use crate::ble::device::{AnkerDevice, DeviceError};
+use crate::ble::telemetry::{PacketType, TelemetryType};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout_at;
+
+/// Upper bound on how long a probe listens
+pub const MAX_PROBE_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FrameKind {
+    Telemetry,
+    StateAck,
+    CommandAck,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ProbeFrame {
+    /// Milliseconds after the command was written
+    pub elapsed_ms: u64,
+    pub kind: FrameKind,
+    /// Command type byte acknowledged, for `command_ack` frames
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acknowledged_type: Option<u8>,
+    /// Frame as hex
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ProbeResult {
+    /// Frame written, as hex
+    pub sent: String,
+    pub frames: Vec<ProbeFrame>,
+}
+
+/// Classify a notification from its header bytes only, so frames the full
+/// parser rejects (unknown command types) still show up
+fn classify(data: &[u8]) -> (FrameKind, Option<u8>) {
+    let (Some(&packet_type), Some(&id)) = (data.get(5), data.get(6)) else {
+        return (FrameKind::Unknown, None);
+    };
+    match packet_type {
+        t if t == PacketType::Telemetry as u8 && id == TelemetryType::Telemetry as u8 => {
+            (FrameKind::Telemetry, None)
+        }
+        t if t == PacketType::Telemetry as u8 && id == TelemetryType::StateAck as u8 => {
+            (FrameKind::StateAck, None)
+        }
+        t if t == PacketType::CommandAck as u8 => (FrameKind::CommandAck, Some(id)),
+        _ => (FrameKind::Unknown, None),
+    }
+}
+
+/// Send `command` and collect the notifications `device` receives within `window`.
+/// Telemetry frames are dropped unless `include_telemetry` is set.
+pub async fn probe(
+    device: &AnkerDevice,
+    command: &RawCommand,
+    window: Duration,
+    include_telemetry: bool,
+) -> Result<ProbeResult, DeviceError> {
+    // Subscribe before writing so a fast ack isn't missed
+    let mut rx = device.subscribe_frames();
+    device.send_raw(command).await?;
+
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::now() + window.min(MAX_PROBE_WINDOW);
+    let mut received = Vec::new();
+
+    loop {
+        let data = match timeout_at(deadline, rx.recv()).await {
+            Ok(Ok(data)) => data,
+            Ok(Err(RecvError::Lagged(_))) => continue,
+            Ok(Err(RecvError::Closed)) | Err(_) => break,
+        };
+
+        let (kind, acknowledged_type) = classify(&data);
+        if kind == FrameKind::Telemetry && !include_telemetry {
+            continue;
+        }
+        received.push(ProbeFrame {
+            elapsed_ms: start.elapsed().as_millis() as u64,
+            kind,
+            acknowledged_type,
+            data: to_hex(&data),
+        });
+    }
+
+    Ok(ProbeResult {
+        sent: to_hex(&command.to_bytes()),
+        frames: received,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ble::command::CommandType;
+    use crate::ble::telemetry::{CommandAck, LedState, StateAck};
+
+    fn command_ack(command_type: CommandType) -> Vec<u8> {
+        CommandAck { command_type }.to_bytes()
+    }
+
+    #[test]
+    fn classify_reads_the_header_only() {
+        let telemetry = crate::battery::sample_telemetry(50, 0, 100).to_bytes();
+        assert_eq!(classify(&telemetry), (FrameKind::Telemetry, None));
+
+        let state_ack = StateAck {
+            ac_outlet_on: true,
+            twelve_volt_on: false,
+            power_save_on: false,
+            led_state: LedState::Off,
+        }
+        .to_bytes();
+        assert_eq!(classify(&state_ack), (FrameKind::StateAck, None));
+
+        assert_eq!(
+            classify(&command_ack(CommandType::Led)),
+            (FrameKind::CommandAck, Some(CommandType::Led as u8))
+        );
+
+        // An unknown command type is still reported with its byte
+        let mut unknown_ack = command_ack(CommandType::Led);
+        unknown_ack[6] = 0x42;
+        assert_eq!(classify(&unknown_ack), (FrameKind::CommandAck, Some(0x42)));
+
+        let mut unknown_packet = telemetry.clone();
+        unknown_packet[5] = 0x7f;
+        assert_eq!(classify(&unknown_packet), (FrameKind::Unknown, None));
+        assert_eq!(classify(&telemetry[..6]), (FrameKind::Unknown, None));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn probe_collects_frames_until_the_window_closes() {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        let command = RawCommand::new(0x42, vec![0x01, 0x02]).unwrap();
+
+        let frames = device.frames();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let _ = frames.send(crate::battery::sample_telemetry(50, 0, 100).to_bytes());
+            let _ = frames.send(command_ack(CommandType::Led));
+            // Outside the window
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            let _ = frames.send(command_ack(CommandType::AcOutput));
+        });
+
+        let result = probe(&device, &command, Duration::from_secs(1), false)
+            .await
+            .unwrap();
+
+        assert_eq!(*written.lock().unwrap(), vec![command.to_bytes()]);
+        assert_eq!(result.sent, to_hex(&command.to_bytes()));
+        assert_eq!(result.frames.len(), 1, "{:?}", result.frames);
+        assert_eq!(result.frames[0].kind, FrameKind::CommandAck);
+        assert_eq!(
+            result.frames[0].acknowledged_type,
+            Some(CommandType::Led as u8)
+        );
+        assert_eq!(
+            result.frames[0].data,
+            to_hex(&command_ack(CommandType::Led))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn probe_includes_telemetry_on_request() {
+        let (device, _written) = AnkerDevice::fake_connected().await;
+        let command = RawCommand::new(0x42, Vec::new()).unwrap();
+
+        let frames = device.frames();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let _ = frames.send(crate::battery::sample_telemetry(50, 0, 100).to_bytes());
+        });
+
+        let result = probe(&device, &command, Duration::from_secs(1), true)
+            .await
+            .unwrap();
+        let kinds: Vec<FrameKind> = result.frames.iter().map(|f| f.kind).collect();
+        assert_eq!(kinds, vec![FrameKind::Telemetry]);
+    }
+
+    #[tokio::test]
+    async fn probe_fails_when_disconnected() {
+        let device = AnkerDevice::new();
+        let command = RawCommand::new(0x42, Vec::new()).unwrap();
+        assert!(probe(&device, &command, Duration::from_millis(10), false)
+            .await
+            .is_err());
+    }
+}
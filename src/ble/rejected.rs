@@ -0,0 +1,118 @@
+//! Recently rejected notifications.
+//!
+//! Frames the parser couldn't handle are kept with their error context so a
+//! bug report can include the exact bytes (`GET /api/debug/rejected-frames`).
+
+use crate::ble::capture::to_hex;
+use crate::ble::telemetry::ParseError;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_REJECTED: usize = 32;
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RejectedFrame {
+    /// Unix time in milliseconds
+    pub timestamp_ms: u64,
+    pub error: String,
+    /// Error variant, as in the `anker_parse_errors_total` metric
+    pub variant: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packet_type: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telemetry_id: Option<u8>,
+    /// Offset of the offending byte
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    /// Frame as hex (the whole notification for reassembly errors)
+    pub data: String,
+}
+
+/// The last `MAX_REJECTED` rejected frames
+#[derive(Debug, Default)]
+pub struct RejectedFrames {
+    frames: Mutex<VecDeque<RejectedFrame>>,
+}
+
+impl RejectedFrames {
+    /// Remember a rejected frame, dropping the oldest beyond `MAX_REJECTED`
+    pub fn record(&self, error: &ParseError) {
+        let frame = RejectedFrame {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            error: error.error.to_string(),
+            variant: error.error.variant().to_string(),
+            packet_type: error.packet_type,
+            telemetry_id: error.telemetry_id,
+            offset: error.offset,
+            data: to_hex(&error.frame),
+        };
+
+        let mut frames = self.frames.lock().unwrap_or_else(|e| e.into_inner());
+        if frames.len() == MAX_REJECTED {
+            frames.pop_front();
+        }
+        frames.push_back(frame);
+    }
+
+    /// Rejected frames, oldest first
+    pub fn recent(&self) -> Vec<RejectedFrame> {
+        let frames = self.frames.lock().unwrap_or_else(|e| e.into_inner());
+        frames.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ble::model;
+    use crate::ble::telemetry::TelemetryError;
+
+    fn unknown_type(id: u8) -> ParseError {
+        let mut frame = crate::battery::sample_telemetry(50, 0, 100).to_bytes();
+        frame[6] = id;
+        ParseError::new(
+            TelemetryError::UnknownTelemetryType(id),
+            &frame,
+            model::default_model(),
+        )
+    }
+
+    #[test]
+    fn snapshot_keeps_error_context() {
+        let rejected = RejectedFrames::default();
+        assert!(rejected.recent().is_empty());
+
+        let error = unknown_type(0x42);
+        rejected.record(&error);
+
+        let [frame] = rejected.recent().try_into().unwrap();
+        assert_eq!(frame.error, "Unknown telemetry type: 0x42");
+        assert_eq!(frame.variant, "unknown_telemetry_type");
+        assert_eq!(frame.packet_type, error.packet_type);
+        assert_eq!(frame.telemetry_id, Some(0x42));
+        assert_eq!(frame.data, to_hex(&error.frame));
+        assert!(frame.timestamp_ms > 0);
+    }
+
+    #[test]
+    fn oldest_frames_are_dropped_first() {
+        let rejected = RejectedFrames::default();
+        for id in 0..MAX_REJECTED as u8 + 5 {
+            rejected.record(&unknown_type(id));
+        }
+
+        let ids: Vec<u8> = rejected
+            .recent()
+            .iter()
+            .map(|f| f.telemetry_id.unwrap())
+            .collect();
+        let expected: Vec<u8> = (5..MAX_REJECTED as u8 + 5).collect();
+        assert_eq!(ids, expected);
+    }
+}
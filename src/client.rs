@@ -0,0 +1,284 @@
+//! Typed async client for embedding the BLE connection in other programs.
+//! Owns its own `AnkerDevice` and connection task; nothing is shared globally.
+
+use crate::ble::command::{
+    AcOutputCommand, AcTimerCommand, CommandError, LedCommand, PowerSaveCommand,
+    RechargePowerCommand, ScreenBrightnessCommand, ScreenTimeoutCommand, TwelveVoltOutputCommand,
+    TwelveVoltTimerCommand,
+};
+use crate::ble::telemetry::LedState;
+use crate::ble::{
+    AnkerCommand, AnkerDevice, ConnectionState, DeviceError, DeviceSettingsReport, Telemetry,
+};
+use futures::Stream;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+    #[error(transparent)]
+    Command(#[from] CommandError),
+    #[error("Connection task stopped")]
+    Stopped,
+}
+
+/// Connection to one PowerHouse. Connects in the background and reconnects
+/// on its own; dropping the client stops the connection task.
+pub struct AnkerClient {
+    device: Arc<AnkerDevice>,
+    task: JoinHandle<()>,
+}
+
+impl AnkerClient {
+    /// Connect to the first PowerHouse found. Must be called within a Tokio runtime.
+    // No `Default`: constructing a client spawns the connection task
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::with_device(AnkerDevice::new())
+    }
+
+    /// Run `device`, e.g. one built with `AnkerDevice::new().with_model(..)`
+    pub fn with_device(device: AnkerDevice) -> Self {
+        let device = Arc::new(device);
+        let task = tokio::spawn({
+            let device = Arc::clone(&device);
+            async move {
+                if let Err(e) = device.run().await {
+                    error!("BLE device error: {}", e);
+                }
+            }
+        });
+        Self { device, task }
+    }
+
+    /// The underlying device, for subscribing to events or raw frames
+    pub fn device(&self) -> &Arc<AnkerDevice> {
+        &self.device
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        *self.device.subscribe_state().borrow()
+    }
+
+    /// Wait until the device is connected and subscribed to notifications
+    pub async fn wait_connected(&self) -> Result<(), ClientError> {
+        self.device
+            .subscribe_state()
+            .wait_for(|s| *s == ConnectionState::Connected)
+            .await
+            .map_err(|_| ClientError::Stopped)?;
+        Ok(())
+    }
+
+    /// Telemetry as it arrives. Frames missed by a slow consumer are skipped.
+    pub fn telemetry_stream(&self) -> impl Stream<Item = Telemetry> + Send + 'static {
+        let rx = self.device.subscribe_telemetry();
+        futures::stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(telemetry) => return Some((telemetry, rx)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Wait for the next telemetry frame
+    pub async fn next_telemetry(&self) -> Result<Telemetry, ClientError> {
+        let mut rx = self.device.subscribe_telemetry();
+        loop {
+            match rx.recv().await {
+                Ok(telemetry) => return Ok(telemetry),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return Err(ClientError::Stopped),
+            }
+        }
+    }
+
+    /// Latest telemetry, if any has been received
+    pub async fn telemetry(&self) -> Option<Telemetry> {
+        self.device.state().read().await.last_telemetry.clone()
+    }
+
+    /// Current settings, device-reported where possible
+    pub async fn settings(&self) -> DeviceSettingsReport {
+        self.device.state().read().await.settings()
+    }
+
+    /// Send any command; rejected if the connected model doesn't support it
+    pub async fn send(&self, command: AnkerCommand) -> Result<(), ClientError> {
+        Ok(self.device.send_command(command).await?)
+    }
+
+    /// Send a command and wait up to `wait` for the device to acknowledge it
+    pub async fn send_acked(
+        &self,
+        command: AnkerCommand,
+        wait: Duration,
+    ) -> Result<(), ClientError> {
+        Ok(self.device.send_command_acked(command, wait).await?)
+    }
+
+    pub async fn set_ac_output(&self, is_on: bool) -> Result<(), ClientError> {
+        self.send(AnkerCommand::AcOutput(AcOutputCommand::new(is_on)))
+            .await
+    }
+
+    pub async fn set_twelve_volt_output(&self, is_on: bool) -> Result<(), ClientError> {
+        self.send(AnkerCommand::TwelveVoltOutput(
+            TwelveVoltOutputCommand::new(is_on),
+        ))
+        .await
+    }
+
+    pub async fn set_power_save(&self, is_on: bool) -> Result<(), ClientError> {
+        self.send(AnkerCommand::PowerSave(PowerSaveCommand::new(is_on)))
+            .await
+    }
+
+    /// Set the LED strip; `LedState::Unknown` is rejected
+    pub async fn set_led(&self, state: LedState) -> Result<(), ClientError> {
+        if let LedState::Unknown(value) = state {
+            return Err(CommandError::InvalidValue(format!("unknown LED state {}", value)).into());
+        }
+        let command = LedCommand::new(u8::from(state))?;
+        self.send(AnkerCommand::Led(command)).await
+    }
+
+    /// Brightness level 0-3
+    pub async fn set_screen_brightness(&self, level: u8) -> Result<(), ClientError> {
+        let command = ScreenBrightnessCommand::new(level)?;
+        self.send(AnkerCommand::ScreenBrightness(command)).await
+    }
+
+    pub async fn set_screen_timeout(&self, seconds: u16) -> Result<(), ClientError> {
+        self.send(AnkerCommand::ScreenTimeout(ScreenTimeoutCommand::new(
+            seconds,
+        )))
+        .await
+    }
+
+    /// Recharge power in watts (200-1440)
+    pub async fn set_recharge_power(&self, watts: u16) -> Result<(), ClientError> {
+        let command = RechargePowerCommand::new(watts)?;
+        self.send(AnkerCommand::RechargePower(command)).await
+    }
+
+    /// Device auto-off timer for the AC outlets; 0 disables it
+    pub async fn set_ac_timer(&self, seconds: u16) -> Result<(), ClientError> {
+        self.send(AnkerCommand::AcTimer(AcTimerCommand::new(seconds)))
+            .await
+    }
+
+    /// Device auto-off timer for the 12V outputs; 0 disables it
+    pub async fn set_twelve_volt_timer(&self, seconds: u16) -> Result<(), ClientError> {
+        self.send(AnkerCommand::TwelveVoltTimer(TwelveVoltTimerCommand::new(
+            seconds,
+        )))
+        .await
+    }
+
+    /// Stop the connection task and disconnect from the device
+    pub async fn disconnect(mut self) -> Result<(), ClientError> {
+        self.task.abort();
+        // Wait for the task to stop so it can't reconnect behind our back
+        let _ = (&mut self.task).await;
+        Ok(self.device.disconnect().await?)
+    }
+}
+
+impl Drop for AnkerClient {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ble::command::CommandType;
+
+    type Written = Arc<std::sync::Mutex<Vec<Vec<u8>>>>;
+
+    /// A client around `device` with no BLE connection task
+    fn client(device: Arc<AnkerDevice>) -> AnkerClient {
+        AnkerClient {
+            device,
+            task: tokio::spawn(std::future::pending()),
+        }
+    }
+
+    async fn connected_client() -> (AnkerClient, Written) {
+        let (device, written) = AnkerDevice::fake_connected().await;
+        (client(device), written)
+    }
+
+    #[tokio::test]
+    async fn wait_connected_resolves_once_connected() {
+        let device = Arc::new(AnkerDevice::new());
+        let client = client(Arc::clone(&device));
+        assert_ne!(client.connection_state(), ConnectionState::Connected);
+
+        let waiting = tokio::spawn(async move { client.wait_connected().await });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        device.fake_reconnect(&Written::default()).await;
+        waiting.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn unknown_led_state_is_not_sent() {
+        let (client, written) = connected_client().await;
+
+        for value in [2, 9] {
+            let result = client.set_led(LedState::Unknown(value)).await;
+            assert!(
+                matches!(result, Err(ClientError::Command(_))),
+                "{:?}",
+                result
+            );
+        }
+        assert!(written.lock().unwrap().is_empty());
+
+        client.set_led(LedState::High).await.unwrap();
+        assert_eq!(
+            *written.lock().unwrap(),
+            vec![AnkerCommand::Led(LedCommand::new(3).unwrap()).to_bytes()]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn send_acked_times_out_without_an_ack() {
+        let (client, written) = connected_client().await;
+        let command = AnkerCommand::AcOutput(AcOutputCommand::new(true));
+
+        let result = client
+            .send_acked(command.clone(), Duration::from_secs(2))
+            .await;
+        assert!(
+            matches!(
+                result,
+                Err(ClientError::Device(DeviceError::NoAck(
+                    CommandType::AcOutput
+                )))
+            ),
+            "{:?}",
+            result
+        );
+        assert_eq!(*written.lock().unwrap(), vec![command.to_bytes()]);
+    }
+
+    #[tokio::test]
+    async fn send_fails_when_disconnected() {
+        let client = client(Arc::new(AnkerDevice::new()));
+        assert!(client.set_ac_output(true).await.is_err());
+    }
+}
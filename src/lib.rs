@@ -1,4 +1,11 @@
+#[cfg(any(feature = "server", feature = "cli"))]
 pub mod api;
+#[cfg(feature = "ble")]
+pub mod automation;
+pub mod battery;
 pub mod ble;
+#[cfg(feature = "ble")]
+pub mod client;
+#[cfg(feature = "metrics")]
 pub mod metrics;
 pub mod ui;
@@ -1,15 +1,23 @@
 //! Anker PowerHouse 767 BLE Web Server
 
 use anker_767_ble_webserver::api::{self, AppState};
-use anker_767_ble_webserver::ble::{AnkerDevice, Telemetry};
+use anker_767_ble_webserver::battery::BatteryHealthTracker;
+use anker_767_ble_webserver::ble::capture::{self, CaptureWriter};
+use anker_767_ble_webserver::ble::device::REPLAY_SPEED_RANGE;
+use anker_767_ble_webserver::ble::telemetry::ParseMode;
+use anker_767_ble_webserver::ble::{model, AnkerDevice, ConnectionState, Telemetry};
 use anker_767_ble_webserver::metrics;
-use axum::routing::{get, post};
+use axum::routing::{get, post, put};
 use axum::Router;
+use std::future::IntoFuture;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
-use tracing::info;
+use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
@@ -19,6 +27,12 @@ use utoipa_swagger_ui::SwaggerUi;
     paths(
         api::get_status,
         api::get_telemetry,
+        api::get_battery_estimate,
+        api::get_battery_health,
+        api::get_events,
+        api::get_protocol_explorer,
+        api::get_rejected_frames,
+        api::send_raw_command,
         api::get_device_state,
         api::set_power_save,
         api::set_ac_output,
@@ -29,6 +43,12 @@ use utoipa_swagger_ui::SwaggerUi;
         api::set_screen_timeout,
         api::set_ac_timer,
         api::set_twelve_volt_timer,
+        api::list_duty_cycles,
+        api::start_duty_cycle,
+        api::cancel_duty_cycle,
+        api::list_output_timers,
+        api::start_output_timer,
+        api::cancel_output_timer,
     ),
     components(schemas(
         api::StatusResponse,
@@ -39,17 +59,47 @@ use utoipa_swagger_ui::SwaggerUi;
         api::LedRequest,
         api::WattsRequest,
         api::SecondsRequest,
+        anker_767_ble_webserver::automation::OutputPort,
+        anker_767_ble_webserver::automation::DutyCycleConfig,
+        anker_767_ble_webserver::automation::DutyCyclePhase,
+        anker_767_ble_webserver::automation::DutyCycleStatus,
+        anker_767_ble_webserver::automation::OutputTimerRequest,
+        anker_767_ble_webserver::automation::OutputTimerStatus,
         Telemetry,
+        anker_767_ble_webserver::ble::telemetry::VersionedTelemetry,
+        anker_767_ble_webserver::battery::BatteryEstimate,
+        anker_767_ble_webserver::battery::TimeEstimate,
+        anker_767_ble_webserver::battery::BatteryHealth,
+        anker_767_ble_webserver::battery::PackHealth,
+        anker_767_ble_webserver::battery::TemperatureExposure,
         anker_767_ble_webserver::ble::telemetry::Output,
         anker_767_ble_webserver::ble::telemetry::Battery,
         anker_767_ble_webserver::ble::telemetry::BatteryState,
         anker_767_ble_webserver::ble::telemetry::LedState,
         anker_767_ble_webserver::ble::telemetry::StateAck,
+        anker_767_ble_webserver::ble::units::Watts,
+        anker_767_ble_webserver::ble::units::Percentage,
+        anker_767_ble_webserver::ble::units::Celsius,
+        anker_767_ble_webserver::ble::units::Seconds,
+        anker_767_ble_webserver::ble::DeviceEvent,
+        anker_767_ble_webserver::ble::DeviceSettingsReport,
+        anker_767_ble_webserver::ble::SettingSource,
+        anker_767_ble_webserver::ble::explorer::ExplorerReport,
+        anker_767_ble_webserver::ble::explorer::ByteActivity,
+        anker_767_ble_webserver::ble::explorer::ByteChange,
+        anker_767_ble_webserver::ble::explorer::CommandEffect,
+        api::RawCommandRequest,
+        anker_767_ble_webserver::ble::probe::ProbeResult,
+        anker_767_ble_webserver::ble::probe::ProbeFrame,
+        anker_767_ble_webserver::ble::rejected::RejectedFrame,
+        anker_767_ble_webserver::ble::probe::FrameKind,
     )),
     tags(
         (name = "status", description = "Connection status"),
         (name = "telemetry", description = "Device telemetry"),
-        (name = "commands", description = "Device commands")
+        (name = "commands", description = "Device commands"),
+        (name = "automation", description = "Server-side output automation"),
+        (name = "debug", description = "Protocol debugging")
     ),
     info(
         title = "Anker PowerHouse 767 API",
@@ -70,32 +120,149 @@ async fn main() {
 
     info!("Starting Anker PowerHouse 767 BLE Web Server");
 
-    // Create BLE device manager
-    let device = Arc::new(AnkerDevice::new());
-    let state: AppState = device.state();
+    // Create BLE device manager; ANKER_MODEL skips model detection
+    let mut device = match std::env::var("ANKER_MODEL") {
+        Ok(name) => {
+            let layout = model::by_model(&name).unwrap_or_else(|| {
+                let known: Vec<_> = model::MODELS.iter().map(|m| m.model).collect();
+                tracing::error!(
+                    "Unknown ANKER_MODEL {}, expected one of {}",
+                    name,
+                    known.join(", ")
+                );
+                std::process::exit(1);
+            });
+            info!("Using the {} layout", layout.name);
+            AnkerDevice::new().with_model(layout)
+        }
+        Err(_) => AnkerDevice::new(),
+    };
+    // Keep frames with unknown battery/LED states instead of dropping them
+    if std::env::var("ANKER_LENIENT_PARSING").is_ok_and(|v| v == "1" || v == "true") {
+        info!("Lenient parsing enabled");
+        device = device.with_parse_mode(ParseMode::Lenient);
+    }
+    // Record raw frames if requested
+    if let Ok(path) = std::env::var("ANKER_CAPTURE_FILE") {
+        match CaptureWriter::create(&path) {
+            Ok(writer) => {
+                info!("Recording raw frames to {}", path);
+                device = device.with_recorder(writer);
+            }
+            Err(e) => warn!("Failed to open capture file {}: {}", path, e),
+        }
+    }
+    let device = Arc::new(device);
+
+    // Persistent data (battery health counters) lives in ANKER_DATA_DIR
+    let data_dir = std::env::var("ANKER_DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."));
+    let battery_health = BatteryHealthTracker::load(data_dir.join("battery_health.json"));
+    metrics::update_from_health(battery_health.health());
+
+    let mut state = AppState::new(&device, battery_health);
+
+    // Raw command endpoint for protocol research, off unless a token is set
+    if let Ok(token) = std::env::var("ANKER_RAW_COMMAND_TOKEN") {
+        if token.is_empty() {
+            warn!("ANKER_RAW_COMMAND_TOKEN is empty, raw command endpoint stays disabled");
+        } else {
+            warn!("Raw command endpoint enabled");
+            state = state.with_raw_command_token(token);
+        }
+    }
 
-    // Spawn BLE connection loop
+    // Spawn BLE connection loop, or replay a capture instead of talking to the device
     let device_clone = Arc::clone(&device);
-    tokio::spawn(async move {
-        if let Err(e) = device_clone.run().await {
-            tracing::error!("BLE device error: {}", e);
+    match std::env::var("ANKER_REPLAY_FILE") {
+        Ok(path) => {
+            let records = capture::read_capture(&path).unwrap_or_else(|e| {
+                tracing::error!("Failed to read replay file {}: {}", path, e);
+                std::process::exit(1);
+            });
+            let requested = std::env::var("ANKER_REPLAY_SPEED")
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok())
+                .filter(|s| *s > 0.0)
+                .unwrap_or(1.0);
+            let speed = requested.clamp(*REPLAY_SPEED_RANGE.start(), *REPLAY_SPEED_RANGE.end());
+            if speed != requested {
+                warn!(
+                    "ANKER_REPLAY_SPEED {} out of range, using {}",
+                    requested, speed
+                );
+            }
+            tokio::spawn(async move {
+                if let Err(e) = device_clone.run_replay(records, speed).await {
+                    tracing::error!("Replay error: {}", e);
+                }
+            });
         }
-    });
+        Err(_) => {
+            tokio::spawn(async move {
+                if let Err(e) = device_clone.run().await {
+                    tracing::error!("BLE device error: {}", e);
+                }
+            });
+        }
+    }
 
     // Spawn telemetry metrics updater
     let mut telemetry_rx = device.subscribe_telemetry();
+    let estimator = Arc::clone(&state.estimator);
+    let battery_health = Arc::clone(&state.battery_health);
     tokio::spawn(async move {
-        while let Ok(telemetry) = telemetry_rx.recv().await {
+        loop {
+            // A burst (e.g. a fast replay) only skips frames; stop when the device is gone
+            let telemetry = match telemetry_rx.recv().await {
+                Ok(telemetry) => telemetry,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Telemetry updater lagged, skipped {} frames", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
             metrics::update_from_telemetry(&telemetry);
+
+            let mut estimator = estimator.write().await;
+            estimator.update(&telemetry);
+            if let Some(estimate) = estimator.estimate() {
+                metrics::update_from_estimate(&estimate);
+            }
+            drop(estimator);
+
+            let mut battery_health = battery_health.write().await;
+            battery_health.update(&telemetry);
+            metrics::update_from_health(battery_health.health());
+        }
+    });
+
+    // Spawn battery health persister
+    let battery_health = Arc::clone(&state.battery_health);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = battery_health.write().await.save() {
+                warn!("Failed to save battery health: {}", e);
+            }
         }
     });
 
+    // Kept for the final save on shutdown
+    let battery_health = Arc::clone(&state.battery_health);
+
     // Spawn connection state metrics updater
     let mut state_rx = device.subscribe_state();
     tokio::spawn(async move {
         while state_rx.changed().await.is_ok() {
             let state = *state_rx.borrow();
             metrics::update_connection_state(state);
+            // No telemetry will refresh the estimate until the device is back
+            if state != ConnectionState::Connected {
+                metrics::clear_estimate();
+            }
         }
     });
 
@@ -108,6 +275,12 @@ async fn main() {
     let api_router = Router::new()
         .route("/status", get(api::get_status))
         .route("/telemetry", get(api::get_telemetry))
+        .route("/telemetry/estimate", get(api::get_battery_estimate))
+        .route("/battery-health", get(api::get_battery_health))
+        .route("/events", get(api::get_events))
+        .route("/debug/protocol-explorer", get(api::get_protocol_explorer))
+        .route("/debug/rejected-frames", get(api::get_rejected_frames))
+        .route("/debug/raw-command", post(api::send_raw_command))
         .route("/device-state", get(api::get_device_state))
         .route("/power-save", post(api::set_power_save))
         .route("/ac-output", post(api::set_ac_output))
@@ -118,6 +291,16 @@ async fn main() {
         .route("/screen-timeout", post(api::set_screen_timeout))
         .route("/ac-timer", post(api::set_ac_timer))
         .route("/twelve-volt-timer", post(api::set_twelve_volt_timer))
+        .route("/duty-cycle", get(api::list_duty_cycles))
+        .route(
+            "/duty-cycle/{output}",
+            put(api::start_duty_cycle).delete(api::cancel_duty_cycle),
+        )
+        .route("/output-timer", get(api::list_output_timers))
+        .route(
+            "/output-timer/{output}",
+            put(api::start_output_timer).delete(api::cancel_output_timer),
+        )
         .with_state(state);
 
     let app = Router::new()
@@ -134,5 +317,45 @@ async fn main() {
     info!("Metrics: http://{}/metrics", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // Not a graceful shutdown: open event streams would keep it waiting forever
+    tokio::select! {
+        result = axum::serve(listener, app).into_future() => result.unwrap(),
+        () = shutdown_signal() => info!("Shutting down"),
+    }
+
+    // Counters since the last periodic save would otherwise be lost
+    let saved = battery_health.write().await.save();
+    if let Err(e) = saved {
+        warn!("Failed to save battery health: {}", e);
+    }
+}
+
+/// Resolves on Ctrl-C or SIGTERM (e.g. `docker stop`)
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
 }
@@ -1,6 +1,10 @@
 //! Prometheus metrics for Anker PowerHouse 767.
 
-use crate::ble::{ConnectionState, Telemetry};
+use crate::battery::health::DOD_BUCKET_PERCENT;
+use crate::battery::{BatteryEstimate, BatteryHealth, PackHealth, TimeEstimate};
+#[cfg(feature = "ble")]
+use crate::ble::ConnectionState;
+use crate::ble::Telemetry;
 use prometheus::{
     Encoder, Gauge, GaugeVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
 };
@@ -18,6 +22,16 @@ pub struct Metrics {
     pub battery_remaining_hours: Gauge,
     pub battery_temperature: GaugeVec,
     pub battery_state: IntGauge,
+    pub battery_packs: IntGauge,
+    // Runtime estimates
+    pub battery_net_watts: Gauge,
+    pub battery_time_to_empty_seconds: GaugeVec,
+    pub battery_time_to_full_seconds: GaugeVec,
+    // Battery health
+    pub battery_equivalent_full_cycles: GaugeVec,
+    pub battery_high_soc_seconds: GaugeVec,
+    pub battery_temperature_exposure_seconds: GaugeVec,
+    pub battery_discharge_sessions: GaugeVec,
     // Power totals
     pub total_output_watts: IntGauge,
     pub total_input_watts: IntGauge,
@@ -39,6 +53,9 @@ pub struct Metrics {
     // Connection
     pub connected: IntGauge,
     pub commands_total: IntCounterVec,
+    pub corrupt_frames_total: IntCounterVec,
+    pub parse_errors_total: IntCounterVec,
+    pub unknown_values_total: IntCounterVec,
     /// Per-metric timestamps in milliseconds (metric key -> timestamp)
     pub timestamps: RwLock<HashMap<String, u64>>,
 }
@@ -74,7 +91,75 @@ impl Metrics {
 
         let battery_state = IntGauge::new(
             "anker_battery_state",
-            "Battery state (0=idle, 1=discharging, 2=charging)",
+            "Battery state (0=idle, 1=discharging, 2=charging, else the raw unknown value)",
+        )
+        .unwrap();
+
+        let battery_packs = IntGauge::new(
+            "anker_battery_packs",
+            "Number of battery packs (1 = internal only, 2 = with expansion battery)",
+        )
+        .unwrap();
+
+        // Runtime estimates
+        let battery_net_watts = Gauge::new(
+            "anker_battery_net_watts",
+            "Smoothed net battery power in watts (positive = charging)",
+        )
+        .unwrap();
+
+        let battery_time_to_empty_seconds = GaugeVec::new(
+            Opts::new(
+                "anker_battery_time_to_empty_seconds",
+                "Estimated time until the battery is empty",
+            ),
+            &["bound"],
+        )
+        .unwrap();
+
+        let battery_time_to_full_seconds = GaugeVec::new(
+            Opts::new(
+                "anker_battery_time_to_full_seconds",
+                "Estimated time until the battery is full",
+            ),
+            &["bound"],
+        )
+        .unwrap();
+
+        // Battery health
+        let battery_equivalent_full_cycles = GaugeVec::new(
+            Opts::new(
+                "anker_battery_equivalent_full_cycles",
+                "Equivalent full discharge cycles since tracking started",
+            ),
+            &["battery"],
+        )
+        .unwrap();
+
+        let battery_high_soc_seconds = GaugeVec::new(
+            Opts::new(
+                "anker_battery_high_soc_seconds",
+                "Time spent at or above 90% state-of-charge",
+            ),
+            &["battery"],
+        )
+        .unwrap();
+
+        let battery_temperature_exposure_seconds = GaugeVec::new(
+            Opts::new(
+                "anker_battery_temperature_exposure_seconds",
+                "Time spent above a temperature threshold in celsius",
+            ),
+            &["battery", "threshold"],
+        )
+        .unwrap();
+
+        let battery_discharge_sessions = GaugeVec::new(
+            Opts::new(
+                "anker_battery_discharge_sessions",
+                "Completed discharge sessions by depth-of-discharge bucket",
+            ),
+            &["battery", "depth"],
         )
         .unwrap();
 
@@ -174,12 +259,47 @@ impl Metrics {
         )
         .unwrap();
 
+        let corrupt_frames_total = IntCounterVec::new(
+            Opts::new(
+                "anker_corrupt_frames_total",
+                "Notifications with a bad header, length or checksum, or bytes skipped to resync",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+
+        let parse_errors_total = IntCounterVec::new(
+            Opts::new(
+                "anker_parse_errors_total",
+                "Notifications that failed to reassemble or parse, by error",
+            ),
+            &["error"],
+        )
+        .unwrap();
+
+        let unknown_values_total = IntCounterVec::new(
+            Opts::new(
+                "anker_unknown_values_total",
+                "Frames delivered with an unknown enum value (lenient parsing), by field",
+            ),
+            &["field"],
+        )
+        .unwrap();
+
         // Register all metrics
         registry.register(Box::new(battery_percentage.clone())).unwrap();
         registry.register(Box::new(battery_percentage_individual.clone())).unwrap();
         registry.register(Box::new(battery_remaining_hours.clone())).unwrap();
         registry.register(Box::new(battery_temperature.clone())).unwrap();
         registry.register(Box::new(battery_state.clone())).unwrap();
+        registry.register(Box::new(battery_packs.clone())).unwrap();
+        registry.register(Box::new(battery_net_watts.clone())).unwrap();
+        registry.register(Box::new(battery_time_to_empty_seconds.clone())).unwrap();
+        registry.register(Box::new(battery_time_to_full_seconds.clone())).unwrap();
+        registry.register(Box::new(battery_equivalent_full_cycles.clone())).unwrap();
+        registry.register(Box::new(battery_high_soc_seconds.clone())).unwrap();
+        registry.register(Box::new(battery_temperature_exposure_seconds.clone())).unwrap();
+        registry.register(Box::new(battery_discharge_sessions.clone())).unwrap();
         registry.register(Box::new(total_output_watts.clone())).unwrap();
         registry.register(Box::new(total_input_watts.clone())).unwrap();
         registry.register(Box::new(ac_input_watts.clone())).unwrap();
@@ -195,6 +315,9 @@ impl Metrics {
         registry.register(Box::new(usb_a_watts.clone())).unwrap();
         registry.register(Box::new(connected.clone())).unwrap();
         registry.register(Box::new(commands_total.clone())).unwrap();
+        registry.register(Box::new(corrupt_frames_total.clone())).unwrap();
+        registry.register(Box::new(parse_errors_total.clone())).unwrap();
+        registry.register(Box::new(unknown_values_total.clone())).unwrap();
 
         Self {
             registry,
@@ -203,6 +326,14 @@ impl Metrics {
             battery_remaining_hours,
             battery_temperature,
             battery_state,
+            battery_packs,
+            battery_net_watts,
+            battery_time_to_empty_seconds,
+            battery_time_to_full_seconds,
+            battery_equivalent_full_cycles,
+            battery_high_soc_seconds,
+            battery_temperature_exposure_seconds,
+            battery_discharge_sessions,
             total_output_watts,
             total_input_watts,
             ac_input_watts,
@@ -218,6 +349,9 @@ impl Metrics {
             usb_a_watts,
             connected,
             commands_total,
+            corrupt_frames_total,
+            parse_errors_total,
+            unknown_values_total,
             timestamps: RwLock::new(HashMap::new()),
         }
     }
@@ -244,53 +378,65 @@ pub fn update_from_telemetry(telemetry: &Telemetry) {
     let m = metrics();
 
     // Battery metrics
-    m.battery_percentage.set(telemetry.total_battery_percentage as i64);
+    m.battery_percentage.set(telemetry.total_battery_percentage.0 as i64);
     set_timestamp(m, "anker_battery_percentage");
 
     m.battery_percentage_individual
         .with_label_values(&["internal"])
-        .set(telemetry.internal_battery.percentage as f64);
+        .set(telemetry.internal_battery.percentage.0 as f64);
     set_timestamp(m, "anker_battery_percentage_individual{battery=\"internal\"}");
 
-    m.battery_percentage_individual
-        .with_label_values(&["external"])
-        .set(telemetry.external_battery.percentage as f64);
-    set_timestamp(m, "anker_battery_percentage_individual{battery=\"external\"}");
-
-    m.battery_remaining_hours.set(telemetry.battery_remaining_hours as f64);
+    m.battery_remaining_hours.set(telemetry.battery_remaining.hours() as f64);
     set_timestamp(m, "anker_battery_remaining_hours");
 
     m.battery_temperature
         .with_label_values(&["internal"])
-        .set(telemetry.internal_battery.temperature as f64);
+        .set(telemetry.internal_battery.temperature.0 as f64);
     set_timestamp(m, "anker_battery_temperature{battery=\"internal\"}");
 
-    m.battery_temperature
-        .with_label_values(&["external"])
-        .set(telemetry.external_battery.temperature as f64);
-    set_timestamp(m, "anker_battery_temperature{battery=\"external\"}");
+    // Expansion battery series only exist while a pack is attached
+    m.battery_packs.set(telemetry.battery_packs() as i64);
+    set_timestamp(m, "anker_battery_packs");
+
+    match &telemetry.external_battery {
+        Some(external) => {
+            m.battery_percentage_individual
+                .with_label_values(&["external"])
+                .set(external.percentage.0 as f64);
+            set_timestamp(m, "anker_battery_percentage_individual{battery=\"external\"}");
+
+            m.battery_temperature
+                .with_label_values(&["external"])
+                .set(external.temperature.0 as f64);
+            set_timestamp(m, "anker_battery_temperature{battery=\"external\"}");
+        }
+        None => {
+            let _ = m.battery_percentage_individual.remove_label_values(&["external"]);
+            let _ = m.battery_temperature.remove_label_values(&["external"]);
+        }
+    }
 
-    m.battery_state.set(telemetry.battery_state.clone() as i64);
+    m.battery_state.set(u8::from(telemetry.battery_state) as i64);
     set_timestamp(m, "anker_battery_state");
 
     // Power totals
-    m.total_output_watts.set(telemetry.total_output_watts as i64);
+    m.total_output_watts.set(telemetry.total_output_watts.0 as i64);
     set_timestamp(m, "anker_total_output_watts");
 
-    m.total_input_watts.set(telemetry.total_input_watts as i64);
+    m.total_input_watts.set(telemetry.total_input_watts.0 as i64);
     set_timestamp(m, "anker_total_input_watts");
 
-    m.ac_input_watts.set(telemetry.ac_input_watts as i64);
+    m.ac_input_watts.set(telemetry.ac_input_watts.0 as i64);
     set_timestamp(m, "anker_ac_input_watts");
 
-    m.solar_input_watts.set(telemetry.solar_input_watts as i64);
+    m.solar_input_watts.set(telemetry.solar_input_watts.0 as i64);
     set_timestamp(m, "anker_solar_input_watts");
 
     // AC outlet
     m.ac_outlet_on.set(telemetry.ac_outlet.is_on as i64);
     set_timestamp(m, "anker_ac_outlet_on");
 
-    m.ac_outlet_watts.set(telemetry.ac_outlet.watts as i64);
+    m.ac_outlet_watts.set(telemetry.ac_outlet.watts.0 as i64);
     set_timestamp(m, "anker_ac_outlet_watts");
 
     // 12V outlets (2 ports)
@@ -299,14 +445,14 @@ pub fn update_from_telemetry(telemetry: &Telemetry) {
         m.twelve_volt_on.with_label_values(&[&port]).set(output.is_on as i64 as f64);
         set_timestamp(m, &format!("anker_twelve_volt_on{{port=\"{}\"}}", port));
 
-        m.twelve_volt_watts.with_label_values(&[&port]).set(output.watts as f64);
+        m.twelve_volt_watts.with_label_values(&[&port]).set(output.watts.0 as f64);
         set_timestamp(m, &format!("anker_twelve_volt_watts{{port=\"{}\"}}", port));
     }
 
     // 12V timer (shared between ports, use first)
     if let Some(output) = telemetry.twelve_volt.first() {
-        if let Some(seconds) = output.time_remaining_seconds {
-            m.twelve_volt_timer_seconds.set(seconds as i64);
+        if let Some(time) = output.time_remaining {
+            m.twelve_volt_timer_seconds.set(time.0 as i64);
             set_timestamp(m, "anker_twelve_volt_timer_seconds");
         }
     }
@@ -317,7 +463,7 @@ pub fn update_from_telemetry(telemetry: &Telemetry) {
         m.usb_c_on.with_label_values(&[&port]).set(output.is_on as i64 as f64);
         set_timestamp(m, &format!("anker_usb_c_on{{port=\"{}\"}}", port));
 
-        m.usb_c_watts.with_label_values(&[&port]).set(output.watts as f64);
+        m.usb_c_watts.with_label_values(&[&port]).set(output.watts.0 as f64);
         set_timestamp(m, &format!("anker_usb_c_watts{{port=\"{}\"}}", port));
     }
 
@@ -327,11 +473,96 @@ pub fn update_from_telemetry(telemetry: &Telemetry) {
         m.usb_a_on.with_label_values(&[&port]).set(output.is_on as i64 as f64);
         set_timestamp(m, &format!("anker_usb_a_on{{port=\"{}\"}}", port));
 
-        m.usb_a_watts.with_label_values(&[&port]).set(output.watts as f64);
+        m.usb_a_watts.with_label_values(&[&port]).set(output.watts.0 as f64);
         set_timestamp(m, &format!("anker_usb_a_watts{{port=\"{}\"}}", port));
     }
 }
 
+pub fn update_from_estimate(estimate: &BatteryEstimate) {
+    let m = metrics();
+
+    m.battery_net_watts.set(estimate.net_watts);
+    set_timestamp(m, "anker_battery_net_watts");
+
+    set_time_estimate(
+        m,
+        &m.battery_time_to_empty_seconds,
+        "anker_battery_time_to_empty_seconds",
+        estimate.time_to_empty.as_ref(),
+    );
+    set_time_estimate(
+        m,
+        &m.battery_time_to_full_seconds,
+        "anker_battery_time_to_full_seconds",
+        estimate.time_to_full.as_ref(),
+    );
+}
+
+/// Drop the time estimates, e.g. once the device disconnects
+pub fn clear_estimate() {
+    let m = metrics();
+    m.battery_time_to_empty_seconds.reset();
+    m.battery_time_to_full_seconds.reset();
+}
+
+/// Set the estimate/min/max series, dropping any that don't currently apply
+fn set_time_estimate(m: &Metrics, gauge: &GaugeVec, name: &str, estimate: Option<&TimeEstimate>) {
+    let bounds = [
+        ("estimate", estimate.map(|e| e.seconds)),
+        ("min", estimate.map(|e| e.min_seconds)),
+        ("max", estimate.and_then(|e| e.max_seconds)),
+    ];
+
+    for (bound, seconds) in bounds {
+        match seconds {
+            Some(seconds) => {
+                gauge.with_label_values(&[bound]).set(seconds as f64);
+                set_timestamp(m, &format!("{}{{bound=\"{}\"}}", name, bound));
+            }
+            None => {
+                let _ = gauge.remove_label_values(&[bound]);
+            }
+        }
+    }
+}
+
+pub fn update_from_health(health: &BatteryHealth) {
+    let m = metrics();
+
+    for (battery, pack) in [
+        ("internal", &health.internal_battery),
+        ("external", &health.external_battery),
+    ] {
+        update_pack_health(m, battery, pack);
+    }
+}
+
+fn update_pack_health(m: &Metrics, battery: &str, pack: &PackHealth) {
+    m.battery_equivalent_full_cycles
+        .with_label_values(&[battery])
+        .set(pack.equivalent_full_cycles);
+
+    m.battery_high_soc_seconds
+        .with_label_values(&[battery])
+        .set(pack.high_soc_seconds);
+
+    for exposure in &pack.temperature_exposure {
+        let threshold = exposure.threshold_celsius.to_string();
+        m.battery_temperature_exposure_seconds
+            .with_label_values(&[battery, &threshold])
+            .set(exposure.seconds);
+    }
+
+    for (i, sessions) in pack.depth_of_discharge_histogram.iter().enumerate() {
+        let low = i as u8 * DOD_BUCKET_PERCENT;
+        let depth = format!("{}-{}", low, low + DOD_BUCKET_PERCENT);
+        m.battery_discharge_sessions
+            .with_label_values(&[battery, &depth])
+            .set(*sessions as f64);
+    }
+}
+
+#[cfg(feature = "ble")]
 pub fn update_connection_state(state: ConnectionState) {
     let m = metrics();
     m.connected.set(if state == ConnectionState::Connected { 1 } else { 0 });
@@ -342,6 +573,21 @@ pub fn increment_command(command_type: &str) {
     m.commands_total.with_label_values(&[command_type]).inc();
 }
 
+pub fn increment_corrupt_frame(reason: &str) {
+    let m = metrics();
+    m.corrupt_frames_total.with_label_values(&[reason]).inc();
+}
+
+pub fn increment_parse_error(error: &str) {
+    let m = metrics();
+    m.parse_errors_total.with_label_values(&[error]).inc();
+}
+
+pub fn increment_unknown_value(field: &str) {
+    let m = metrics();
+    m.unknown_values_total.with_label_values(&[field]).inc();
+}
+
 pub fn render() -> String {
     let m = metrics();
     let mut buffer = Vec::new();
@@ -0,0 +1,134 @@
+//! Exit codes of the `anker767` CLI against a stub server: 0 on success,
+//! 1 for an API error, 2 for a usage error and 3 when the server can't be
+//! reached.
+
+#![cfg(feature = "cli")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Command, Output};
+use std::thread::{self, JoinHandle};
+
+/// Serve one request with `status` and a JSON `body`, returning the request
+/// line and body the client sent
+fn stub_server(status: &'static str, body: &'static str) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let request = read_request(&stream);
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        (&stream).write_all(response.as_bytes()).unwrap();
+        request
+    });
+    (url, handle)
+}
+
+fn read_request(stream: &TcpStream) -> String {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+    format!(
+        "{}{}",
+        request_line.trim_end(),
+        String::from_utf8(body).unwrap()
+    )
+}
+
+fn anker767(url: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_anker767"))
+        .arg("--url")
+        .arg(url)
+        .args(args)
+        .env_remove("ANKER_URL")
+        .env_remove("ANKER_MODEL")
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn command_succeeds() {
+    let (url, server) = stub_server("200 OK", r#"{"success":true}"#);
+    let output = anker767(&url, &["ac", "on"]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+    assert_eq!(
+        server.join().unwrap(),
+        r#"POST /api/ac-output HTTP/1.1{"is_on":true}"#
+    );
+}
+
+#[test]
+fn api_error_exits_1_with_the_server_message() {
+    let (url, server) = stub_server(
+        "503 Service Unavailable",
+        r#"{"error":"Device not connected"}"#,
+    );
+    let output = anker767(&url, &["ac", "off"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("Device not connected"),
+        "{}",
+        stderr(&output)
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn usage_error_exits_2() {
+    // Rejected before any request is made, so nothing needs to listen
+    let output = anker767("http://127.0.0.1:9", &["timer", "ac", "soon"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr(&output).contains("invalid duration"),
+        "{}",
+        stderr(&output)
+    );
+
+    let output = anker767("http://127.0.0.1:9", &["brightness", "9"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn unreachable_server_exits_3() {
+    // Take a free port, then close it so the connection is refused
+    let url = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let output = anker767(&url, &["status"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        stderr(&output).contains("can't reach"),
+        "{}",
+        stderr(&output)
+    );
+}
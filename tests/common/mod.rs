@@ -0,0 +1,25 @@
+//! Helpers shared by the frame corpus tests
+
+use anker_767_ble_webserver::ble::telemetry::{Header, NotificationPacket};
+use serde_json::{json, Value};
+
+/// Decoded packet as the API would serialize it, or the parse error. Frames
+/// failing the header checks are still decoded, as the device does, with the
+/// check's error alongside.
+pub fn decode(frame: &[u8]) -> Value {
+    let decoded = match NotificationPacket::from_bytes(frame) {
+        Ok(packet) => serde_json::to_value(packet).unwrap(),
+        Err(e) => json!({ "error": e.to_string() }),
+    };
+    match Header::verify(frame) {
+        Ok(()) => decoded,
+        Err(e) => json!({ "unverified": e.to_string(), "decoded": decoded }),
+    }
+}
+
+/// Write `actual` to `path` as pretty JSON
+pub fn write_expected(path: &std::path::Path, actual: &Value) {
+    let mut pretty = serde_json::to_string_pretty(actual).unwrap();
+    pretty.push('\n');
+    std::fs::write(path, pretty).unwrap();
+}
@@ -0,0 +1,88 @@
+//! Golden captures from a real 767: every `tests/golden/<name>.jsonl` (recorder
+//! output, or `anker767-protocol btsnoop --capture`) is reassembled and decoded
+//! the way the device does, and the result compared with `<name>.json`.
+//!
+//! Frames built by our own encoders live in `tests/synthetic`; they can only
+//! show that the encoder and decoder agree, not that the mapping is right.
+//!
+//! Run with `UPDATE_GOLDEN=1` to (re)write the expected JSON after adding
+//! captures or intentionally changing the output, then review the diff.
+
+mod common;
+
+use anker_767_ble_webserver::ble::capture::{read_capture, Direction};
+use anker_767_ble_webserver::ble::framing::FrameDecoder;
+use common::{decode, write_expected};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// Every frame reassembled from the capture's notifications, in order, with
+/// `{"dropped": ...}` wherever the frame decoder skipped bytes
+fn decode_capture(path: &Path) -> Value {
+    let records = read_capture(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    let mut decoder = FrameDecoder::new();
+    let mut frames = Vec::new();
+    for record in records.iter().filter(|r| r.direction == Direction::Rx) {
+        decoder.push(&record.data);
+        while let Some(frame) = decoder.next_frame() {
+            frames.push(match frame {
+                Ok(frame) => decode(&frame),
+                Err(e) => json!({ "dropped": e.to_string() }),
+            });
+        }
+    }
+    Value::Array(frames)
+}
+
+#[test]
+#[ignore = "no real captures in tests/golden yet, see its README"]
+fn golden_captures_decode_as_expected() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut captures: Vec<PathBuf> = fs::read_dir(corpus_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "jsonl"))
+        .collect();
+    captures.sort();
+    assert!(!captures.is_empty(), "golden corpus is empty");
+
+    let mut failures = Vec::new();
+    for path in &captures {
+        let actual = decode_capture(path);
+        let expected_path = path.with_extension("json");
+
+        if update {
+            write_expected(&expected_path, &actual);
+            continue;
+        }
+
+        let expected: Value = match fs::read_to_string(&expected_path) {
+            Ok(text) => serde_json::from_str(&text).unwrap(),
+            Err(_) => {
+                failures.push(format!("{}: missing expected JSON", path.display()));
+                continue;
+            }
+        };
+        if actual != expected {
+            failures.push(format!(
+                "{}:\n  expected {}\n  actual   {}",
+                path.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} golden captures differ (UPDATE_GOLDEN=1 to accept):\n{}",
+        failures.len(),
+        captures.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,74 @@
+//! Compatibility tests for the JSON the API serves. The fixtures in
+//! `tests/schema/` are frozen: they must keep deserializing into the crate
+//! types and serialize back unchanged. A change that breaks them needs a new
+//! `TELEMETRY_SCHEMA_VERSION` and a new fixture, not an edited one.
+
+use anker_767_ble_webserver::ble::telemetry::{
+    BatteryState, VersionedTelemetry, TELEMETRY_SCHEMA_VERSION,
+};
+use anker_767_ble_webserver::ble::units::{Percentage, Seconds, Watts};
+use anker_767_ble_webserver::ble::StateAck;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn fixture(name: &str) -> Value {
+    let text = fs::read_to_string(tests_dir().join("schema").join(name)).unwrap();
+    serde_json::from_str(&text).unwrap()
+}
+
+/// Deserialize `json` and check it serializes back to the same document
+fn round_trip<T: Serialize + DeserializeOwned>(json: Value) -> T {
+    let parsed: T = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+    parsed
+}
+
+#[test]
+fn telemetry_v1_is_stable() {
+    let versioned: VersionedTelemetry = round_trip(fixture("telemetry_v1.json"));
+    assert_eq!(versioned.schema_version, TELEMETRY_SCHEMA_VERSION);
+
+    let telemetry = versioned.telemetry;
+    assert_eq!(telemetry.total_output_watts, Watts(120));
+    assert_eq!(telemetry.total_battery_percentage, Percentage(70));
+    assert_eq!(telemetry.battery_remaining, Seconds(98280));
+    assert_eq!(telemetry.battery_state, BatteryState::Discharging);
+    assert!(telemetry.external_battery.is_some());
+}
+
+#[test]
+fn telemetry_ignores_unknown_fields() {
+    let mut json = fixture("telemetry_v1.json");
+    json["added_in_a_later_release"] = Value::from(42);
+    let versioned: VersionedTelemetry = serde_json::from_value(json).unwrap();
+    assert_eq!(versioned.schema_version, TELEMETRY_SCHEMA_VERSION);
+}
+
+#[test]
+fn telemetry_rejects_other_schema_versions() {
+    let mut json = fixture("telemetry_v1.json");
+    json["schema_version"] = Value::from(TELEMETRY_SCHEMA_VERSION + 1);
+    let error = serde_json::from_value::<VersionedTelemetry>(json).unwrap_err();
+    assert!(error.to_string().contains("schema version"), "{}", error);
+}
+
+#[test]
+fn state_ack_v1_is_stable() {
+    round_trip::<StateAck>(fixture("state_ack_v1.json"));
+}
+
+#[cfg(feature = "ble")]
+#[test]
+fn set_state_v1_is_stable() {
+    use anker_767_ble_webserver::ble::SetState;
+
+    let set_state: SetState = round_trip(fixture("set_state_v1.json"));
+    assert_eq!(set_state.recharge_power, Some(800));
+}
@@ -0,0 +1,245 @@
+//! Property tests for the protocol layer: command framing and round trips,
+//! notification round trips, and parser robustness on arbitrary input.
+
+use anker_767_ble_webserver::ble::command::{
+    checksum, AcOutputCommand, AcTimerCommand, AnkerCommand, LedCommand, PowerSaveCommand,
+    RawCommand, RechargePowerCommand, ScreenBrightnessCommand, ScreenTimeoutCommand,
+    TwelveVoltOutputCommand, TwelveVoltTimerCommand, HEADER,
+};
+use anker_767_ble_webserver::ble::framing::FrameDecoder;
+use anker_767_ble_webserver::ble::telemetry::{
+    Battery, BatteryState, CommandAck, LedState, NotificationPacket, Output, StateAck,
+    NOTIFICATION_MAGIC,
+};
+use anker_767_ble_webserver::ble::units::{Celsius, Percentage, Seconds, Watts};
+use anker_767_ble_webserver::ble::{CommandType, Telemetry};
+use proptest::prelude::*;
+
+fn any_command() -> impl Strategy<Value = AnkerCommand> {
+    prop_oneof![
+        any::<bool>().prop_map(|v| AnkerCommand::PowerSave(PowerSaveCommand::new(v))),
+        any::<bool>().prop_map(|v| AnkerCommand::AcOutput(AcOutputCommand::new(v))),
+        any::<bool>().prop_map(|v| AnkerCommand::TwelveVoltOutput(TwelveVoltOutputCommand::new(v))),
+        (0u8..=3).prop_map(|v| {
+            AnkerCommand::ScreenBrightness(ScreenBrightnessCommand::new(v).unwrap())
+        }),
+        (0u8..=4).prop_map(|v| AnkerCommand::Led(LedCommand::new(v).unwrap())),
+        (200u16..=1440)
+            .prop_map(|v| AnkerCommand::RechargePower(RechargePowerCommand::new(v).unwrap())),
+        any::<u16>().prop_map(|v| AnkerCommand::ScreenTimeout(ScreenTimeoutCommand::new(v))),
+        any::<u16>().prop_map(|v| AnkerCommand::AcTimer(AcTimerCommand::new(v))),
+        any::<u16>().prop_map(|v| AnkerCommand::TwelveVoltTimer(TwelveVoltTimerCommand::new(v))),
+    ]
+}
+
+fn any_command_type() -> impl Strategy<Value = CommandType> {
+    prop_oneof![
+        Just(CommandType::AcTimer),
+        Just(CommandType::TwelveVoltTimer),
+        Just(CommandType::RechargePower),
+        Just(CommandType::ScreenTimeout),
+        Just(CommandType::AcOutput),
+        Just(CommandType::TwelveVoltOutput),
+        Just(CommandType::ScreenBrightness),
+        Just(CommandType::PowerSave),
+        Just(CommandType::Led),
+    ]
+}
+
+fn any_output(timer: Option<u16>) -> impl Strategy<Value = Output> {
+    (any::<bool>(), any::<u16>()).prop_map(move |(is_on, watts)| Output {
+        is_on,
+        watts: Watts(watts),
+        time_remaining: timer.map(Seconds::from),
+    })
+}
+
+fn any_battery() -> impl Strategy<Value = Battery> {
+    (any::<u8>(), any::<u8>()).prop_map(|(temperature, percentage)| Battery {
+        temperature: Celsius(temperature),
+        percentage: Percentage(percentage),
+    })
+}
+
+/// Telemetry as the parser would produce it: remaining hours on the 0.1 h grid,
+/// a 16-character serial and an expansion pack only when it reads non-zero
+fn any_telemetry() -> impl Strategy<Value = Telemetry> {
+    let ports = (
+        any_output(None),
+        any::<u16>().prop_flat_map(|t| prop::collection::vec(any_output(Some(t)), 2)),
+        prop::collection::vec(any_output(None), 3),
+        prop::collection::vec(any_output(None), 2),
+    );
+    let watts = || any::<u16>().prop_map(Watts);
+    let power = (watts(), watts(), watts(), watts());
+    let battery = (
+        any_battery(),
+        prop::option::of(any_battery().prop_filter("reads as absent", |b| {
+            b.temperature != Celsius(0) || b.percentage != Percentage(0)
+        })),
+        prop_oneof![
+            Just(BatteryState::Idle),
+            Just(BatteryState::Discharging),
+            Just(BatteryState::Charging),
+        ],
+        any::<u8>().prop_map(Percentage),
+    );
+    // Whole days plus tenths of an hour within the day, as the device encodes it
+    let remaining = (any::<u8>(), 0u8..240);
+    let serial = "[A-Z0-9]{16}";
+
+    (ports, power, battery, remaining, serial).prop_map(
+        |(
+            (ac_outlet, twelve_volt, usb_c, usb_a),
+            (total_output_watts, ac_input_watts, solar_input_watts, total_input_watts),
+            (internal_battery, external_battery, battery_state, total_battery_percentage),
+            (days, tenths),
+            device_serial,
+        )| Telemetry {
+            battery_remaining: Seconds::from_days_and_tenths(days, tenths),
+            ac_outlet,
+            twelve_volt,
+            usb_c,
+            usb_a,
+            total_output_watts,
+            ac_input_watts,
+            solar_input_watts,
+            total_input_watts,
+            internal_battery,
+            external_battery,
+            battery_state,
+            total_battery_percentage,
+            device_serial,
+        },
+    )
+}
+
+fn any_state_ack() -> impl Strategy<Value = StateAck> {
+    (
+        any::<bool>(),
+        any::<bool>(),
+        any::<bool>(),
+        prop_oneof![
+            Just(LedState::Off),
+            Just(LedState::Low),
+            Just(LedState::Mid),
+            Just(LedState::High),
+            Just(LedState::Sos),
+        ],
+    )
+        .prop_map(
+            |(ac_outlet_on, twelve_volt_on, power_save_on, led_state)| StateAck {
+                ac_outlet_on,
+                twelve_volt_on,
+                power_save_on,
+                led_state,
+            },
+        )
+}
+
+fn any_packet() -> impl Strategy<Value = NotificationPacket> {
+    prop_oneof![
+        any_telemetry().prop_map(NotificationPacket::Telemetry),
+        any_state_ack().prop_map(NotificationPacket::StateAck),
+        any_command_type()
+            .prop_map(|command_type| NotificationPacket::CommandAck(CommandAck { command_type })),
+    ]
+}
+
+/// Arbitrary payload wrapped in a header with a correct length and checksum,
+/// so the input gets past frame validation into the field parsers
+fn framed_garbage() -> impl Strategy<Value = Vec<u8>> {
+    (
+        any::<u8>(),
+        any::<u8>(),
+        prop::collection::vec(any::<u8>(), 1..200),
+    )
+        .prop_map(|(packet_type, id, payload)| {
+            let length = 9 + payload.len() + 1;
+            let mut data = NOTIFICATION_MAGIC.to_vec();
+            data.extend_from_slice(&[0x00, 0x00, 0x01, packet_type % 3, id]);
+            data.extend_from_slice(&(length as u16).to_le_bytes());
+            data.extend_from_slice(&payload);
+            data.push(checksum(&data));
+            data
+        })
+}
+
+proptest! {
+    #[test]
+    fn command_round_trips(command in any_command()) {
+        prop_assert_eq!(AnkerCommand::from_bytes(&command.to_bytes()).unwrap(), command);
+    }
+
+    #[test]
+    fn command_framing_invariants(command in any_command()) {
+        let bytes = command.to_bytes();
+        prop_assert_eq!(&bytes[..HEADER.len()], &HEADER[..]);
+        prop_assert_eq!(bytes[HEADER.len()], command.command_type() as u8);
+        prop_assert_eq!(bytes[HEADER.len() + 1] as usize, bytes.len());
+        let (body, trailer) = bytes.split_at(bytes.len() - 1);
+        prop_assert_eq!(trailer[0], checksum(body));
+    }
+
+    #[test]
+    fn raw_command_round_trips(command_type in any::<u8>(), parameters in prop::collection::vec(any::<u8>(), 0..=246)) {
+        let command = RawCommand::new(command_type, parameters).unwrap();
+        let bytes = command.to_bytes();
+        prop_assert_eq!(bytes.len(), command.length() as usize);
+        prop_assert_eq!(RawCommand::from_bytes(&bytes).unwrap(), command);
+    }
+
+    #[test]
+    fn command_decoder_never_panics(data in prop::collection::vec(any::<u8>(), 0..64)) {
+        let _ = AnkerCommand::from_bytes(&data);
+    }
+
+    #[test]
+    fn packet_round_trips(packet in any_packet()) {
+        prop_assert_eq!(NotificationPacket::from_bytes(&packet.to_bytes()).unwrap(), packet);
+    }
+
+    #[test]
+    fn parser_never_panics_on_arbitrary_input(data in prop::collection::vec(any::<u8>(), 0..300)) {
+        let _ = NotificationPacket::from_bytes(&data);
+    }
+
+    #[test]
+    fn parser_never_panics_on_well_framed_garbage(data in framed_garbage()) {
+        let _ = NotificationPacket::from_bytes(&data);
+    }
+
+    #[test]
+    fn decoder_reassembles_any_split(
+        packets in prop::collection::vec(any_packet(), 1..5),
+        chunk_sizes in prop::collection::vec(1usize..120, 1..40),
+    ) {
+        let frames: Vec<Vec<u8>> = packets.iter().map(NotificationPacket::to_bytes).collect();
+        let stream = frames.concat();
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::new();
+        let mut offset = 0;
+        for size in chunk_sizes.iter().cycle() {
+            if offset >= stream.len() {
+                break;
+            }
+            let end = (offset + size).min(stream.len());
+            decoder.push(&stream[offset..end]);
+            offset = end;
+            while let Some(result) = decoder.next_frame() {
+                decoded.push(result.unwrap());
+            }
+        }
+        prop_assert_eq!(decoded, frames);
+    }
+
+    #[test]
+    fn decoder_never_panics(chunks in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..64), 0..16)) {
+        let mut decoder = FrameDecoder::new();
+        for chunk in &chunks {
+            decoder.push(chunk);
+            while decoder.next_frame().is_some() {}
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Synthetic frames: every `tests/synthetic/<name>.hex` was built with this
+//! crate's own encoders. Its decoded JSON is checked in as `<name>.json`, so a
+//! changed offset in a layout shows up as a diff even though the frames still
+//! round-trip. Frames captured from a real 767 live in `tests/golden` instead.
+//!
+//! Run with `UPDATE_SYNTHETIC=1` to (re)write the expected JSON after adding
+//! frames or intentionally changing the output, then review the diff.
+
+mod common;
+
+use anker_767_ble_webserver::ble::capture::from_hex;
+use anker_767_ble_webserver::ble::telemetry::{Header, NotificationPacket};
+use common::{decode, write_expected};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Every synthetic frame as (path, bytes), sorted by name
+fn frames() -> Vec<(PathBuf, Vec<u8>)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/synthetic");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "hex"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "synthetic corpus is empty");
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let hex = fs::read_to_string(&path).unwrap();
+            let frame = from_hex(hex.trim())
+                .unwrap_or_else(|| panic!("{} is not valid hex", path.display()));
+            (path, frame)
+        })
+        .collect()
+}
+
+fn is_corrupt(path: &Path) -> bool {
+    path.file_stem()
+        .is_some_and(|name| name.to_string_lossy().starts_with("corrupt_"))
+}
+
+#[test]
+fn synthetic_frames_decode_as_expected() {
+    let update = std::env::var_os("UPDATE_SYNTHETIC").is_some();
+    let frames = frames();
+
+    let mut failures = Vec::new();
+    for (path, frame) in &frames {
+        let actual = decode(frame);
+        let expected_path = path.with_extension("json");
+
+        if update {
+            write_expected(&expected_path, &actual);
+            continue;
+        }
+
+        let expected: Value = match fs::read_to_string(&expected_path) {
+            Ok(text) => serde_json::from_str(&text).unwrap(),
+            Err(_) => {
+                failures.push(format!("{}: missing expected JSON", path.display()));
+                continue;
+            }
+        };
+        if actual != expected {
+            failures.push(format!(
+                "{}:\n  expected {}\n  actual   {}",
+                path.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} synthetic frames differ (UPDATE_SYNTHETIC=1 to accept):\n{}",
+        failures.len(),
+        frames.len(),
+        failures.join("\n")
+    );
+}
+
+/// Decoding then encoding gives back the exact bytes, and the expected JSON
+/// deserializes back into the decoded packet
+#[test]
+fn synthetic_frames_round_trip() {
+    for (path, frame) in frames() {
+        if is_corrupt(&path) {
+            continue;
+        }
+        let packet = NotificationPacket::from_bytes(&frame)
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(packet.to_bytes(), frame, "{}", path.display());
+
+        let text = fs::read_to_string(path.with_extension("json")).unwrap();
+        let parsed: NotificationPacket =
+            serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(parsed, packet, "{}", path.display());
+    }
+}
+
+#[test]
+fn corrupt_frames_fail_header_checks() {
+    for (path, frame) in frames() {
+        assert_eq!(
+            Header::verify(&frame).is_err(),
+            is_corrupt(&path),
+            "{}",
+            path.display()
+        );
+    }
+}